@@ -0,0 +1,10 @@
+// per-frame uniforms written by RenderContext, bound as set 0
+layout (set = 0, binding = 0) uniform Frame {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    // the logical render extent in pixels
+    vec2 resolution;
+    float time;
+    float delta_time;
+} frame;
@@ -0,0 +1,11 @@
+#version 450
+
+layout (location = 0) in vec4 v_color;
+layout (location = 1) in vec2 v_uv;
+layout (location = 2) in vec4 v_params;
+
+layout (location = 0) out vec4 color;
+
+void main() {
+    color = v_color;
+}
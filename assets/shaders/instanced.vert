@@ -0,0 +1,25 @@
+#version 450
+#include "frame.glsl"
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec2 uv;
+
+// per instance
+layout (location = 2) in vec4 transform_0;
+layout (location = 3) in vec4 transform_1;
+layout (location = 4) in vec4 transform_2;
+layout (location = 5) in vec4 transform_3;
+layout (location = 6) in vec4 color;
+layout (location = 7) in vec4 params;
+
+layout (location = 0) out vec4 v_color;
+layout (location = 1) out vec2 v_uv;
+layout (location = 2) out vec4 v_params;
+
+void main() {
+    mat4 model = mat4(transform_0, transform_1, transform_2, transform_3);
+    gl_Position = frame.view_projection * model * vec4(position, 1.0);
+    v_color = color;
+    v_uv = uv;
+    v_params = params;
+}
@@ -0,0 +1,27 @@
+#version 450
+#include "common.glsl"
+
+// params: threshold, intensity, radius in pixels
+const int SAMPLES = 32;
+const float GOLDEN_ANGLE = 2.39996323;
+
+void main() {
+    float threshold = pc.params.x;
+    float intensity = pc.params.y;
+    float radius = pc.params.z;
+    vec4 base = texture(tex, v_uv);
+
+    // a spiral of samples covering a disc, weighted towards the centre
+    vec3 bloom = vec3(0.0);
+    float total = 0.0;
+    for (int i = 0; i < SAMPLES; i++) {
+        float r = sqrt((float(i) + 0.5) / float(SAMPLES));
+        float angle = float(i) * GOLDEN_ANGLE;
+        vec2 offset = vec2(cos(angle), sin(angle)) * r * radius * pc.texel_size;
+        vec3 c = texture(tex, v_uv + offset).rgb;
+        float weight = 1.0 - r * r;
+        bloom += max(c - threshold, 0.0) * weight;
+        total += weight;
+    }
+    color = vec4(base.rgb + bloom / total * intensity, base.a);
+}
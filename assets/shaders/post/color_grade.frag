@@ -0,0 +1,28 @@
+#version 450
+#include "common.glsl"
+
+// params: strength
+layout (set = 0, binding = 1) uniform sampler2D lut;
+
+vec3 linear_to_srgb(vec3 c) {
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
+// the lut is a strip of `size` slices of size x size texels, one per blue value, indexed with
+// sRGB encoded colours. the lut texture is sRGB, so the looked up colour is linear again
+vec3 lookup(vec3 c) {
+    float size = float(textureSize(lut, 0).y);
+    vec3 coord = clamp(linear_to_srgb(clamp(c, 0.0, 1.0)), 0.0, 1.0) * (size - 1.0);
+    float slice = floor(coord.b);
+    float blend = coord.b - slice;
+    vec2 uv = (coord.rg + 0.5) / vec2(size * size, size);
+    vec2 slice_offset = vec2(1.0 / size, 0.0);
+    vec3 low = textureLod(lut, uv + slice_offset * slice, 0.0).rgb;
+    vec3 high = textureLod(lut, uv + slice_offset * min(slice + 1.0, size - 1.0), 0.0).rgb;
+    return mix(low, high, blend);
+}
+
+void main() {
+    vec4 c = texture(tex, v_uv);
+    color = vec4(mix(c.rgb, lookup(c.rgb), pc.params.x), c.a);
+}
@@ -0,0 +1,16 @@
+// inputs shared by every post effect, the push constant block must match `PushConstants` in
+// src/graphics/post/mod.rs
+layout (location = 0) in vec2 v_uv;
+
+layout (location = 0) out vec4 color;
+
+layout (set = 0, binding = 0) uniform sampler2D tex;
+
+layout (push_constant) uniform PushConstants {
+    vec4 params;
+    vec2 texel_size;
+} pc;
+
+float luminance(vec3 c) {
+    return dot(c, vec3(0.2126, 0.7152, 0.0722));
+}
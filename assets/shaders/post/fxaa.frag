@@ -0,0 +1,53 @@
+#version 450
+#include "common.glsl"
+
+// params: edge threshold, maximum blend span in pixels
+// a reduced FXAA: the edge direction is estimated from the luma of the 3x3 neighbourhood and the
+// pixel is blended along it
+
+// luma in a roughly perceptual space, the input is linear
+float luma(vec3 c) {
+    return sqrt(luminance(max(c, 0.0)));
+}
+
+void main() {
+    float edge_threshold = pc.params.x;
+    float span_max = pc.params.y;
+    vec2 t = pc.texel_size;
+
+    vec4 center = texture(tex, v_uv);
+    float l_c = luma(center.rgb);
+    float l_n = luma(texture(tex, v_uv + vec2(0.0, -t.y)).rgb);
+    float l_s = luma(texture(tex, v_uv + vec2(0.0, t.y)).rgb);
+    float l_w = luma(texture(tex, v_uv + vec2(-t.x, 0.0)).rgb);
+    float l_e = luma(texture(tex, v_uv + vec2(t.x, 0.0)).rgb);
+    float l_nw = luma(texture(tex, v_uv + vec2(-t.x, -t.y)).rgb);
+    float l_ne = luma(texture(tex, v_uv + vec2(t.x, -t.y)).rgb);
+    float l_sw = luma(texture(tex, v_uv + vec2(-t.x, t.y)).rgb);
+    float l_se = luma(texture(tex, v_uv + vec2(t.x, t.y)).rgb);
+
+    float l_min = min(l_c, min(min(l_n, l_s), min(l_w, l_e)));
+    float l_max = max(l_c, max(max(l_n, l_s), max(l_w, l_e)));
+    float contrast = l_max - l_min;
+    if (contrast < max(0.0312, l_max * edge_threshold)) {
+        color = center;
+        return;
+    }
+
+    // the gradient across the edge, the blend direction runs along it
+    vec2 dir = vec2(
+        -((l_nw + l_ne) - (l_sw + l_se)),
+        (l_nw + l_sw) - (l_ne + l_se)
+    );
+    float dir_reduce = max((l_nw + l_ne + l_sw + l_se) * 0.25 * 0.125, 1.0 / 128.0);
+    float scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * scale, -span_max, span_max) * t;
+
+    vec3 a = 0.5 * (texture(tex, v_uv + dir * (1.0 / 3.0 - 0.5)).rgb
+        + texture(tex, v_uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 b = a * 0.5 + 0.25 * (texture(tex, v_uv - dir * 0.5).rgb
+        + texture(tex, v_uv + dir * 0.5).rgb);
+    float l_b = luma(b);
+    vec3 result = (l_b < l_min || l_b > l_max) ? a : b;
+    color = vec4(result, center.a);
+}
@@ -0,0 +1,42 @@
+#version 450
+#include "common.glsl"
+
+// params: transform (0 none, 1 sRGB encode, 2 HDR10, 3 scRGB), paper white in nits
+
+// Rec.709 to Rec.2020 primaries, column major
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274040, 0.0690970, 0.0163916,
+    0.3292820, 0.9195400, 0.0880132,
+    0.0433136, 0.0113612, 0.8955950
+);
+
+vec3 srgb_encode(vec3 c) {
+    c = clamp(c, 0.0, 1.0);
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
+// the SMPTE ST 2084 inverse EOTF, from absolute luminance in nits
+vec3 pq_encode(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+void main() {
+    vec3 c = texture(tex, v_uv).rgb;
+    int transform = int(pc.params.x + 0.5);
+    float paper_white = pc.params.y;
+    if (transform == 1) {
+        c = srgb_encode(c);
+    } else if (transform == 2) {
+        c = pq_encode(REC709_TO_REC2020 * c * paper_white);
+    } else if (transform == 3) {
+        // scRGB 1.0 is 80 nits
+        c = c * (paper_white / 80.0);
+    }
+    color = vec4(c, 1.0);
+}
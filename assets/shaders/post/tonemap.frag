@@ -0,0 +1,19 @@
+#version 450
+#include "common.glsl"
+
+// params: exposure, operator (0 Reinhard, 1 ACES)
+
+vec3 reinhard(vec3 c) {
+    return c / (1.0 + luminance(c));
+}
+
+vec3 aces(vec3 c) {
+    return clamp((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec4 c = texture(tex, v_uv);
+    vec3 exposed = c.rgb * pc.params.x;
+    vec3 mapped = pc.params.y < 0.5 ? reinhard(exposed) : aces(exposed);
+    color = vec4(mapped, c.a);
+}
@@ -0,0 +1,11 @@
+#version 450
+#include "common.glsl"
+
+// params: intensity, radius, softness
+void main() {
+    vec4 c = texture(tex, v_uv);
+    // 0 at the centre and 1 in the corners
+    float distance = length(v_uv - 0.5) * sqrt(2.0);
+    float falloff = smoothstep(pc.params.y, pc.params.y + pc.params.z, distance);
+    color = vec4(c.rgb * (1.0 - falloff * pc.params.x), c.a);
+}
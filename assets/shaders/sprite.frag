@@ -0,0 +1,11 @@
+#version 450
+layout (location = 0) in vec2 v_uv;
+layout (location = 1) in vec4 v_color;
+
+layout (location = 0) out vec4 color;
+
+layout (set = 0, binding = 0) uniform sampler2D tex;
+
+void main() {
+    color = texture(tex, v_uv) * v_color;
+}
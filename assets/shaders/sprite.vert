@@ -0,0 +1,25 @@
+#version 450
+layout (location = 0) in vec2 position;
+layout (location = 1) in vec2 size;
+layout (location = 2) in float rotation;
+layout (location = 3) in vec4 uv;
+layout (location = 4) in vec4 color;
+
+layout (location = 0) out vec2 v_uv;
+layout (location = 1) out vec4 v_color;
+
+layout (push_constant) uniform PushConstants {
+    vec2 screen_size;
+} pc;
+
+void main() {
+    // quad corners in triangle strip order: (0, 0), (1, 0), (0, 1), (1, 1)
+    vec2 corner = vec2(gl_VertexIndex & 1, gl_VertexIndex >> 1);
+    vec2 local = (corner - 0.5) * size;
+    float s = sin(rotation);
+    float c = cos(rotation);
+    vec2 pixel = position + vec2(c * local.x - s * local.y, s * local.x + c * local.y);
+    gl_Position = vec4(pixel / pc.screen_size * 2.0 - 1.0, 0.0, 1.0);
+    v_uv = mix(uv.xy, uv.zw, corner);
+    v_color = color;
+}
@@ -0,0 +1,12 @@
+#version 450
+
+layout (set = 1, binding = 0) uniform Draw {
+    mat4 model;
+    vec4 color;
+} draw;
+
+layout (location = 0) out vec4 color;
+
+void main() {
+    color = draw.color;
+}
@@ -0,0 +1,13 @@
+#version 450
+#include "frame.glsl"
+
+layout (set = 1, binding = 0) uniform Draw {
+    mat4 model;
+    vec4 color;
+} draw;
+
+layout (location = 0) in vec2 position;
+
+void main() {
+    gl_Position = frame.view_projection * draw.model * vec4(position, 0.0, 1.0);
+}
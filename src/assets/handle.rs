@@ -0,0 +1,273 @@
+use std::{
+    any::Any,
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, RwLock, Weak,
+    },
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    // the error of the loader, or of the first dependency that failed
+    Failed(String),
+}
+
+pub(crate) struct Slot<T> {
+    path: PathBuf,
+    state: Mutex<LoadState>,
+    finished: Condvar,
+    asset: RwLock<Option<Arc<T>>>,
+    // bumped every time a new version of the asset is swapped in
+    version: AtomicU64,
+    // weak, so that assets depending on each other are still freed. an asset keeps the
+    // dependencies it uses alive through the handles it holds itself
+    dependencies: Mutex<Vec<Weak<dyn ErasedSlot>>>,
+}
+
+impl<T> Slot<T> {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            state: Mutex::new(LoadState::Loading),
+            finished: Condvar::new(),
+            asset: RwLock::new(None),
+            version: AtomicU64::new(0),
+            dependencies: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+// the type-erased part of a slot, used by the manager and its worker
+pub(crate) trait ErasedSlot: Send + Sync {
+    fn path(&self) -> &Path;
+    fn state(&self) -> LoadState;
+    fn version(&self) -> u64;
+    fn dependencies(&self) -> Vec<UntypedHandle>;
+    // swaps in the result of a loader. when a loaded asset fails to reload it keeps its previous
+    // version and dependencies
+    fn finish(
+        &self,
+        result: anyhow::Result<Arc<dyn Any + Send + Sync>>,
+        dependencies: Vec<UntypedHandle>,
+    ) -> anyhow::Result<()>;
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+}
+
+impl<T: Send + Sync + 'static> ErasedSlot for Slot<T> {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn state(&self) -> LoadState {
+        self.state.lock().unwrap().clone()
+    }
+
+    fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    fn dependencies(&self) -> Vec<UntypedHandle> {
+        self.dependencies
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|slot| slot.upgrade())
+            .map(|slot| UntypedHandle { slot })
+            .collect()
+    }
+
+    fn finish(
+        &self,
+        result: anyhow::Result<Arc<dyn Any + Send + Sync>>,
+        dependencies: Vec<UntypedHandle>,
+    ) -> anyhow::Result<()> {
+        let result = result.and_then(|asset| {
+            asset.downcast::<T>().map_err(|_| {
+                anyhow::anyhow!(
+                    "The loader returned an asset of the wrong type for {}",
+                    self.path.display()
+                )
+            })
+        });
+        let mut state = self.state.lock().unwrap();
+        match result {
+            Ok(asset) => {
+                *self.asset.write().unwrap() = Some(asset);
+                *self.dependencies.lock().unwrap() = dependencies
+                    .iter()
+                    .map(|handle| Arc::downgrade(&handle.slot))
+                    .collect();
+                self.version.fetch_add(1, Ordering::AcqRel);
+                *state = LoadState::Loaded;
+                self.finished.notify_all();
+                Ok(())
+            }
+            Err(e) => {
+                if *state != LoadState::Loaded {
+                    *state = LoadState::Failed(format!("{:#}", e));
+                    self.finished.notify_all();
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+// a reference-counted handle to an asset that may still be loading. the asset is dropped along
+// with the last handle to it, loading the same path again afterwards reads it from scratch
+pub struct Handle<T> {
+    pub(crate) slot: Arc<Slot<T>>,
+}
+
+impl<T: Send + Sync + 'static> Handle<T> {
+    pub fn path(&self) -> &Path {
+        &self.slot.path
+    }
+
+    // the state of this asset alone, see `recursive_state` to include its dependencies
+    pub fn state(&self) -> LoadState {
+        self.slot.state()
+    }
+
+    pub fn recursive_state(&self) -> LoadState {
+        self.untyped().recursive_state()
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.state() == LoadState::Loaded
+    }
+
+    // the latest version of the asset. callers that keep the result around can compare
+    // `version` with the one they got it at to notice reloads
+    pub fn get(&self) -> Option<Arc<T>> {
+        self.slot.asset.read().unwrap().clone()
+    }
+
+    // 0 while loading, incremented by every successful load and reload
+    pub fn version(&self) -> u64 {
+        self.slot.version()
+    }
+
+    // blocks until the loader has finished, without waiting for dependencies
+    pub fn wait(&self) -> LoadState {
+        let mut state = self.slot.state.lock().unwrap();
+        while *state == LoadState::Loading {
+            state = self.slot.finished.wait(state).unwrap();
+        }
+        state.clone()
+    }
+
+    // the assets requested by the loader of this one that are still alive, empty until it has
+    // finished
+    pub fn dependencies(&self) -> Vec<UntypedHandle> {
+        self.slot.dependencies()
+    }
+
+    // the number of handles to this asset, including those held by assets that keep it
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.slot)
+    }
+
+    pub fn untyped(&self) -> UntypedHandle {
+        UntypedHandle {
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.slot, &other.slot)
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({})", self.slot.path.display())
+    }
+}
+
+// a handle to an asset of any type, it keeps the asset alive like a typed one
+#[derive(Clone)]
+pub struct UntypedHandle {
+    pub(crate) slot: Arc<dyn ErasedSlot>,
+}
+
+impl UntypedHandle {
+    pub fn path(&self) -> &Path {
+        self.slot.path()
+    }
+
+    pub fn state(&self) -> LoadState {
+        self.slot.state()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.slot.version()
+    }
+
+    pub fn dependencies(&self) -> Vec<UntypedHandle> {
+        self.slot.dependencies()
+    }
+
+    pub(crate) fn address(&self) -> usize {
+        Arc::as_ptr(&self.slot) as *const () as usize
+    }
+
+    // Loaded once this asset and everything it depends on has loaded, Failed as soon as any of
+    // them failed
+    pub fn recursive_state(&self) -> LoadState {
+        let mut visited = HashSet::new();
+        let mut stack = vec![self.clone()];
+        let mut state = LoadState::Loaded;
+        while let Some(handle) = stack.pop() {
+            if !visited.insert(handle.address()) {
+                continue;
+            }
+            match handle.state() {
+                LoadState::Failed(e) => return LoadState::Failed(e),
+                LoadState::Loading => state = LoadState::Loading,
+                LoadState::Loaded => stack.extend(handle.dependencies()),
+            }
+        }
+        state
+    }
+
+    pub fn typed<T: Send + Sync + 'static>(&self) -> Option<Handle<T>> {
+        let slot = self.slot.clone().into_any().downcast::<Slot<T>>().ok()?;
+        Some(Handle { slot })
+    }
+}
+
+impl PartialEq for UntypedHandle {
+    fn eq(&self, other: &Self) -> bool {
+        self.address() == other.address()
+    }
+}
+
+impl Eq for UntypedHandle {}
+
+impl fmt::Debug for UntypedHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UntypedHandle({})", self.path().display())
+    }
+}
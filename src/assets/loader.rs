@@ -0,0 +1,119 @@
+use std::{
+    any::Any,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use image::RgbaImage;
+
+use crate::graphics::texture::decode_image;
+
+use super::{
+    handle::{Handle, UntypedHandle},
+    manager::AssetManager,
+};
+
+// turns the bytes of a file into an asset. loaders run on the asset worker thread, one asset
+// at a time
+pub trait AssetLoader: Send + Sync + 'static {
+    type Asset: Send + Sync + 'static;
+
+    // the file extensions handled by this loader, an empty list accepts any file
+    fn extensions(&self) -> &[&str];
+
+    // loaders that create shared resources, like textures, may return an `Arc` they already hold
+    fn load(&self, ctx: &mut LoadContext) -> anyhow::Result<Arc<Self::Asset>>;
+}
+
+pub(crate) trait ErasedLoader: Send + Sync {
+    fn extensions(&self) -> &[&str];
+    fn load(&self, ctx: &mut LoadContext) -> anyhow::Result<Arc<dyn Any + Send + Sync>>;
+}
+
+impl<L: AssetLoader> ErasedLoader for L {
+    fn extensions(&self) -> &[&str] {
+        AssetLoader::extensions(self)
+    }
+
+    fn load(&self, ctx: &mut LoadContext) -> anyhow::Result<Arc<dyn Any + Send + Sync>> {
+        Ok(AssetLoader::load(self, ctx)?)
+    }
+}
+
+// what a loader knows about the asset it is loading
+pub struct LoadContext<'a> {
+    pub(crate) manager: &'a AssetManager,
+    pub(crate) path: &'a Path,
+    pub(crate) dependencies: Vec<UntypedHandle>,
+}
+
+impl<'a> LoadContext<'a> {
+    // the path of the asset, relative to the asset root
+    pub fn path(&self) -> &Path {
+        self.path
+    }
+
+    pub fn read(&self) -> anyhow::Result<Vec<u8>> {
+        self.manager.read(self.path)
+    }
+
+    // `path` is relative to the directory of the asset being loaded
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(path.as_ref())
+    }
+
+    // starts loading another asset this one depends on. it counts towards the recursive load
+    // state of this asset and is reloaded along with it for as long as it is alive, the asset
+    // keeps it alive by holding on to the handle
+    pub fn load<T: Send + Sync + 'static>(&mut self, path: impl AsRef<Path>) -> Handle<T> {
+        let handle = self.manager.load::<T>(self.resolve(path));
+        self.dependencies.push(handle.untyped());
+        handle
+    }
+}
+
+pub struct BytesLoader;
+
+impl AssetLoader for BytesLoader {
+    type Asset = Vec<u8>;
+
+    fn extensions(&self) -> &[&str] {
+        &[]
+    }
+
+    fn load(&self, ctx: &mut LoadContext) -> anyhow::Result<Arc<Vec<u8>>> {
+        Ok(Arc::new(ctx.read()?))
+    }
+}
+
+pub struct TextLoader;
+
+impl AssetLoader for TextLoader {
+    type Asset = String;
+
+    fn extensions(&self) -> &[&str] {
+        &[]
+    }
+
+    fn load(&self, ctx: &mut LoadContext) -> anyhow::Result<Arc<String>> {
+        Ok(Arc::new(String::from_utf8(ctx.read()?)?))
+    }
+}
+
+// decoded RGBA8 pixels on the CPU, see `TextureAssetLoader` for images on the GPU
+pub struct ImageLoader;
+
+impl AssetLoader for ImageLoader {
+    type Asset = RgbaImage;
+
+    fn extensions(&self) -> &[&str] {
+        &["png", "jpg", "jpeg"]
+    }
+
+    fn load(&self, ctx: &mut LoadContext) -> anyhow::Result<Arc<RgbaImage>> {
+        Ok(Arc::new(decode_image(&ctx.read()?)?))
+    }
+}
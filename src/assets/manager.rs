@@ -0,0 +1,583 @@
+use std::{
+    any::{type_name, TypeId},
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    path::{Component, Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, RwLock, Weak,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{utils::watch::FileWatcher, vfs::Vfs};
+
+use super::{
+    handle::{ErasedSlot, Handle, LoadState, Slot, UntypedHandle},
+    loader::{AssetLoader, BytesLoader, ErasedLoader, ImageLoader, LoadContext, TextLoader},
+};
+
+// editors usually write a file in several steps, so changes are collected for a short while
+// before reloading
+const RELOAD_DELAY: Duration = Duration::from_millis(100);
+
+type SlotKey = (TypeId, PathBuf);
+
+struct LoadRequest {
+    slot: Weak<dyn ErasedSlot>,
+    loader: Arc<dyn ErasedLoader>,
+    // for reloads, the assets whose reload caused this one, to stop at dependency cycles
+    reload_chain: Option<Vec<usize>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum AssetEvent {
+    // a new version of the asset was swapped in behind its handles, assets that depend on it are
+    // reloaded after it and reported separately
+    Reloaded(UntypedHandle),
+    // the asset keeps its previous version
+    ReloadFailed(UntypedHandle, String),
+}
+
+struct Shared {
+    vfs: Vfs,
+    // several loaders may produce the same type from different file formats
+    loaders: RwLock<HashMap<TypeId, Vec<Arc<dyn ErasedLoader>>>>,
+    slots: Mutex<HashMap<SlotKey, Weak<dyn ErasedSlot>>>,
+    requests: Mutex<Sender<LoadRequest>>,
+    subscribers: Mutex<Vec<Sender<AssetEvent>>>,
+    _watchers: Vec<FileWatcher>,
+}
+
+// loads assets from the files of a virtual file system on a background thread. every path is
+// loaded at most once per type while handles to it are alive, later requests share the same
+// handle. assets are reloaded when their files in a mounted directory change, see `subscribe`.
+// cloning the manager is cheap, clones share their assets
+#[derive(Clone)]
+pub struct AssetManager {
+    shared: Arc<Shared>,
+}
+
+impl AssetManager {
+    pub fn new(vfs: Vfs) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        // the threads only hold on to the manager while they work, they exit once the last
+        // clone of the manager, and with it the request sender and the watcher, is dropped
+        let shared = Arc::new_cyclic(|shared: &Weak<Shared>| {
+            let worker_shared = shared.clone();
+            thread::Builder::new()
+                .name("asset loader".into())
+                .spawn(move || worker(worker_shared, receiver))
+                .expect("failed to spawn the asset loader thread");
+            let watchers = vfs
+                .directories()
+                .into_iter()
+                .filter_map(|(point, directory)| {
+                    watch(shared.clone(), point, &directory)
+                        .map_err(|e| {
+                            log::warn!(
+                                "Asset hot reload disabled for {}: {}",
+                                directory.display(),
+                                e
+                            )
+                        })
+                        .ok()
+                })
+                .collect();
+            Shared {
+                vfs,
+                loaders: RwLock::new(HashMap::new()),
+                slots: Mutex::new(HashMap::new()),
+                requests: Mutex::new(sender),
+                subscribers: Mutex::new(Vec::new()),
+                _watchers: watchers,
+            }
+        });
+
+        let manager = Self { shared };
+        manager.register(BytesLoader);
+        manager.register(TextLoader);
+        manager.register(ImageLoader);
+        manager
+    }
+
+    pub fn vfs(&self) -> &Vfs {
+        &self.shared.vfs
+    }
+
+    // loaders registered later take precedence over earlier ones for the same type and extension
+    pub fn register<L: AssetLoader>(&self, loader: L) {
+        self.shared
+            .loaders
+            .write()
+            .unwrap()
+            .entry(TypeId::of::<L::Asset>())
+            .or_default()
+            .push(Arc::new(loader));
+    }
+
+    // returns immediately, the asset is loaded in the background unless a handle to it is
+    // already alive
+    pub fn load<T: Send + Sync + 'static>(&self, path: impl AsRef<Path>) -> Handle<T> {
+        let path = normalize(path.as_ref());
+        let key = (TypeId::of::<T>(), path.clone());
+        let mut slots = self.shared.slots.lock().unwrap();
+        if let Some(slot) = slots.get(&key).and_then(Weak::upgrade) {
+            let slot = slot.into_any().downcast::<Slot<T>>().unwrap();
+            return Handle { slot };
+        }
+        slots.retain(|_, slot| slot.strong_count() > 0);
+
+        let slot = Arc::new(Slot::<T>::new(path.clone()));
+        let erased: Arc<dyn ErasedSlot> = slot.clone();
+        slots.insert(key, Arc::downgrade(&erased));
+        drop(slots);
+
+        match self.loader(TypeId::of::<T>(), &path) {
+            Some(loader) => self.request(&erased, loader, None),
+            None => {
+                let e = anyhow::anyhow!(
+                    "No loader for {} produces {}",
+                    path.display(),
+                    type_name::<T>()
+                );
+                log::error!("Failed to load asset {}: {:?}", path.display(), e);
+                let _ = erased.finish(Err(e), Vec::new());
+            }
+        }
+        Handle { slot }
+    }
+
+    // reloads every asset loaded from `path` in the background, as if its file had changed
+    pub fn reload(&self, path: impl AsRef<Path>) {
+        let path = normalize(path.as_ref());
+        for (type_id, slot) in self.live_slots() {
+            if slot.path() == path {
+                if let Some(loader) = self.loader(type_id, &path) {
+                    self.request(&slot, loader, Some(Vec::new()));
+                }
+            }
+        }
+    }
+
+    // reports every reload from now on. the manager stops sending events to a subscriber once
+    // its receiver is dropped
+    pub fn subscribe(&self) -> Receiver<AssetEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    // blocks until the asset has loaded, for assets needed before anything can be shown.
+    // dependencies are still loaded in the background
+    pub fn load_blocking<T: Send + Sync + 'static>(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> anyhow::Result<Handle<T>> {
+        let handle = self.load::<T>(path);
+        match handle.wait() {
+            LoadState::Failed(e) => Err(anyhow::anyhow!(e)),
+            _ => Ok(handle),
+        }
+    }
+
+    // the number of assets with live handles that are still waiting for their loader
+    pub fn loading_count(&self) -> usize {
+        self.live_slots()
+            .into_iter()
+            .filter(|(_, slot)| slot.state() == LoadState::Loading)
+            .count()
+    }
+
+    pub fn read(&self, path: impl AsRef<Path>) -> anyhow::Result<Vec<u8>> {
+        self.shared.vfs.read(path)
+    }
+
+    fn live_slots(&self) -> Vec<(TypeId, Arc<dyn ErasedSlot>)> {
+        self.shared
+            .slots
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|((type_id, _), slot)| Some((*type_id, slot.upgrade()?)))
+            .collect()
+    }
+
+    fn request(
+        &self,
+        slot: &Arc<dyn ErasedSlot>,
+        loader: Arc<dyn ErasedLoader>,
+        reload_chain: Option<Vec<usize>>,
+    ) {
+        let request = LoadRequest {
+            slot: Arc::downgrade(slot),
+            loader,
+            reload_chain,
+        };
+        // the worker only stops on its own once the manager is gone, anything else is a bug that
+        // should not take the caller down with it
+        if self.shared.requests.lock().unwrap().send(request).is_err() {
+            let e = anyhow::anyhow!("The asset loader thread has stopped");
+            log::error!("Failed to load asset {}: {:?}", slot.path().display(), e);
+            let _ = slot.finish(Err(e), Vec::new());
+        }
+    }
+
+    fn notify(&self, event: AssetEvent) {
+        self.shared
+            .subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    // assets whose loaders requested `handle` may have derived data from it, so they are
+    // reloaded as well
+    fn reload_dependents(&self, handle: &UntypedHandle, mut chain: Vec<usize>) {
+        chain.push(handle.address());
+        for (type_id, slot) in self.live_slots() {
+            let dependent = UntypedHandle { slot };
+            if chain.contains(&dependent.address()) || !dependent.dependencies().contains(handle) {
+                continue;
+            }
+            if let Some(loader) = self.loader(type_id, dependent.path()) {
+                self.request(&dependent.slot, loader, Some(chain.clone()));
+            }
+        }
+    }
+
+    fn loader(&self, type_id: TypeId, path: &Path) -> Option<Arc<dyn ErasedLoader>> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        let loaders = self.shared.loaders.read().unwrap();
+        let loaders = loaders.get(&type_id)?;
+        let matches_extension = |loader: &&Arc<dyn ErasedLoader>| match &extension {
+            Some(ext) => loader.extensions().contains(&ext.as_str()),
+            None => false,
+        };
+        loaders
+            .iter()
+            .rev()
+            .find(matches_extension)
+            .or_else(|| {
+                loaders
+                    .iter()
+                    .rev()
+                    .find(|loader| loader.extensions().is_empty())
+            })
+            .cloned()
+    }
+}
+
+// "a/./b/../c.png" and "a/c.png" name the same asset
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            component => out.push(component),
+        }
+    }
+    out
+}
+
+fn worker(shared: Weak<Shared>, requests: Receiver<LoadRequest>) {
+    while let Ok(request) = requests.recv() {
+        // every handle to the asset was dropped before it got its turn
+        let slot = match request.slot.upgrade() {
+            Some(slot) => slot,
+            None => continue,
+        };
+        let manager = match shared.upgrade() {
+            Some(shared) => AssetManager { shared },
+            None => return,
+        };
+        let mut ctx = LoadContext {
+            manager: &manager,
+            path: slot.path(),
+            dependencies: Vec::new(),
+        };
+        // a panicking loader fails its asset instead of stopping the worker for every other one
+        let result = panic::catch_unwind(AssertUnwindSafe(|| request.loader.load(&mut ctx)))
+            .unwrap_or_else(|payload| {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown cause");
+                Err(anyhow::anyhow!("The loader panicked: {}", message))
+            });
+        let dependencies = ctx.dependencies;
+        let was_loaded = slot.state() == LoadState::Loaded;
+        let result = slot.finish(result, dependencies);
+
+        let handle = UntypedHandle { slot };
+        let path = handle.path().display();
+        match (result, request.reload_chain) {
+            (Ok(()), Some(chain)) => {
+                log::info!("Reloaded asset {}", path);
+                manager.notify(AssetEvent::Reloaded(handle.clone()));
+                manager.reload_dependents(&handle, chain);
+            }
+            (Ok(()), None) => {}
+            (Err(e), Some(_)) if was_loaded => {
+                log::error!(
+                    "Failed to reload asset {}, keeping the previous version: {:?}",
+                    path,
+                    e
+                );
+                manager.notify(AssetEvent::ReloadFailed(handle.clone(), format!("{:#}", e)));
+            }
+            (Err(e), _) => log::error!("Failed to load asset {}: {:?}", path, e),
+        }
+    }
+}
+
+// reports changed files of a directory mounted at `point` to the manager
+fn watch(shared: Weak<Shared>, point: PathBuf, directory: &Path) -> anyhow::Result<FileWatcher> {
+    let root = directory.canonicalize()?;
+    let (sender, receiver) = mpsc::channel::<PathBuf>();
+    thread::Builder::new()
+        .name("asset watcher".into())
+        .spawn(move || {
+            while let Ok(path) = receiver.recv() {
+                thread::sleep(RELOAD_DELAY);
+                let mut paths = vec![path];
+                paths.extend(receiver.try_iter());
+                paths.sort();
+                paths.dedup();
+                let manager = match shared.upgrade() {
+                    Some(shared) => AssetManager { shared },
+                    None => return,
+                };
+                paths.into_iter().for_each(|path| manager.reload(path));
+            }
+        })?;
+    FileWatcher::new(&root.clone(), move |path| {
+        if let Ok(path) = path.strip_prefix(&root) {
+            let _ = sender.send(point.join(path));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, sync::mpsc::RecvTimeoutError};
+
+    use crate::vfs::{DirectoryMount, Mount};
+
+    use super::*;
+
+    const EVENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    // a fresh directory for each test, so tests running in parallel do not see each other's files
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("amk-assets-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn manager(dir: &Path) -> AssetManager {
+        AssetManager::new(Vfs::new().mount("", DirectoryMount::new(dir)))
+    }
+
+    // files that can be changed without the directory watcher reloading them behind the test's
+    // back
+    #[derive(Clone, Default)]
+    struct MemoryMount {
+        files: Arc<Mutex<HashMap<PathBuf, Vec<u8>>>>,
+    }
+
+    impl MemoryMount {
+        fn write(&self, path: &str, data: &str) {
+            let data = data.as_bytes().to_vec();
+            self.files.lock().unwrap().insert(path.into(), data);
+        }
+
+        fn remove(&self, path: &str) {
+            self.files.lock().unwrap().remove(Path::new(path));
+        }
+    }
+
+    impl Mount for MemoryMount {
+        fn read(&self, path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(self.files.lock().unwrap().get(path).cloned())
+        }
+
+        fn exists(&self, path: &Path) -> bool {
+            self.files.lock().unwrap().contains_key(path)
+        }
+    }
+
+    // the text of a file, loading every file named on its lines as a dependency
+    struct Listing(String);
+
+    struct ListingLoader;
+
+    impl AssetLoader for ListingLoader {
+        type Asset = Listing;
+
+        fn extensions(&self) -> &[&str] {
+            &["list"]
+        }
+
+        fn load(&self, ctx: &mut LoadContext) -> anyhow::Result<Arc<Listing>> {
+            let text = String::from_utf8(ctx.read()?)?;
+            for line in text.lines().skip(1) {
+                ctx.load::<Listing>(line);
+            }
+            Ok(Arc::new(Listing(text)))
+        }
+    }
+
+    fn next_event(events: &Receiver<AssetEvent>) -> AssetEvent {
+        events.recv_timeout(EVENT_TIMEOUT).unwrap()
+    }
+
+    struct Panics;
+
+    struct PanicLoader;
+
+    impl AssetLoader for PanicLoader {
+        type Asset = Panics;
+
+        fn extensions(&self) -> &[&str] {
+            &["boom"]
+        }
+
+        fn load(&self, _ctx: &mut LoadContext) -> anyhow::Result<Arc<Panics>> {
+            panic!("boom")
+        }
+    }
+
+    #[test]
+    fn load_shares_handles() {
+        let dir = temp_dir("share");
+        fs::write(dir.join("a.txt"), "hello").unwrap();
+        let assets = manager(&dir);
+        let handle = assets.load_blocking::<String>("a.txt").unwrap();
+        assert_eq!(handle.get().unwrap().as_str(), "hello");
+        assert_eq!(assets.load::<String>("./b/../a.txt"), handle);
+        // the same file as another type is a separate asset
+        let bytes = assets.load_blocking::<Vec<u8>>("a.txt").unwrap();
+        assert_eq!(bytes.get().unwrap().as_slice(), b"hello");
+        assert!(assets.load_blocking::<String>("missing.txt").is_err());
+    }
+
+    #[test]
+    fn panicking_loader_fails_its_asset() {
+        let dir = temp_dir("panic");
+        fs::write(dir.join("a.boom"), "").unwrap();
+        fs::write(dir.join("b.txt"), "still loading").unwrap();
+        let assets = manager(&dir);
+        assets.register(PanicLoader);
+        let e = assets.load_blocking::<Panics>("a.boom").unwrap_err();
+        assert!(e.to_string().contains("panicked: boom"), "{}", e);
+        // the worker survived the panic
+        let handle = assets.load_blocking::<String>("b.txt").unwrap();
+        assert_eq!(handle.get().unwrap().as_str(), "still loading");
+    }
+
+    #[test]
+    fn reload_swaps_behind_handles() {
+        let mount = MemoryMount::default();
+        mount.write("a.bin", "one");
+        let assets = AssetManager::new(Vfs::new().mount("", mount.clone()));
+        let events = assets.subscribe();
+        let handle = assets.load_blocking::<Vec<u8>>("a.bin").unwrap();
+        let old = handle.get().unwrap();
+        assert_eq!(handle.version(), 1);
+
+        mount.write("a.bin", "two");
+        assets.reload("a.bin");
+        match next_event(&events) {
+            AssetEvent::Reloaded(reloaded) => assert_eq!(reloaded, handle.untyped()),
+            event => panic!("unexpected {:?}", event),
+        }
+        assert_eq!(handle.get().unwrap().as_slice(), b"two");
+        assert_eq!(handle.version(), 2);
+        // whoever held on to the previous version keeps it
+        assert_eq!(old.as_slice(), b"one");
+    }
+
+    #[test]
+    fn failed_reload_keeps_previous_version() {
+        let mount = MemoryMount::default();
+        mount.write("a.bin", "one");
+        let assets = AssetManager::new(Vfs::new().mount("", mount.clone()));
+        let events = assets.subscribe();
+        let handle = assets.load_blocking::<Vec<u8>>("a.bin").unwrap();
+
+        mount.remove("a.bin");
+        assets.reload("a.bin");
+        match next_event(&events) {
+            AssetEvent::ReloadFailed(failed, _) => assert_eq!(failed, handle.untyped()),
+            event => panic!("unexpected {:?}", event),
+        }
+        assert_eq!(handle.state(), LoadState::Loaded);
+        assert_eq!(handle.get().unwrap().as_slice(), b"one");
+        assert_eq!(handle.version(), 1);
+    }
+
+    // a and b depend on each other, reloading b reloads a once and stops there
+    #[test]
+    fn reload_dependents_until_cycle() {
+        let mount = MemoryMount::default();
+        mount.write("a.list", "a\nb.list");
+        mount.write("b.list", "b\na.list");
+        let assets = AssetManager::new(Vfs::new().mount("", mount.clone()));
+        assets.register(ListingLoader);
+        let events = assets.subscribe();
+        let a = assets.load::<Listing>("a.list");
+        let b = assets.load::<Listing>("b.list");
+        assert_eq!(a.wait(), LoadState::Loaded);
+        assert_eq!(b.wait(), LoadState::Loaded);
+        assert_eq!(a.recursive_state(), LoadState::Loaded);
+
+        mount.write("b.list", "b2\na.list");
+        assets.reload("b.list");
+        let reloaded: Vec<_> = (0..2)
+            .map(|_| match next_event(&events) {
+                AssetEvent::Reloaded(handle) => handle,
+                event => panic!("unexpected {:?}", event),
+            })
+            .collect();
+        assert_eq!(reloaded, [b.untyped(), a.untyped()]);
+        assert_eq!(
+            events.recv_timeout(Duration::from_millis(200)).unwrap_err(),
+            RecvTimeoutError::Timeout
+        );
+        assert_eq!(b.get().unwrap().0, "b2\na.list");
+        assert_eq!([a.version(), b.version()], [2, 2]);
+    }
+
+    // a and b depend on each other, dropping both handles frees both
+    #[test]
+    fn dependency_cycle_is_freed() {
+        let mount = MemoryMount::default();
+        mount.write("a.list", "a\nb.list");
+        mount.write("b.list", "b\na.list");
+        mount.write("c.bin", "c");
+        let assets = AssetManager::new(Vfs::new().mount("", mount));
+        assets.register(ListingLoader);
+        let a = assets.load::<Listing>("a.list");
+        let b = assets.load::<Listing>("b.list");
+        assert_eq!(a.wait(), LoadState::Loaded);
+        assert_eq!(b.wait(), LoadState::Loaded);
+        assert_eq!(a.dependencies(), [b.untyped()]);
+        assert_eq!(b.dependencies(), [a.untyped()]);
+        let slots = [Arc::downgrade(&a.slot), Arc::downgrade(&b.slot)];
+
+        drop((a, b));
+        // the worker handles requests in order, so it has let go of a and b once c has loaded
+        assets.load_blocking::<Vec<u8>>("c.bin").unwrap();
+        assert!(slots.iter().all(|slot| slot.strong_count() == 0));
+    }
+}
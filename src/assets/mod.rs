@@ -0,0 +1,3 @@
+pub mod handle;
+pub mod loader;
+pub mod manager;
@@ -0,0 +1,75 @@
+// builds an asset archive out of a directory tree: pack <directory> <archive>
+use std::{
+    env,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+// the reader half is only used by the game
+#[allow(dead_code)]
+#[path = "../vfs/archive.rs"]
+mod archive;
+
+use archive::{archive_path, ArchiveWriter, Compression};
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (input, output) = match args.as_slice() {
+        [input, output] => (PathBuf::from(input), PathBuf::from(output)),
+        _ => anyhow::bail!("usage: pack <directory> <archive>"),
+    };
+
+    // collected before the archive is created, so it never ends up inside itself
+    let mut files = Vec::new();
+    collect_files(&input, &mut files)?;
+    // a stable order makes packing the same files twice produce the same archive
+    files.sort();
+
+    let file =
+        File::create(&output).with_context(|| format!("Unable to create {}", output.display()))?;
+    let mut writer = ArchiveWriter::new(BufWriter::new(file))?;
+    let (mut size, mut stored_size) = (0, 0);
+    for path in &files {
+        let relative = path.strip_prefix(&input)?;
+        let name = archive_path(relative)
+            .with_context(|| format!("{} is not valid UTF-8", relative.display()))?;
+        let data = fs::read(path).with_context(|| format!("Unable to read {}", path.display()))?;
+        let entry = writer.add(&name, &data)?;
+        let compression = match entry.compression {
+            Compression::None => "stored",
+            Compression::Deflate => "deflated",
+        };
+        println!(
+            "{:>10} -> {:>10} {:>8}  {}",
+            entry.size, entry.stored_size, compression, name
+        );
+        size += entry.size;
+        stored_size += entry.stored_size;
+    }
+    writer.finish()?;
+    println!(
+        "Packed {} files from {} bytes into {} bytes at {}",
+        files.len(),
+        size,
+        stored_size,
+        output.display()
+    );
+    Ok(())
+}
+
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let entries = fs::read_dir(directory)
+        .with_context(|| format!("Unable to read the directory {}", directory.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
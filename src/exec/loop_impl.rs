@@ -5,7 +5,10 @@ use std::sync::{
 
 use winit::{dpi::PhysicalSize, event::Event, window::Window};
 
-use crate::{graphics::context::RenderContext, scenes::root::RootScene};
+use crate::{
+    graphics::{context::RenderContext, dump::AudioSink, screenshot::default_screenshot_path},
+    scenes::root::RootScene,
+};
 
 use super::{
     loops::GameLoop,
@@ -27,13 +30,22 @@ impl GameLoop for RenderLoop {
         match self.elrl_receiver.try_recv() {
             Ok(msg) => match msg {
                 ELRLMsg::Resize(size) => self.new_size = Some(size),
+                ELRLMsg::Screenshot => self.render_ctx.request_screenshot(|screenshot| {
+                    // encoding takes long enough to cause a visible hitch on the render thread
+                    std::thread::spawn(move || {
+                        let path = default_screenshot_path();
+                        match screenshot.save_png(&path) {
+                            Ok(()) => log::info!("Saved screenshot to {}", path.display()),
+                            Err(e) => log::error!("Failed to save screenshot: {:?}", e),
+                        }
+                    });
+                }),
             },
             Err(TryRecvError::Empty) => {}
             e => {
                 e.unwrap();
             }
         }
-        self.render_ctx.wait_for_done();
         let done_resizing = if let Some(size) = &self.new_size {
             self.render_ctx.resize(*size)?
         } else {
@@ -48,8 +60,41 @@ impl GameLoop for RenderLoop {
 }
 pub struct AudioLoop {
     pub root_scene: Arc<RootScene>,
+    // receives the mixed output while frames are dumped
+    pub dump_sink: Option<AudioSink>,
+    dumped_sample_frames: u64,
+}
+impl AudioLoop {
+    pub fn new(root_scene: Arc<RootScene>, dump_sink: Option<AudioSink>) -> Self {
+        Self {
+            root_scene,
+            dump_sink,
+            dumped_sample_frames: 0,
+        }
+    }
+
+    // interleaved samples in -1..1. nothing produces sound yet, so the output is silence
+    fn mix(&mut self, _out: &mut [f32]) {}
+}
+impl GameLoop for AudioLoop {
+    fn run(&mut self) -> anyhow::Result<()> {
+        let (due, channels) = match &self.dump_sink {
+            Some(sink) => (sink.sample_frames_due(), sink.channels() as usize),
+            None => return Ok(()),
+        };
+        // mixes exactly the samples the video has reached, the loop is paced by the same clock
+        let count = due.saturating_sub(self.dumped_sample_frames) as usize;
+        if count > 0 {
+            let mut samples = vec![0.0; count * channels];
+            self.mix(&mut samples);
+            if let Some(sink) = &self.dump_sink {
+                sink.write(&samples);
+            }
+            self.dumped_sample_frames = due;
+        }
+        Ok(())
+    }
 }
-impl GameLoop for AudioLoop {}
 pub struct EventLoop {
     pub window: Window,
     pub root_scene: Arc<RootScene>,
@@ -5,7 +5,10 @@ use std::{
 
 use winit::event_loop::ControlFlow;
 
-use crate::utils::sync::{new_clock_sync, ClockSync};
+use crate::utils::{
+    clock::VirtualClock,
+    sync::{new_clock_sync, ClockSync},
+};
 
 use super::{
     loop_impl::{AudioLoop, EventLoop, RenderLoop, UpdateLoop},
@@ -27,6 +30,7 @@ pub struct GameLoopManager {
     exec_mode: Mode,
     event_loop: EventLoop,
     clock_sync: Box<dyn ClockSync>,
+    virtual_clock: Option<VirtualClock>,
 }
 
 pub trait DropData {
@@ -34,11 +38,14 @@ pub trait DropData {
 }
 
 impl GameLoopManager {
+    // with a virtual clock, e.g. the clock of a frame dump, every thread is paced by it instead
+    // of the wall clock. the render loop advances that clock, so its thread is never paced
     pub fn new(
         event_loop: EventLoop,
         update_loop: UpdateLoop,
         render_loop: RenderLoop,
         audio_loop: AudioLoop,
+        virtual_clock: Option<VirtualClock>,
     ) -> Self {
         let mut loops = GameLoopContainer::new();
         loops.insert(GameLoopKind::Update, Box::new(update_loop), 1.0);
@@ -46,7 +53,8 @@ impl GameLoopManager {
         loops.insert(GameLoopKind::Audio, Box::new(audio_loop), 1.0);
         Self {
             runners: Default::default(),
-            clock_sync: new_clock_sync(),
+            clock_sync: new_clock_sync(virtual_clock.clone()),
+            virtual_clock,
             exec_mode: Mode::new(),
             loops,
             event_loop,
@@ -59,8 +67,15 @@ impl GameLoopManager {
         render_loop: RenderLoop,
         audio_loop: AudioLoop,
         exec_mode: Mode,
+        virtual_clock: Option<VirtualClock>,
     ) -> Self {
-        let mut manager = Self::new(event_loop, update_loop, render_loop, audio_loop);
+        let mut manager = Self::new(
+            event_loop,
+            update_loop,
+            render_loop,
+            audio_loop,
+            virtual_clock,
+        );
         manager.set_mode(exec_mode);
         manager
     }
@@ -76,7 +91,7 @@ impl GameLoopManager {
 
     fn get_or_create_runner(&mut self, thread_id: usize) {
         if self.runners[thread_id].is_none() {
-            self.runners[thread_id] = Some(Runner::new());
+            self.runners[thread_id] = Some(Runner::new(self.virtual_clock.clone()));
         }
     }
 
@@ -140,12 +155,22 @@ impl GameLoopManager {
         }
 
         for i in 0..MAX_RUNNERS {
-            self.set_thread_frequency(i, new_mode.thread_frequencies[i]);
+            self.set_thread_frequency(i, self.thread_frequency(&new_mode, i));
         }
 
         self.exec_mode = new_mode;
     }
 
+    // waiting for the virtual clock on the thread that advances it would never return
+    fn thread_frequency(&self, mode: &Mode, thread_id: usize) -> f64 {
+        let (render_thread_id, _) = mode.get(GameLoopKind::Render);
+        if self.virtual_clock.is_some() && thread_id == render_thread_id {
+            0.0
+        } else {
+            mode.thread_frequencies[thread_id]
+        }
+    }
+
     pub fn run(mut self, window_loop: WinitEventLoop, elglm_receiver: Receiver<ELGLMMsg>) -> ! {
         window_loop.run(move |evt, _, cf| {
             *cf = if self.loops.empty() {
@@ -167,8 +192,8 @@ impl GameLoopManager {
                         }
                     }
                     self.loops.run().expect("Error running game loops");
-                    self.clock_sync
-                        .sync(self.exec_mode.thread_frequencies[MAIN_THREAD_ID]);
+                    let frequency = self.thread_frequency(&self.exec_mode, MAIN_THREAD_ID);
+                    self.clock_sync.sync(frequency);
                 }
                 e => self.event_loop.run(e),
             }
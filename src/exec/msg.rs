@@ -20,5 +20,6 @@ pub enum ELGLMMsg {
 }
 
 pub enum ELRLMsg {
-    Resize(PhysicalSize<u32>)
+    Resize(PhysicalSize<u32>),
+    Screenshot,
 }
@@ -3,7 +3,7 @@ use std::{
     thread::{self, JoinHandle},
 };
 
-use crate::utils::sync::new_clock_sync;
+use crate::utils::{clock::VirtualClock, sync::new_clock_sync};
 
 use super::{
     loops::{GameLoop, GameLoopContainer, GameLoopKind},
@@ -17,7 +17,7 @@ pub(crate) struct Runner {
 }
 
 impl Runner {
-    pub fn new() -> Self {
+    pub fn new(virtual_clock: Option<VirtualClock>) -> Self {
         let (f_sender, f_receiver) = mpsc::channel::<FromRunnerMsg>();
         let (t_sender, t_receiver) = mpsc::channel::<ToRunnerMsg>();
         Self {
@@ -25,7 +25,7 @@ impl Runner {
                 let sender = f_sender;
                 let receiver = t_receiver;
                 let mut container = GameLoopContainer::new();
-                let mut clock_sync = new_clock_sync();
+                let mut clock_sync = new_clock_sync(virtual_clock);
                 let mut frequency: f64 = 0.0;
                 loop {
                     if let Some(msg) = Self::receive_msg(&receiver, container.empty()) {
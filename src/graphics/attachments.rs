@@ -0,0 +1,240 @@
+use std::sync::Arc;
+
+use vulkano::{
+    device::{physical::PhysicalDevice, Device},
+    format::{ClearValue, Format},
+    image::{ImageFormatInfo, ImageLayout, ImageUsage, SampleCount, SampleCounts},
+    render_pass::{
+        AttachmentDescription, AttachmentReference, LoadOp, RenderPass, RenderPassCreateInfo,
+        StoreOp, SubpassDescription,
+    },
+};
+
+// every format has a stencil aspect, a depth-only format would silently drop the stencil
+const DEPTH_STENCIL_FORMATS: [Format; 3] = [
+    Format::D24_UNORM_S8_UINT,
+    Format::D32_SFLOAT_S8_UINT,
+    Format::D16_UNORM_S8_UINT,
+];
+
+pub fn select_depth_stencil_format(phys_device: &PhysicalDevice) -> Option<Format> {
+    DEPTH_STENCIL_FORMATS.into_iter().find(|format| {
+        phys_device
+            .format_properties(*format)
+            .optimal_tiling_features
+            .depth_stencil_attachment
+    })
+}
+
+// picks the highest sample count that does not exceed the requested one, and that both the
+// framebuffer limits and the formats of the multisampled attachments support
+pub fn select_samples(
+    phys_device: &PhysicalDevice,
+    requested: u32,
+    color_format: Format,
+    depth_stencil_format: Option<Format>,
+) -> SampleCount {
+    let properties = phys_device.properties();
+    let mut counts = vec![
+        properties.framebuffer_color_sample_counts,
+        format_sample_counts(
+            phys_device,
+            color_format,
+            ImageUsage {
+                color_attachment: true,
+                transient_attachment: true,
+                ..ImageUsage::empty()
+            },
+        ),
+    ];
+    if let Some(format) = depth_stencil_format {
+        counts.push(properties.framebuffer_depth_sample_counts);
+        counts.push(properties.framebuffer_stencil_sample_counts);
+        counts.push(format_sample_counts(
+            phys_device,
+            format,
+            ImageUsage {
+                depth_stencil_attachment: true,
+                transient_attachment: true,
+                ..ImageUsage::empty()
+            },
+        ));
+    }
+    let supported = |samples: SampleCount| {
+        counts.iter().all(|counts| match samples {
+            SampleCount::Sample64 => counts.sample64,
+            SampleCount::Sample32 => counts.sample32,
+            SampleCount::Sample16 => counts.sample16,
+            SampleCount::Sample8 => counts.sample8,
+            SampleCount::Sample4 => counts.sample4,
+            SampleCount::Sample2 => counts.sample2,
+            SampleCount::Sample1 => true,
+        })
+    };
+    [
+        SampleCount::Sample64,
+        SampleCount::Sample32,
+        SampleCount::Sample16,
+        SampleCount::Sample8,
+        SampleCount::Sample4,
+        SampleCount::Sample2,
+    ]
+    .into_iter()
+    .find(|samples| *samples as u32 <= requested && supported(*samples))
+    .unwrap_or(SampleCount::Sample1)
+}
+
+// the sample counts of 2D optimally tiled images of `format`, none if it cannot be used so
+fn format_sample_counts(
+    phys_device: &PhysicalDevice,
+    format: Format,
+    usage: ImageUsage,
+) -> SampleCounts {
+    phys_device
+        .image_format_properties(ImageFormatInfo {
+            format: Some(format),
+            usage,
+            ..Default::default()
+        })
+        .ok()
+        .flatten()
+        .map_or_else(SampleCounts::empty, |properties| properties.sample_counts)
+}
+
+// attachment order: [multisampled color], [depth/stencil], output color
+pub fn create_main_render_pass(
+    device: Arc<Device>,
+    color_format: Format,
+    samples: SampleCount,
+    depth_stencil_format: Option<Format>,
+) -> anyhow::Result<Arc<RenderPass>> {
+    let multisampled = samples != SampleCount::Sample1;
+    let mut attachments = Vec::new();
+    let mut subpass = SubpassDescription::default();
+
+    if multisampled {
+        subpass.color_attachments.push(Some(AttachmentReference {
+            attachment: attachments.len() as u32,
+            layout: ImageLayout::ColorAttachmentOptimal,
+            ..Default::default()
+        }));
+        attachments.push(AttachmentDescription {
+            format: Some(color_format),
+            samples,
+            load_op: LoadOp::Clear,
+            store_op: StoreOp::DontCare,
+            initial_layout: ImageLayout::ColorAttachmentOptimal,
+            final_layout: ImageLayout::ColorAttachmentOptimal,
+            ..Default::default()
+        });
+    }
+
+    if let Some(format) = depth_stencil_format {
+        subpass.depth_stencil_attachment = Some(AttachmentReference {
+            attachment: attachments.len() as u32,
+            layout: ImageLayout::DepthStencilAttachmentOptimal,
+            ..Default::default()
+        });
+        attachments.push(AttachmentDescription {
+            format: Some(format),
+            samples,
+            load_op: LoadOp::Clear,
+            store_op: StoreOp::DontCare,
+            stencil_load_op: LoadOp::Clear,
+            stencil_store_op: StoreOp::DontCare,
+            initial_layout: ImageLayout::DepthStencilAttachmentOptimal,
+            final_layout: ImageLayout::DepthStencilAttachmentOptimal,
+            ..Default::default()
+        });
+    }
+
+    let output = AttachmentReference {
+        attachment: attachments.len() as u32,
+        layout: ImageLayout::ColorAttachmentOptimal,
+        ..Default::default()
+    };
+    if multisampled {
+        subpass.resolve_attachments.push(Some(output));
+    } else {
+        subpass.color_attachments.push(Some(output));
+    }
+    attachments.push(AttachmentDescription {
+        format: Some(color_format),
+        samples: SampleCount::Sample1,
+        load_op: if multisampled {
+            LoadOp::DontCare
+        } else {
+            LoadOp::Clear
+        },
+        store_op: StoreOp::Store,
+        initial_layout: ImageLayout::ColorAttachmentOptimal,
+        final_layout: ImageLayout::ColorAttachmentOptimal,
+        ..Default::default()
+    });
+
+    Ok(RenderPass::new(
+        device,
+        RenderPassCreateInfo {
+            attachments,
+            subpasses: vec![subpass],
+            ..Default::default()
+        },
+    )?)
+}
+
+// a single color attachment for full-screen passes, cleared first if the pass does not cover
+// all of it
+pub fn create_fullscreen_render_pass(
+    device: Arc<Device>,
+    format: Format,
+    clear: bool,
+) -> anyhow::Result<Arc<RenderPass>> {
+    Ok(RenderPass::new(
+        device,
+        RenderPassCreateInfo {
+            attachments: vec![AttachmentDescription {
+                format: Some(format),
+                samples: SampleCount::Sample1,
+                load_op: if clear {
+                    LoadOp::Clear
+                } else {
+                    LoadOp::DontCare
+                },
+                store_op: StoreOp::Store,
+                initial_layout: ImageLayout::ColorAttachmentOptimal,
+                final_layout: ImageLayout::ColorAttachmentOptimal,
+                ..Default::default()
+            }],
+            subpasses: vec![SubpassDescription {
+                color_attachments: vec![Some(AttachmentReference {
+                    attachment: 0,
+                    layout: ImageLayout::ColorAttachmentOptimal,
+                    ..Default::default()
+                })],
+                ..Default::default()
+            }],
+            ..Default::default()
+        },
+    )?)
+}
+
+pub fn main_clear_values(
+    color: [f32; 4],
+    samples: SampleCount,
+    depth_stencil_format: Option<Format>,
+) -> Vec<Option<ClearValue>> {
+    let multisampled = samples != SampleCount::Sample1;
+    let mut clear_values = Vec::new();
+    if multisampled {
+        clear_values.push(Some(color.into()));
+    }
+    if depth_stencil_format.is_some() {
+        clear_values.push(Some(ClearValue::DepthStencil((1.0, 0))));
+    }
+    clear_values.push(if multisampled {
+        None
+    } else {
+        Some(color.into())
+    });
+    clear_values
+}
@@ -0,0 +1,210 @@
+use winit::dpi::PhysicalSize;
+
+// column major, as expected by GLSL
+pub type Mat4 = [[f32; 4]; 4];
+
+pub const IDENTITY: Mat4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+pub fn mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut out = [[0.0; 4]; 4];
+    for (col, out_col) in out.iter_mut().enumerate() {
+        for (row, value) in out_col.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[col][k]).sum();
+        }
+    }
+    out
+}
+
+pub fn translation(offset: [f32; 3]) -> Mat4 {
+    let mut out = IDENTITY;
+    out[3] = [offset[0], offset[1], offset[2], 1.0];
+    out
+}
+
+pub fn scale(factor: [f32; 3]) -> Mat4 {
+    let mut out = IDENTITY;
+    for (i, factor) in factor.into_iter().enumerate() {
+        out[i][i] = factor;
+    }
+    out
+}
+
+pub fn rotation_x(angle: f32) -> Mat4 {
+    let (sin, cos) = angle.sin_cos();
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, cos, sin, 0.0],
+        [0.0, -sin, cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+pub fn rotation_y(angle: f32) -> Mat4 {
+    let (sin, cos) = angle.sin_cos();
+    [
+        [cos, 0.0, -sin, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [sin, 0.0, cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+pub fn rotation_z(angle: f32) -> Mat4 {
+    let (sin, cos) = angle.sin_cos();
+    [
+        [cos, sin, 0.0, 0.0],
+        [-sin, cos, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    // `height` world units are visible vertically, the width follows the aspect ratio of the
+    // render extent
+    Orthographic { height: f32, near: f32, far: f32 },
+    // `fov_y` is the vertical field of view in radians
+    Perspective { fov_y: f32, near: f32, far: f32 },
+    // view space is passed through as clip space, +y down and depth 0..1
+    Clip,
+}
+
+// world space is right handed with +y up, the camera looks down -z when yaw, pitch and roll are
+// all 0. the projection maps depth to 0..1 with +y down, as Vulkan expects, except for
+// `Projection::Clip`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub position: [f32; 3],
+    // rotation around +y, then around the rotated +x, then around the view direction
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    pub projection: Projection,
+}
+
+impl Camera {
+    // a 2D camera centred on the origin, with depth -1..1 in front of and behind the xy plane
+    pub fn orthographic(height: f32) -> Self {
+        Self {
+            position: [0.0; 3],
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            projection: Projection::Orthographic {
+                height,
+                near: -1.0,
+                far: 1.0,
+            },
+        }
+    }
+
+    pub fn perspective(fov_y: f32) -> Self {
+        Self {
+            position: [0.0; 3],
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            projection: Projection::Perspective {
+                fov_y,
+                near: 0.1,
+                far: 1000.0,
+            },
+        }
+    }
+
+    // draws in clip space unchanged, for vertices that are already in normalized device
+    // coordinates
+    pub fn clip() -> Self {
+        Self {
+            position: [0.0; 3],
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            projection: Projection::Clip,
+        }
+    }
+
+    pub fn position(mut self, position: [f32; 3]) -> Self {
+        self.position = position;
+        self
+    }
+
+    // the rotation of a 2D camera
+    pub fn roll(mut self, roll: f32) -> Self {
+        self.roll = roll;
+        self
+    }
+
+    pub fn depth_range(mut self, near_plane: f32, far_plane: f32) -> Self {
+        match &mut self.projection {
+            Projection::Orthographic { near, far, .. }
+            | Projection::Perspective { near, far, .. } => {
+                *near = near_plane;
+                *far = far_plane;
+            }
+            Projection::Clip => {}
+        }
+        self
+    }
+
+    // turns the camera towards `target`, keeping its roll
+    pub fn look_at(mut self, target: [f32; 3]) -> Self {
+        let dir = [
+            target[0] - self.position[0],
+            target[1] - self.position[1],
+            target[2] - self.position[2],
+        ];
+        self.yaw = (-dir[0]).atan2(-dir[2]);
+        self.pitch = dir[1].atan2(dir[0].hypot(dir[2]));
+        self
+    }
+
+    // world to view space
+    pub fn view(&self) -> Mat4 {
+        let [x, y, z] = self.position;
+        let rotation = mul(
+            &rotation_z(-self.roll),
+            &mul(&rotation_x(-self.pitch), &rotation_y(-self.yaw)),
+        );
+        mul(&rotation, &translation([-x, -y, -z]))
+    }
+
+    pub fn projection(&self, extent: PhysicalSize<u32>) -> Mat4 {
+        let aspect = extent.width.max(1) as f32 / extent.height.max(1) as f32;
+        match self.projection {
+            Projection::Orthographic { height, near, far } => [
+                [2.0 / (height * aspect), 0.0, 0.0, 0.0],
+                [0.0, -2.0 / height, 0.0, 0.0],
+                [0.0, 0.0, -1.0 / (far - near), 0.0],
+                [0.0, 0.0, -near / (far - near), 1.0],
+            ],
+            Projection::Perspective { fov_y, near, far } => {
+                let f = 1.0 / (fov_y / 2.0).tan();
+                [
+                    [f / aspect, 0.0, 0.0, 0.0],
+                    [0.0, -f, 0.0, 0.0],
+                    [0.0, 0.0, far / (near - far), -1.0],
+                    [0.0, 0.0, near * far / (near - far), 0.0],
+                ]
+            }
+            Projection::Clip => IDENTITY,
+        }
+    }
+
+    pub fn view_projection(&self, extent: PhysicalSize<u32>) -> Mat4 {
+        mul(&self.projection(extent), &self.view())
+    }
+}
+
+impl Default for Camera {
+    // renderers written before the camera existed draw in normalized device coordinates
+    fn default() -> Self {
+        Self::clip()
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+
+use vulkano::{
+    format::{Format, NumericType},
+    swapchain::ColorSpace,
+};
+
+// the colour space requested for the window, HDR spaces fall back to sRGB when the surface does
+// not offer them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputColorSpace {
+    Srgb,
+    // Rec.2020 primaries encoded with the ST 2084 (PQ) curve
+    Hdr10,
+    // linear Rec.709 primaries where 1.0 is 80 nits, values may exceed 1.0
+    ScRgb,
+}
+
+impl OutputColorSpace {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value.to_lowercase().as_str() {
+            "srgb" => Self::Srgb,
+            "hdr10" => Self::Hdr10,
+            "scrgb" => Self::ScRgb,
+            _ => return None,
+        })
+    }
+
+    // the value of `--color-space <name>` or `--color-space=<name>`
+    pub fn from_args() -> Option<Self> {
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--color-space" {
+                return args.next().and_then(|value| Self::parse(&value));
+            }
+            if let Some(value) = arg.strip_prefix("--color-space=") {
+                return Self::parse(value);
+            }
+        }
+        None
+    }
+
+    fn surface_color_space(&self) -> ColorSpace {
+        match self {
+            Self::Srgb => ColorSpace::SrgbNonLinear,
+            Self::Hdr10 => ColorSpace::Hdr10St2084,
+            Self::ScRgb => ColorSpace::ExtendedSrgbLinear,
+        }
+    }
+}
+
+impl fmt::Display for OutputColorSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Srgb => write!(f, "sRGB"),
+            Self::Hdr10 => write!(f, "HDR10"),
+            Self::ScRgb => write!(f, "scRGB"),
+        }
+    }
+}
+
+fn is_srgb(format: Format) -> bool {
+    format.type_color() == Some(NumericType::SRGB)
+}
+
+// prefers an sRGB format in the sRGB colour space, so the hardware encodes the linear output
+pub fn select_surface_format(
+    formats: &[(Format, ColorSpace)],
+    requested: OutputColorSpace,
+) -> (Format, ColorSpace) {
+    if requested != OutputColorSpace::Srgb {
+        let wanted = requested.surface_color_space();
+        if let Some(found) = formats.iter().find(|(_, cs)| *cs == wanted) {
+            return *found;
+        }
+        log::warn!(
+            "The surface does not offer {} output, falling back to sRGB",
+            requested
+        );
+    }
+    *formats
+        .iter()
+        .max_by_key(|(format, cs)| {
+            let mut score = 0;
+            if *cs == ColorSpace::SrgbNonLinear {
+                score += 2;
+            }
+            if is_srgb(*format) {
+                score += 2;
+            } else if *format == Format::R8G8B8A8_UNORM || *format == Format::B8G8R8A8_UNORM {
+                score += 1;
+            }
+            score
+        })
+        .unwrap()
+}
+
+// how the output pass encodes the linear Rec.709 scene for the render target
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputTransform {
+    // the target format is sRGB, so the hardware encodes on write
+    Linear,
+    // a UNORM target presented as sRGB
+    SrgbEncode,
+    // `paper_white` is the brightness of 1.0 in nits
+    Hdr10 { paper_white: f32 },
+    ScRgb { paper_white: f32 },
+}
+
+impl OutputTransform {
+    pub fn new(format: Format, color_space: ColorSpace, paper_white: f32) -> Self {
+        match color_space {
+            ColorSpace::Hdr10St2084 => Self::Hdr10 { paper_white },
+            ColorSpace::ExtendedSrgbLinear => Self::ScRgb { paper_white },
+            _ if is_srgb(format) => Self::Linear,
+            _ => Self::SrgbEncode,
+        }
+    }
+
+    // the `params` push constant of the output shader
+    pub(crate) fn params(&self) -> [f32; 4] {
+        match *self {
+            Self::Linear => [0.0, 0.0, 0.0, 0.0],
+            Self::SrgbEncode => [1.0, 0.0, 0.0, 0.0],
+            Self::Hdr10 { paper_white } => [2.0, paper_white, 0.0, 0.0],
+            Self::ScRgb { paper_white } => [3.0, paper_white, 0.0, 0.0],
+        }
+    }
+}
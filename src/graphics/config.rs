@@ -0,0 +1,118 @@
+use std::path::PathBuf;
+
+use crate::vfs::Vfs;
+
+use super::{
+    color::OutputColorSpace, device::DeviceSelector, pipeline_cache::default_cache_dir,
+    post::effect::PostChain, resolution::ResolutionMode, validation::ValidationConfig,
+};
+
+pub struct RenderConfig {
+    pub(crate) frames_in_flight: usize,
+    pub(crate) samples: u32,
+    pub(crate) depth_stencil: bool,
+    pub(crate) vfs: Option<Vfs>,
+    pub(crate) shader_dir: PathBuf,
+    pub(crate) pipeline_cache_dir: Option<PathBuf>,
+    pub(crate) device: DeviceSelector,
+    pub(crate) validation: ValidationConfig,
+    pub(crate) clear_color: [f32; 4],
+    pub(crate) post: PostChain,
+    pub(crate) output_color_space: OutputColorSpace,
+    pub(crate) paper_white: f32,
+    pub(crate) resolution: ResolutionMode,
+}
+
+impl RenderConfig {
+    pub fn new() -> Self {
+        Self {
+            frames_in_flight: 2,
+            samples: 1,
+            depth_stencil: false,
+            vfs: None,
+            shader_dir: PathBuf::from("shaders"),
+            pipeline_cache_dir: default_cache_dir(),
+            device: DeviceSelector::Auto,
+            validation: ValidationConfig::new(),
+            clear_color: [0.0, 0.0, 0.2, 1.0],
+            post: PostChain::new(),
+            output_color_space: OutputColorSpace::Srgb,
+            paper_white: 200.0,
+            resolution: ResolutionMode::Window,
+        }
+    }
+
+    pub fn frames_in_flight(mut self, count: usize) -> Self {
+        self.frames_in_flight = count.max(1);
+        self
+    }
+
+    // clamped to the highest sample count the device supports
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    pub fn depth_stencil(mut self, enabled: bool) -> Self {
+        self.depth_stencil = enabled;
+        self
+    }
+
+    // the files shaders and fonts are read from, `default_vfs` if not set
+    pub fn vfs(mut self, vfs: Vfs) -> Self {
+        self.vfs = Some(vfs);
+        self
+    }
+
+    // directory of the virtual file system that shader names passed to `ShaderManager::module`
+    // are resolved against
+    pub fn shader_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.shader_dir = dir.into();
+        self
+    }
+
+    pub fn device(mut self, selector: DeviceSelector) -> Self {
+        self.device = selector;
+        self
+    }
+
+    // `None` keeps the pipeline cache in memory only
+    pub fn pipeline_cache_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.pipeline_cache_dir = dir;
+        self
+    }
+
+    pub fn validation(mut self, validation: ValidationConfig) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    // linear colour the scene is cleared to every frame
+    pub fn clear_color(mut self, color: [f32; 4]) -> Self {
+        self.clear_color = color;
+        self
+    }
+
+    // the initial post effects, the chain can be changed at runtime through `RenderContext::post`
+    pub fn post(mut self, chain: PostChain) -> Self {
+        self.post = chain;
+        self
+    }
+
+    pub fn output_color_space(mut self, color_space: OutputColorSpace) -> Self {
+        self.output_color_space = color_space;
+        self
+    }
+
+    // brightness in nits that a linear 1.0 is shown at on HDR outputs
+    pub fn paper_white(mut self, nits: f32) -> Self {
+        self.paper_white = nits.max(1.0);
+        self
+    }
+
+    // can be changed later with `RenderContext::set_resolution`
+    pub fn resolution(mut self, mode: ResolutionMode) -> Self {
+        self.resolution = mode;
+        self
+    }
+}
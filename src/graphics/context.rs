@@ -1,201 +1,198 @@
-use std::{cell::Cell, collections::HashSet, sync::Arc};
-
-use raw_window_handle::{
-    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
+use std::{
+    collections::HashSet,
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
 };
+
+use bytemuck::Pod;
 use vulkano::{
+    buffer::{BufferAccess, BufferContents, BufferUsage, CpuAccessibleBuffer},
     command_buffer::{
-        allocator::{
-            CommandBufferAllocator, StandardCommandBufferAllocator,
-            StandardCommandBufferBuilderAlloc,
-        },
-        AutoCommandBufferBuilder, CommandBufferLevel, CommandBufferUsage, RenderPassBeginInfo,
-        SubpassContents,
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        CopyImageToBufferInfo, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents,
     },
+    descriptor_set::allocator::StandardDescriptorSetAllocator,
     device::{physical::PhysicalDevice, Device},
-    device::{
-        physical::PhysicalDeviceType, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo,
-    },
+    device::{DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo},
     format::Format,
-    image::{view::ImageView, ImageUsage, SwapchainImage},
-    instance::{
-        debug::{
-            DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessenger,
-            DebugUtilsMessengerCreateInfo,
-        },
-        Instance, InstanceCreateInfo,
-    },
+    image::{ImageAccess, SampleCount},
+    instance::{debug::DebugUtilsMessenger, Instance, InstanceCreateInfo, InstanceExtensions},
     pipeline::graphics::viewport::Viewport,
-    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass},
-    swapchain::{
-        acquire_next_image, AcquireError, ColorSpace, Surface, Swapchain, SwapchainAbstract,
-        SwapchainCreateInfo, SwapchainCreationError, SwapchainPresentInfo,
-    },
-    sync::{FlushError, GpuFuture, Sharing},
-    VulkanLibrary, buffer::TypedBufferAccess,
+    render_pass::{Framebuffer, RenderPass},
+    swapchain::{acquire_next_image, AcquireError, SwapchainPresentInfo},
+    sync::{FlushError, GpuFuture},
+    VulkanLibrary,
+};
+use vulkano_win::required_extensions;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    window::Window,
 };
-use vulkano_win::{create_surface_from_handle, required_extensions};
-use winit::{dpi::PhysicalSize, window::Window};
-
-use super::renderers::triangle::TriangleRenderer;
-
-#[derive(Debug)]
-pub struct SendSyncWindowHandle {
-    pub window: RawWindowHandle,
-    pub display: RawDisplayHandle,
-}
 
-unsafe impl Send for SendSyncWindowHandle {}
-unsafe impl Sync for SendSyncWindowHandle {}
+use crate::{assets::manager::AssetManager, vfs::default_vfs};
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
-enum QueueKind {
-    Graphics,
-    Present,
-}
+use super::{
+    attachments::{
+        create_main_render_pass, main_clear_values, select_depth_stencil_format, select_samples,
+    },
+    camera::{mul, Camera},
+    color::OutputColorSpace,
+    config::RenderConfig,
+    device::{select_physical_device, SelectedDevice},
+    dump::{FrameDump, FrameDumpConfig},
+    frame::Frame,
+    graph::RenderGraph,
+    pipeline_cache::PersistentPipelineCache,
+    post::{PostProcessor, HDR_FORMAT},
+    renderer::{RenderFrame, Renderer, RendererCreateInfo, RendererId, RendererList},
+    renderers::{instanced::InstancedRenderer, sprite::SpriteRenderer, triangle::TriangleRenderer},
+    resolution::{Letterbox, ResolutionMode},
+    screenshot::{PendingCapture, Screenshot, ScreenshotCallback},
+    shader::ShaderManager,
+    target::{create_framebuffers, create_surface, OffscreenTarget, RenderTarget, SwapchainTarget},
+    text::{font::FontId, TextRenderer, TextStyle},
+    texture::{TextureAssetLoader, TextureLoader},
+    timing::{FrameStats, GpuTimer},
+    uniforms::FrameUniforms,
+    upload::{MemoryReport, UploadQueue},
+    validation::{create_messenger, ValidationState},
+};
 
-unsafe impl HasRawWindowHandle for SendSyncWindowHandle {
-    fn raw_window_handle(&self) -> RawWindowHandle {
-        self.window
-    }
-}
-
-unsafe impl HasRawDisplayHandle for SendSyncWindowHandle {
-    fn raw_display_handle(&self) -> RawDisplayHandle {
-        self.display
-    }
-}
+// format of offscreen targets, which have no surface to pick one from
+const OFFSCREEN_FORMAT: Format = Format::R8G8B8A8_SRGB;
+const STATS_LOG_INTERVAL: Duration = Duration::from_secs(5);
+// how often the pipeline cache is saved if it has grown, besides after shader reloads and resizes
+const PIPELINE_CACHE_SAVE_INTERVAL: Duration = Duration::from_secs(30);
 
 pub struct RenderContext {
     pub lib: Arc<VulkanLibrary>,
     pub instance: Arc<Instance>,
     pub debug_messenger: Option<DebugUtilsMessenger>,
-    pub surface: Arc<Surface<SendSyncWindowHandle>>,
+    pub validation: Arc<ValidationState>,
     pub phys_device: Arc<PhysicalDevice>,
     pub device: Arc<Device>,
     pub graphics_queue: Arc<Queue>,
-    pub present_queue: Arc<Queue>,
-    pub swapchain: Arc<Swapchain<SendSyncWindowHandle>>,
-    pub images: Vec<Arc<SwapchainImage<SendSyncWindowHandle>>>,
+    // a queue of a family without graphics, if the device has one
+    pub transfer_queue: Option<Arc<Queue>>,
+    pub target: RenderTarget,
     pub command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
-    pub main_command_buffer: StandardCommandBufferBuilderAlloc,
-    pub render_extent: Cell<PhysicalSize<u32>>,
+    pub descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    pub uploads: Arc<UploadQueue>,
+    // the logical size the scene is rendered at
+    pub render_extent: PhysicalSize<u32>,
+    resolution: ResolutionMode,
+    letterbox: Letterbox,
     pub render_pass: Arc<RenderPass>,
-    pub framebuffers: Vec<Arc<Framebuffer>>,
-    pub prev_frame_end: Option<Box<dyn GpuFuture>>,
+    pub samples: SampleCount,
+    pub depth_stencil_format: Option<Format>,
+    // one per frame in flight, rendering into the scene image of its post targets
+    pub scene_framebuffers: Vec<Arc<Framebuffer>>,
+    pub clear_color: [f32; 4],
+    // written to the frame uniforms at the start of every frame
+    pub camera: Camera,
+    pub frames: Vec<Frame>,
+    pub frame_index: usize,
+    screenshot_requests: Vec<ScreenshotCallback>,
+    frame_dump: Option<FrameDump>,
+    timer: GpuTimer,
+    stats: FrameStats,
+    last_frame_start: Option<Instant>,
+    stats_logged: Instant,
+    started: Instant,
 
-    pub triangle_renderer: TriangleRenderer,
+    pub graph: RenderGraph,
+    pub renderers: RendererList,
+    pub post: PostProcessor,
+    pub triangle_renderer: RendererId,
+    pub instanced_renderer: RendererId,
+    pub sprite_renderer: RendererId,
+    pub text_renderer: TextRenderer,
+    pub shaders: Arc<ShaderManager>,
+    pub pipeline_cache: PersistentPipelineCache,
+    // set when pipelines are likely to have been created, so the cache is saved after the frame
+    pipelines_changed: bool,
+    pipeline_cache_saved: Instant,
 }
 
 unsafe impl Send for RenderContext {}
 
 impl RenderContext {
-    pub fn new(window: &Window) -> anyhow::Result<Self> {
+    pub fn new(window: &Window, config: RenderConfig) -> anyhow::Result<Self> {
+        Self::create(Some(window), window.inner_size(), config)
+    }
+
+    // renders into images owned by the context instead of a window, works without a display
+    pub fn new_offscreen(extent: PhysicalSize<u32>, config: RenderConfig) -> anyhow::Result<Self> {
+        Self::create(None, extent, config)
+    }
+
+    fn create(
+        window: Option<&Window>,
+        extent: PhysicalSize<u32>,
+        config: RenderConfig,
+    ) -> anyhow::Result<Self> {
         let lib = VulkanLibrary::new()?;
-        let layers = ["VK_LAYER_KHRONOS_validation"];
-        let debug = cfg!(debug_assertions) && lib.supported_extensions().ext_debug_utils;
+        let layers = config.validation.available_layers(&lib)?;
+        let debug = !layers.is_empty() && lib.supported_extensions().ext_debug_utils;
+        if config.validation.strict && !debug {
+            log::warn!("Strict validation was requested, but no validation layer is available");
+        }
         let instance = Instance::new(lib.clone(), {
             let mut info = InstanceCreateInfo::application_from_cargo_toml();
-            let required_exts = required_extensions(&lib);
+            let required_exts = match window {
+                Some(_) => required_extensions(&lib),
+                None => InstanceExtensions::empty(),
+            };
             info.enabled_extensions = required_exts;
             info.enabled_extensions.ext_debug_utils = debug;
+            // needed for every colour space other than sRGB
+            info.enabled_extensions.ext_swapchain_colorspace = window.is_some()
+                && config.output_color_space != OutputColorSpace::Srgb
+                && lib.supported_extensions().ext_swapchain_colorspace;
             info.enumerate_portability = true;
-            info.enabled_layers = lib
-                .layer_properties()?
-                .map(|layer| String::from(layer.name().trim_end_matches('\0')))
-                .filter(|layer_name| layers.contains(&layer_name.as_str()))
-                .collect::<Vec<_>>();
+            info.enabled_layers = layers;
             info
         })?;
+        let validation = Arc::new(ValidationState::default());
         let debug_messenger = if debug {
-            unsafe {
-                Some(DebugUtilsMessenger::new(
-                    instance.clone(),
-                    DebugUtilsMessengerCreateInfo {
-                        message_severity: DebugUtilsMessageSeverity {
-                            error: true,
-                            warning: true,
-                            information: true,
-                            verbose: true,
-                            ..Default::default()
-                        },
-                        message_type: DebugUtilsMessageType {
-                            general: true,
-                            validation: true,
-                            performance: true,
-                            ..Default::default()
-                        },
-                        ..DebugUtilsMessengerCreateInfo::user_callback(Arc::new(|msg| {
-                            if msg.severity.error {
-                                log::error!(target: "vk", "{}", msg.description);
-                            } else if msg.severity.warning {
-                                log::warn!(target: "vk", "{}", msg.description);
-                            } else if msg.severity.information {
-                                log::info!(target: "vk", "{}", msg.description);
-                            } else if msg.severity.verbose {
-                                log::debug!(target: "vk", "{}", msg.description);
-                            }
-                        }))
-                    },
-                )?)
-            }
+            Some(create_messenger(
+                instance.clone(),
+                &config.validation,
+                validation.clone(),
+            )?)
         } else {
             None
         };
-        let window_handle = SendSyncWindowHandle {
-            window: window.raw_window_handle(),
-            display: window.raw_display_handle(),
-        };
-        let surface = create_surface_from_handle(window_handle, instance.clone())?;
+        let surface = window
+            .map(|window| create_surface(window, instance.clone()))
+            .transpose()?;
 
         let device_exts = DeviceExtensions {
-            khr_swapchain: true,
+            khr_swapchain: surface.is_some(),
             ..Default::default()
         };
 
-        let (phys_device, graphics_queue_index, present_queue_index) = instance
-            .enumerate_physical_devices()?
-            .filter(|pd| pd.supported_extensions().contains(&device_exts))
-            .filter_map(|pd| {
-                let graphics_queue_index = pd
-                    .queue_family_properties()
-                    .iter()
-                    .enumerate()
-                    .position(|(_, queue)| queue.queue_flags.graphics);
-                let present_queue_index =
-                    pd.queue_family_properties()
-                        .iter()
-                        .enumerate()
-                        .position(|(i, _)| {
-                            pd.surface_support(i.try_into().unwrap(), &surface)
-                                .unwrap_or(false)
-                        });
-                graphics_queue_index
-                    .and_then(|graphics| present_queue_index.map(|present| (pd, graphics, present)))
-            })
-            .max_by_key(|(pd, ..)| {
-                let mut score = 0;
-                match pd.properties().device_type {
-                    PhysicalDeviceType::DiscreteGpu => score += 4,
-                    PhysicalDeviceType::IntegratedGpu => score += 3,
-                    PhysicalDeviceType::VirtualGpu => score += 2,
-                    PhysicalDeviceType::Cpu => score += 1,
-                    _ => {}
-                }
-                score
-            })
-            .ok_or_else(|| anyhow::anyhow!("No suitable physical device found"))?;
+        let SelectedDevice {
+            phys_device,
+            graphics_queue_family,
+            present_queue_family,
+            transfer_queue_family,
+        } = select_physical_device(&instance, surface.as_deref(), &device_exts, &config.device)?;
 
-        let unique_queue_families = vec![graphics_queue_index, present_queue_index]
+        let unique_queue_families = vec![graphics_queue_family, present_queue_family]
             .drain(..)
             .collect::<HashSet<_>>();
+        // the transfer family is only ever a dedicated one, but one create info per family
+        // must hold either way
+        let mut queue_families = unique_queue_families.clone();
+        queue_families.extend(transfer_queue_family);
         let (device, queues) = Device::new(
             phys_device.clone(),
             DeviceCreateInfo {
-                queue_create_infos: unique_queue_families
+                queue_create_infos: queue_families
                     .iter()
                     .map(|index| QueueCreateInfo {
-                        queue_family_index: (*index).try_into().unwrap(),
+                        queue_family_index: *index,
                         ..Default::default()
                     })
                     .collect::<Vec<_>>(),
@@ -207,177 +204,453 @@ impl RenderContext {
         let queues = queues.collect::<Vec<_>>();
         let graphics_queue = queues
             .iter()
-            .find(|q| graphics_queue_index == q.queue_family_index().try_into().unwrap())
+            .find(|q| q.queue_family_index() == graphics_queue_family)
             .unwrap()
             .clone();
         let present_queue = queues
             .iter()
-            .find(|q| present_queue_index == q.queue_family_index().try_into().unwrap())
+            .find(|q| q.queue_family_index() == present_queue_family)
             .unwrap()
             .clone();
-
-        let (swapchain, images) = {
-            let surf_caps = device
-                .physical_device()
-                .surface_capabilities(&surface, Default::default())?;
-            let image_count = (surf_caps.min_image_count + 1).clamp(
-                surf_caps.min_image_count,
-                surf_caps.max_image_count.unwrap_or(u32::MAX),
-            );
-            let (format, color_space) = *phys_device
-                .surface_formats(&surface, Default::default())?
+        let transfer_queue = transfer_queue_family.map(|family| {
+            queues
                 .iter()
-                .max_by_key(|(fmt, cs)| {
-                    let mut score = 0;
-                    if *fmt == Format::R8G8B8A8_UNORM || *fmt == Format::B8G8R8A8_UNORM {
-                        score += 1;
-                    }
-                    if *cs == ColorSpace::SrgbNonLinear {
-                        score += 1;
-                    }
-                    score
-                })
-                .unwrap();
-            let extent = window.inner_size().into();
-            Swapchain::new(
+                .find(|q| q.queue_family_index() == family)
+                .unwrap()
+                .clone()
+        });
+        match transfer_queue_family {
+            Some(family) => log::info!("Using queue family {} for async uploads", family),
+            None => log::info!("No dedicated transfer queue, async uploads use the graphics queue"),
+        }
+
+        let target = match surface {
+            Some(surface) => RenderTarget::Swapchain(SwapchainTarget::new(
                 device.clone(),
-                surface.clone(),
-                SwapchainCreateInfo {
-                    image_color_space: color_space,
-                    image_format: Some(format),
-                    image_extent: extent,
-                    image_sharing: match unique_queue_families.len() {
-                        1 => Sharing::Exclusive,
-                        _ => Sharing::Concurrent(
-                            unique_queue_families
-                                .iter()
-                                .map(|i| u32::try_from(*i).unwrap())
-                                .collect(),
-                        ),
-                    },
-                    image_usage: ImageUsage {
-                        color_attachment: true,
-                        ..Default::default()
-                    },
-                    min_image_count: image_count,
-                    ..Default::default()
-                },
-            )?
+                surface,
+                present_queue,
+                &unique_queue_families.iter().copied().collect::<Vec<_>>(),
+                extent.into(),
+                config.output_color_space,
+            )?),
+            None => RenderTarget::Offscreen(OffscreenTarget::new(
+                device.clone(),
+                OFFSCREEN_FORMAT,
+                extent.into(),
+                config.frames_in_flight,
+            )?),
         };
 
         let command_buffer_allocator =
             Arc::new(StandardCommandBufferAllocator::new(device.clone()));
-        let main_command_buffer = command_buffer_allocator
-            .allocate(
-                graphics_queue_index.try_into().unwrap(),
-                CommandBufferLevel::Primary,
-                1,
-            )?
-            .next()
-            .unwrap();
+        let descriptor_set_allocator =
+            Arc::new(StandardDescriptorSetAllocator::new(device.clone()));
+        let uploads = Arc::new(UploadQueue::new(
+            graphics_queue.clone(),
+            transfer_queue.clone(),
+            command_buffer_allocator.clone(),
+        )?);
 
-        let render_pass = vulkano::single_pass_renderpass!(
-            device.clone(),
-            attachments: {
-                color: {
-                    load: Clear,
-                    store: Store,
-                    format: swapchain.image_format(),
-                    samples: 1,
-                }
-            },
-            pass: {
-                color: [color],
-                depth_stencil: {}
+        let depth_stencil_format = if config.depth_stencil {
+            let format = select_depth_stencil_format(&phys_device);
+            if format.is_none() {
+                log::warn!(
+                    "No supported depth/stencil format, rendering without depth and stencil"
+                );
             }
-        )?;
+            format
+        } else {
+            None
+        };
+        let samples = select_samples(
+            &phys_device,
+            config.samples,
+            HDR_FORMAT,
+            depth_stencil_format,
+        );
+        if samples as u32 != config.samples {
+            log::warn!(
+                "{} samples requested, using {} samples instead",
+                config.samples,
+                samples as u32
+            );
+        }
 
-        let framebuffers = Self::create_framebuffers(&images, &render_pass)?;
+        // the scene is rendered in linear HDR, the post processor writes it to the target
+        let render_pass =
+            create_main_render_pass(device.clone(), HDR_FORMAT, samples, depth_stencil_format)?;
 
-        let prev_frame_end = Some(vulkano::sync::now(device.clone()).boxed());
+        let frames = (0..config.frames_in_flight)
+            .map(|_| Frame::new(device.clone()))
+            .collect::<anyhow::Result<_>>()?;
+
+        let letterbox = Letterbox::new(config.resolution, extent);
+        let render_extent = letterbox.logical_size;
+        let vfs = match config.vfs {
+            Some(vfs) => vfs,
+            None => default_vfs()?,
+        };
+        let shaders = Arc::new(ShaderManager::new(
+            device.clone(),
+            vfs.clone(),
+            &config.shader_dir,
+        ));
+        let pipeline_cache =
+            PersistentPipelineCache::load(device.clone(), config.pipeline_cache_dir.as_deref())?;
+        let post = PostProcessor::new(
+            device.clone(),
+            shaders.clone(),
+            pipeline_cache.cache(),
+            &target,
+            &letterbox,
+            config.post,
+            config.paper_white,
+            config.frames_in_flight,
+        )?;
+        let scene_framebuffers = create_framebuffers(
+            &post.scene_images(),
+            &render_pass,
+            samples,
+            depth_stencil_format,
+        )?;
+        let renderer_info = RendererCreateInfo {
+            device: device.clone(),
+            queue: graphics_queue.clone(),
+            command_buffer_allocator: command_buffer_allocator.clone(),
+            descriptor_set_allocator: descriptor_set_allocator.clone(),
+            render_pass: render_pass.clone(),
+            pipeline_cache: pipeline_cache.cache(),
+            frames_in_flight: config.frames_in_flight,
+            extent: render_extent,
+            shaders: shaders.clone(),
+            uploads: uploads.clone(),
+        };
+        let mut renderers = RendererList::new();
+        let triangle_renderer = renderers.push(Box::new(TriangleRenderer::create(&renderer_info)?));
+        let instanced_renderer =
+            renderers.push(Box::new(InstancedRenderer::create(&renderer_info)?));
+        let sprite_renderer = renderers.push(Box::new(SpriteRenderer::create(&renderer_info)?));
 
         Ok(Self {
-            triangle_renderer: TriangleRenderer::new(device.clone(), render_pass.clone())?,
+            graph: RenderGraph::new(device.clone(), pipeline_cache.cache(), render_extent.into()),
+            renderers,
+            post,
+            triangle_renderer,
+            instanced_renderer,
+            sprite_renderer,
+            text_renderer: TextRenderer::new(vfs, uploads.clone()),
             lib,
             instance,
             debug_messenger,
-            surface,
+            validation,
             phys_device,
             device,
             graphics_queue,
-            present_queue,
-            swapchain,
-            images,
+            transfer_queue,
+            target,
             command_buffer_allocator,
-            main_command_buffer,
-            render_extent: Cell::new(window.inner_size()),
+            descriptor_set_allocator,
+            uploads,
+            render_extent,
+            resolution: config.resolution,
+            letterbox,
             render_pass,
-            framebuffers,
-            prev_frame_end,
+            samples,
+            depth_stencil_format,
+            scene_framebuffers,
+            clear_color: config.clear_color,
+            camera: Camera::default(),
+            frames,
+            frame_index: 0,
+            screenshot_requests: Vec::new(),
+            frame_dump: None,
+            timer: GpuTimer::new(&graphics_queue, config.frames_in_flight)?,
+            stats: FrameStats::default(),
+            last_frame_start: None,
+            stats_logged: Instant::now(),
+            started: Instant::now(),
+            shaders,
+            pipeline_cache,
+            pipelines_changed: true,
+            pipeline_cache_saved: Instant::now(),
         })
     }
 
-    fn create_framebuffers(
-        images: &[Arc<SwapchainImage<SendSyncWindowHandle>>],
-        render_pass: &Arc<RenderPass>,
-    ) -> anyhow::Result<Vec<Arc<Framebuffer>>> {
-        images
-            .iter()
-            .map(|img| ImageView::new_default(img.clone()).map_err(anyhow::Error::from))
-            .map(|iv| {
-                iv.and_then(|iv| {
-                    Framebuffer::new(
-                        render_pass.clone(),
-                        FramebufferCreateInfo {
-                            attachments: vec![iv],
-                            ..Default::default()
-                        },
-                    )
-                    .map_err(anyhow::Error::from)
-                })
-            })
-            .collect::<Result<Vec<Arc<Framebuffer>>, _>>()
-            .map_err(anyhow::Error::from)
+    pub fn texture_loader(&self) -> TextureLoader {
+        TextureLoader::new(
+            self.device.clone(),
+            self.graphics_queue.clone(),
+            self.command_buffer_allocator.clone(),
+        )
     }
 
-    pub fn wait_for_done(&mut self) {
-        self.prev_frame_end.as_mut().unwrap().cleanup_finished();
+    // registers the loaders of assets that live on the GPU, like `Texture`
+    pub fn register_asset_loaders(&self, assets: &AssetManager) {
+        assets.register(TextureAssetLoader::new(self.uploads.clone()));
     }
 
-    pub fn resize(&mut self, size: PhysicalSize<u32>) -> anyhow::Result<bool> {
-        let (new_swapchain, new_images) = match self.swapchain.recreate(SwapchainCreateInfo {
-            image_extent: size.into(),
-            ..self.swapchain.create_info()
-        }) {
-            Ok(r) => r,
-            Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return Ok(false),
-            Err(e) => Err(e)?,
-        };
+    pub fn renderer_create_info(&self) -> RendererCreateInfo {
+        RendererCreateInfo {
+            device: self.device.clone(),
+            queue: self.graphics_queue.clone(),
+            command_buffer_allocator: self.command_buffer_allocator.clone(),
+            descriptor_set_allocator: self.descriptor_set_allocator.clone(),
+            render_pass: self.render_pass.clone(),
+            pipeline_cache: self.pipeline_cache.cache(),
+            frames_in_flight: self.frames.len(),
+            extent: self.render_extent,
+            shaders: self.shaders.clone(),
+            uploads: self.uploads.clone(),
+        }
+    }
+
+    pub fn add_renderer<R: Renderer>(&mut self) -> anyhow::Result<RendererId> {
+        let renderer = R::create(&self.renderer_create_info())?;
+        Ok(self.renderers.push(Box::new(renderer)))
+    }
+
+    pub fn remove_renderer(&mut self, id: RendererId) -> Option<Box<dyn Renderer>> {
+        self.renderers.remove(id)
+    }
+
+    pub fn renderer_mut<R: Renderer>(&mut self, id: RendererId) -> Option<&mut R> {
+        self.renderers.get_mut(id)
+    }
+
+    pub fn draw_text(
+        &mut self,
+        font: FontId,
+        text: &str,
+        position: [f32; 2],
+        style: &TextStyle,
+    ) -> anyhow::Result<()> {
+        let sprites = self
+            .renderers
+            .get_mut::<SpriteRenderer>(self.sprite_renderer)
+            .ok_or_else(|| anyhow::anyhow!("The sprite renderer was removed"))?;
+        self.text_renderer
+            .draw_text(sprites, font, text, position, style)
+    }
+
+    pub fn current_frame(&mut self) -> &mut Frame {
+        &mut self.frames[self.frame_index]
+    }
+
+    fn previous_frame_end(&self) -> Box<dyn GpuFuture> {
+        let previous_index = (self.frame_index + self.frames.len() - 1) % self.frames.len();
+        match self.frames[previous_index].fence.clone() {
+            Some(fence) => fence.boxed(),
+            None => vulkano::sync::now(self.device.clone()).boxed(),
+        }
+    }
+
+    // the returned buffer is kept alive until the GPU is done with the current frame
+    pub fn transient_buffer<T, I>(
+        &mut self,
+        usage: BufferUsage,
+        data: I,
+    ) -> anyhow::Result<Arc<CpuAccessibleBuffer<[T]>>>
+    where
+        T: Pod + Send + Sync,
+        [T]: BufferContents,
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let buffer = CpuAccessibleBuffer::from_iter(self.device.clone(), usage, false, data)?;
+        self.current_frame()
+            .transient_buffers
+            .push(buffer.clone() as Arc<dyn BufferAccess>);
+        Ok(buffer)
+    }
 
-        self.swapchain = new_swapchain;
-        self.framebuffers = Self::create_framebuffers(&new_images, &self.render_pass)?;
-        self.render_extent.set(size);
+    pub fn resize(&mut self, size: PhysicalSize<u32>) -> anyhow::Result<bool> {
+        if !self.target.recreate(size.into())? {
+            return Ok(false);
+        }
+        self.letterbox = Letterbox::new(self.resolution, size);
+        self.resize_scene()?;
         Ok(true)
     }
 
+    pub fn set_resolution(&mut self, mode: ResolutionMode) -> anyhow::Result<()> {
+        self.resolution = mode;
+        self.letterbox = Letterbox::new(mode, self.letterbox.window_size);
+        self.resize_scene()
+    }
+
+    fn resize_scene(&mut self) -> anyhow::Result<()> {
+        self.post.resize(&self.target, &self.letterbox)?;
+        self.scene_framebuffers = create_framebuffers(
+            &self.post.scene_images(),
+            &self.render_pass,
+            self.samples,
+            self.depth_stencil_format,
+        )?;
+        self.render_extent = self.letterbox.logical_size;
+        self.graph.rebuild(self.render_extent.into());
+        self.pipelines_changed = true;
+        let renderer_info = self.renderer_create_info();
+        self.renderers.recreate(&renderer_info)
+    }
+
+    pub fn letterbox(&self) -> Letterbox {
+        self.letterbox
+    }
+
+    // converts a cursor position from a window event to logical coordinates, None on the bars
+    pub fn window_to_logical(&self, position: PhysicalPosition<f64>) -> Option<[f32; 2]> {
+        self.letterbox.window_to_logical(position)
+    }
+
+    pub fn logical_to_window(&self, position: [f32; 2]) -> PhysicalPosition<f64> {
+        self.letterbox.logical_to_window(position)
+    }
+
+    // the callback runs on the render thread once the next rendered frame has been read back
+    pub fn request_screenshot(&mut self, callback: impl FnOnce(Screenshot) + Send + 'static) {
+        self.screenshot_requests.push(Box::new(callback));
+    }
+
+    // renders a frame and waits for it to be read back. an out of date swapchain is recreated
+    // once, if still no frame could be rendered the request is dropped with an error
+    pub fn screenshot(&mut self) -> anyhow::Result<Screenshot> {
+        anyhow::ensure!(
+            self.target.supports_capture(),
+            "Frames of {:?} images cannot be captured",
+            self.target.image_format()
+        );
+        let (sender, receiver) = mpsc::channel();
+        let queued = self.screenshot_requests.len();
+        self.request_screenshot(move |screenshot| {
+            let _ = sender.send(screenshot);
+        });
+        let mut rendered = self.render().map(|_| ());
+        if rendered.is_ok() && self.screenshot_requests.len() > queued {
+            let size = self.letterbox.window_size;
+            rendered = self.resize(size).and_then(|_| self.render()).map(|_| ());
+        }
+        // a request left in the queue keeps the sender alive, so waiting for it would never end
+        if self.screenshot_requests.len() > queued {
+            self.screenshot_requests.truncate(queued);
+            rendered?;
+            anyhow::bail!("No frame could be rendered for the screenshot");
+        }
+        rendered?;
+        self.wait_idle()?;
+        receiver
+            .recv()
+            .map_err(|_| anyhow::anyhow!("The frame could not be captured"))
+    }
+
+    // from the next frame on, every frame is read back and written to a video stream, with frame
+    // times taken from the virtual clock of the dump
+    pub fn start_frame_dump(&mut self, config: FrameDumpConfig) -> anyhow::Result<()> {
+        self.stop_frame_dump()?;
+        anyhow::ensure!(
+            self.target.supports_capture(),
+            "Frames of {:?} images cannot be dumped",
+            self.target.image_format()
+        );
+        self.frame_dump = Some(FrameDump::new(config)?);
+        Ok(())
+    }
+
+    pub fn frame_dump(&self) -> Option<&FrameDump> {
+        self.frame_dump.as_ref()
+    }
+
+    pub fn stop_frame_dump(&mut self) -> anyhow::Result<()> {
+        if self.frame_dump.is_some() {
+            self.wait_idle()?;
+        }
+        match self.frame_dump.take() {
+            Some(mut dump) => dump.finish(),
+            None => Ok(()),
+        }
+    }
+
+    // waits for every frame in flight, delivering any pending screenshots
+    pub fn wait_idle(&mut self) -> anyhow::Result<()> {
+        for frame in &mut self.frames {
+            frame.wait()?;
+        }
+        self.check_validation()
+    }
+
+    // fails with the first validation error reported since the last check in strict mode
+    fn check_validation(&self) -> anyhow::Result<()> {
+        match self.validation.take_error() {
+            Some(message) => Err(anyhow::anyhow!("Vulkan validation error: {}", message)),
+            None => Ok(()),
+        }
+    }
+
+    // buffers and images created through `uploads`, and its staging memory
+    pub fn memory_report(&self) -> MemoryReport {
+        self.uploads.memory_report()
+    }
+
+    // GPU timings lag a few frames behind the CPU timings
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
     pub fn render(&mut self) -> anyhow::Result<bool> {
-        let (image_idx, mut recreate_swapchain, acquire_future) =
-            match acquire_next_image(self.swapchain.clone(), None) {
-                Ok(r) => r,
-                Err(AcquireError::OutOfDate) => return Ok(false),
-                Err(e) => Err(e)?,
-            };
+        let frame_start = Instant::now();
+        let changed_shaders = self.shaders.poll();
+        if !changed_shaders.is_empty() {
+            let renderer_info = self.renderer_create_info();
+            self.renderers
+                .shaders_changed(&renderer_info, &changed_shaders);
+            self.post.shaders_changed(&changed_shaders);
+            self.pipelines_changed = true;
+        }
+
+        self.current_frame().wait()?;
+        let (image_idx, recreate_swapchain, acquire_future) = match &self.target {
+            RenderTarget::Swapchain(target) => {
+                match acquire_next_image(target.swapchain.clone(), None) {
+                    Ok((index, suboptimal, future)) => (index as usize, suboptimal, Some(future)),
+                    Err(AcquireError::OutOfDate) => return Ok(false),
+                    Err(e) => Err(e)?,
+                }
+            }
+            RenderTarget::Offscreen(_) => (self.frame_index, false, None),
+        };
         let mut builder = AutoCommandBufferBuilder::primary(
             self.command_buffer_allocator.as_ref(),
             self.graphics_queue.queue_family_index(),
             CommandBufferUsage::OneTimeSubmit,
         )?;
+        let uniforms = self.frame_uniforms(frame_start);
+        if let Some(dump) = &self.frame_dump {
+            self.screenshot_requests.push(dump.capture());
+        }
+        *self.current_frame().uniforms.write()? = uniforms;
+        let frame = RenderFrame {
+            frame_index: self.frame_index,
+            extent: self.render_extent,
+            uniforms,
+            uniform_buffer: self.frames[self.frame_index].uniforms.clone(),
+            descriptor_set_allocator: self.descriptor_set_allocator.clone(),
+        };
+        self.timer.begin_frame(&mut builder, self.frame_index)?;
+        if self.uploads.has_pending() {
+            let uploads = &self.uploads;
+            self.timer
+                .scope(&mut builder, "uploads", |builder| uploads.record(builder))?;
+        }
+        self.graph.execute(&mut builder, &frame, &mut self.timer)?;
         builder
             .begin_render_pass(
                 RenderPassBeginInfo {
-                    clear_values: vec![Some([0.0, 0.0, 0.2, 1.0].into())],
+                    clear_values: main_clear_values(
+                        self.clear_color,
+                        self.samples,
+                        self.depth_stencil_format,
+                    ),
                     ..RenderPassBeginInfo::framebuffer(
-                        self.framebuffers[image_idx as usize].clone(),
+                        self.scene_framebuffers[self.frame_index].clone(),
                     )
                 },
                 SubpassContents::Inline,
@@ -387,39 +660,176 @@ impl RenderContext {
                 [Viewport {
                     origin: [0.0, 0.0],
                     dimensions: [
-                        self.render_extent.get().width as f32,
-                        self.render_extent.get().height as f32,
+                        self.render_extent.width as f32,
+                        self.render_extent.height as f32,
                     ],
-                    depth_range: -1.0..1.0,
+                    depth_range: 0.0..1.0,
                 }],
-            )
-            .bind_pipeline_graphics(self.triangle_renderer.pipeline.clone())
-            .bind_vertex_buffers(0, self.triangle_renderer.vertex_buffer.clone())
-            .draw(self.triangle_renderer.vertex_buffer.len() as u32, 1, 0, 0)?
-            .end_render_pass()?;
-        let command_buffer = builder.build()?;
-        let future = self
-            .prev_frame_end
-            .take()
-            .unwrap()
-            .join(acquire_future)
-            .then_execute(self.graphics_queue.clone(), command_buffer)?
-            .then_swapchain_present(
-                self.present_queue.clone(),
-                SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), image_idx),
-            )
-            .then_signal_fence_and_flush();
-        match future {
-            Ok(f) => self.prev_frame_end = Some(f.boxed()),
+            );
+        self.renderers
+            .render(&mut builder, &frame, &mut self.timer)?;
+        builder.end_render_pass()?;
+        self.post
+            .record(&mut builder, self.frame_index, image_idx, &mut self.timer)?;
+        let capture = self.record_capture(&mut builder, image_idx)?;
+        let command_buffer = Arc::new(builder.build()?);
+
+        // the frame waits on the GPU for async uploads started before it was submitted
+        let mut previous_end = self.previous_frame_end();
+        let mut uploads_ready = Vec::new();
+        let mut ownership_transfers = Vec::new();
+        for upload in self.uploads.take_submitted() {
+            previous_end = previous_end.join(upload.future).boxed();
+            uploads_ready.push(upload.ready);
+            if let Some(mut transfer) = upload.ownership_transfer {
+                transfer.acquire(&self.graphics_queue)?;
+                ownership_transfers.push(transfer);
+            }
+        }
+        let future = match (&self.target, acquire_future) {
+            (RenderTarget::Swapchain(target), Some(acquire_future)) => previous_end
+                .join(acquire_future)
+                .then_execute(self.graphics_queue.clone(), command_buffer.clone())?
+                .then_swapchain_present(
+                    target.present_queue.clone(),
+                    SwapchainPresentInfo::swapchain_image_index(
+                        target.swapchain.clone(),
+                        image_idx as u32,
+                    ),
+                )
+                .boxed(),
+            _ => previous_end
+                .then_execute(self.graphics_queue.clone(), command_buffer.clone())?
+                .boxed(),
+        }
+        .then_signal_fence_and_flush();
+        let frame = self.current_frame();
+        frame.command_buffer = Some(command_buffer);
+        frame.capture = capture;
+        frame.uploads_ready = uploads_ready;
+        frame.ownership_transfers = ownership_transfers;
+        let result = match future {
+            Ok(f) => {
+                frame.fence = Some(Arc::new(f));
+                Ok(recreate_swapchain)
+            }
             Err(FlushError::OutOfDate) => {
-                recreate_swapchain = true;
-                self.prev_frame_end = Some(vulkano::sync::now(self.device.clone()).boxed())
+                frame.fence = None;
+                Ok(true)
             }
             Err(e) => {
-                self.prev_frame_end = Some(vulkano::sync::now(self.device.clone()).boxed());
-                Err(e)?
+                frame.fence = None;
+                Err(e.into())
             }
+        };
+        // a frame that was not submitted is not read back either, so the clock waits for it
+        let submitted = self.current_frame().fence.is_some();
+        if let Some(dump) = self.frame_dump.as_mut().filter(|_| submitted) {
+            dump.advance();
+        }
+        self.frame_index = (self.frame_index + 1) % self.frames.len();
+        self.update_stats(frame_start);
+        self.save_pipeline_cache();
+        self.check_validation()?;
+        result
+    }
+
+    // the context is not dropped when the app exits, so the cache is saved while rendering: after
+    // the frames in which pipelines were built, and now and then for pipelines created elsewhere
+    fn save_pipeline_cache(&mut self) {
+        if !self.pipelines_changed
+            && self.pipeline_cache_saved.elapsed() < PIPELINE_CACHE_SAVE_INTERVAL
+        {
+            return;
+        }
+        self.pipelines_changed = false;
+        self.pipeline_cache_saved = Instant::now();
+        if let Err(e) = self.pipeline_cache.save() {
+            log::warn!("Failed to save pipeline cache: {:?}", e);
+        }
+    }
+
+    fn frame_uniforms(&self, frame_start: Instant) -> FrameUniforms {
+        let (time, delta_time) = match &self.frame_dump {
+            Some(dump) => (
+                dump.clock().elapsed().as_secs_f32(),
+                dump.frame_duration().as_secs_f32(),
+            ),
+            None => (
+                (frame_start - self.started).as_secs_f32(),
+                self.last_frame_start
+                    .map_or(0.0, |last| (frame_start - last).as_secs_f32()),
+            ),
+        };
+        let view = self.camera.view();
+        let projection = self.camera.projection(self.render_extent);
+        FrameUniforms {
+            view,
+            projection,
+            view_projection: mul(&projection, &view),
+            resolution: [
+                self.render_extent.width as f32,
+                self.render_extent.height as f32,
+            ],
+            time,
+            delta_time,
+        }
+    }
+
+    fn update_stats(&mut self, frame_start: Instant) {
+        self.stats.cpu_time = frame_start.elapsed();
+        if let Some(last_frame_start) = self.last_frame_start {
+            self.stats.frame_time = frame_start - last_frame_start;
+        }
+        self.last_frame_start = Some(frame_start);
+        self.stats.gpu_passes = self.timer.timings().to_vec();
+        let (errors, warnings) = self.validation.take_counts();
+        self.stats.validation_errors = errors;
+        self.stats.validation_warnings = warnings;
+        if self.stats_logged.elapsed() >= STATS_LOG_INTERVAL {
+            self.stats_logged = Instant::now();
+            log::debug!("{}", self.stats);
+            log::debug!("{}", self.memory_report());
+        }
+    }
+
+    // copies the rendered image into a host buffer if a screenshot was requested
+    fn record_capture(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_idx: usize,
+    ) -> anyhow::Result<Option<PendingCapture>> {
+        if self.screenshot_requests.is_empty() {
+            return Ok(None);
         }
-        Ok(recreate_swapchain)
+        if !self.target.supports_capture() {
+            log::error!(
+                "Frames of {:?} images cannot be captured, dropping screenshot requests",
+                self.target.image_format()
+            );
+            self.screenshot_requests.clear();
+            return Ok(None);
+        }
+        let image = self.target.image(image_idx);
+        let format = image.format();
+        let extent = image.dimensions().width_height();
+        let size =
+            extent[0] as usize * extent[1] as usize * format.block_size().unwrap_or(4) as usize;
+        let buffer = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage {
+                transfer_dst: true,
+                ..BufferUsage::empty()
+            },
+            true,
+            (0..size).map(|_| 0u8),
+        )?;
+        builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buffer.clone()))?;
+        Ok(Some(PendingCapture {
+            buffer,
+            format,
+            extent,
+            callbacks: self.screenshot_requests.drain(..).collect(),
+        }))
     }
 }
@@ -0,0 +1,341 @@
+use std::{fmt, fmt::Write, sync::Arc};
+
+use anyhow::Context;
+
+use vulkano::{
+    device::{
+        physical::{PhysicalDevice, PhysicalDeviceType},
+        DeviceExtensions,
+    },
+    instance::{Instance, InstanceCreateInfo},
+    swapchain::Surface,
+    VulkanLibrary,
+};
+use vulkano_win::required_extensions;
+use winit::window::Window;
+
+use super::target::{create_surface, SendSyncWindowHandle};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector {
+    // the highest scoring device, preferring discrete GPUs
+    Auto,
+    Index(usize),
+    Vendor(u32),
+    // case-insensitive substring of the device name
+    Name(String),
+    // a CPU implementation such as lavapipe, whose output does not depend on the GPU
+    Cpu,
+}
+
+impl DeviceSelector {
+    // "auto", "cpu", "index:1", "vendor:0x10de" or "name:geforce", a bare name selects by name
+    // too. bare numbers are rejected, they could mean an index as well as a vendor id
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        if let Some(index) = value.strip_prefix("index:") {
+            let index = index
+                .parse()
+                .with_context(|| format!("Invalid device index {}", index))?;
+            return Ok(Self::Index(index));
+        }
+        if let Some(vendor) = value.strip_prefix("vendor:") {
+            let id = match vendor.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => vendor.parse(),
+            };
+            let id = id.with_context(|| format!("Invalid vendor id {}", vendor))?;
+            return Ok(Self::Vendor(id));
+        }
+        if let Some(name) = value.strip_prefix("name:") {
+            return Ok(Self::Name(name.to_lowercase()));
+        }
+        match value {
+            "" | "auto" => Ok(Self::Auto),
+            "cpu" => Ok(Self::Cpu),
+            _ if value.starts_with("0x") || value.parse::<u64>().is_ok() => Err(anyhow::anyhow!(
+                "Ambiguous device selector {}, use index:{} or vendor:{}",
+                value,
+                value,
+                value
+            )),
+            _ => Ok(Self::Name(value.to_lowercase())),
+        }
+    }
+
+    // the value of `--gpu <selector>` or `--gpu=<selector>`
+    pub fn from_args() -> anyhow::Result<Option<Self>> {
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--gpu" {
+                return args.next().map(|value| Self::parse(&value)).transpose();
+            }
+            if let Some(value) = arg.strip_prefix("--gpu=") {
+                return Self::parse(value).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn matches(&self, index: usize, phys_device: &PhysicalDevice) -> bool {
+        let properties = phys_device.properties();
+        match self {
+            Self::Auto => true,
+            Self::Index(i) => *i == index,
+            Self::Vendor(vendor) => *vendor == properties.vendor_id,
+            Self::Name(name) => properties.device_name.to_lowercase().contains(name),
+            Self::Cpu => properties.device_type == PhysicalDeviceType::Cpu,
+        }
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Index(index) => write!(f, "index {}", index),
+            Self::Vendor(vendor) => write!(f, "vendor 0x{:04x}", vendor),
+            Self::Name(name) => write!(f, "name \"{}\"", name),
+            Self::Cpu => write!(f, "cpu"),
+        }
+    }
+}
+
+pub struct SelectedDevice {
+    pub phys_device: Arc<PhysicalDevice>,
+    pub graphics_queue_family: u32,
+    pub present_queue_family: u32,
+    // a family that can transfer but not draw, so copies run alongside rendering
+    pub transfer_queue_family: Option<u32>,
+}
+
+// logs every enumerated device with the reason it was selected or rejected
+pub fn select_physical_device(
+    instance: &Arc<Instance>,
+    surface: Option<&Surface<SendSyncWindowHandle>>,
+    extensions: &DeviceExtensions,
+    selector: &DeviceSelector,
+) -> anyhow::Result<SelectedDevice> {
+    let candidates = instance
+        .enumerate_physical_devices()?
+        .enumerate()
+        .map(|(index, pd)| {
+            let result = evaluate(index, &pd, surface, extensions, selector);
+            (pd, result)
+        })
+        .collect::<Vec<_>>();
+    let selected = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, result))| result.is_ok())
+        .max_by_key(|(_, (pd, _))| score(pd))
+        .map(|(index, _)| index);
+
+    log::info!("Physical devices (selector: {}):", selector);
+    for (index, (pd, result)) in candidates.iter().enumerate() {
+        let status = match result {
+            _ if Some(index) == selected => "selected".to_owned(),
+            Ok(_) => "usable, but scored lower".to_owned(),
+            Err(reason) => format!("rejected, {}", reason),
+        };
+        log::info!("  [{}] {}: {}", index, describe(pd), status);
+    }
+
+    match selected {
+        Some(index) => Ok(candidates.into_iter().nth(index).unwrap().1.unwrap()),
+        None if candidates.is_empty() => Err(anyhow::anyhow!(
+            "No suitable physical device found, the Vulkan implementation reported no devices"
+        )),
+        None => Err(anyhow::anyhow!(
+            "No suitable physical device found for selector {}, see the device list above",
+            selector
+        )),
+    }
+}
+
+fn evaluate(
+    index: usize,
+    pd: &Arc<PhysicalDevice>,
+    surface: Option<&Surface<SendSyncWindowHandle>>,
+    extensions: &DeviceExtensions,
+    selector: &DeviceSelector,
+) -> Result<SelectedDevice, String> {
+    if !selector.matches(index, pd) {
+        return Err(format!("does not match {}", selector));
+    }
+    if !pd.supported_extensions().contains(extensions) {
+        return Err(format!(
+            "missing extensions {:?}",
+            extensions.difference(pd.supported_extensions())
+        ));
+    }
+    let families = pd.queue_family_properties();
+    let graphics_queue_family = families
+        .iter()
+        .position(|family| family.queue_flags.graphics)
+        .map(|i| i as u32)
+        .ok_or_else(|| "no graphics queue family".to_owned())?;
+    let present_queue_family = match surface {
+        Some(surface) => (0..families.len() as u32)
+            .find(|&i| pd.surface_support(i, surface).unwrap_or(false))
+            .ok_or_else(|| "no queue family can present to the window".to_owned())?,
+        None => graphics_queue_family,
+    };
+    // prefer a family made for transfers only, usually backed by a DMA engine, over one that
+    // also does compute
+    let transfer_queue_family = families
+        .iter()
+        .enumerate()
+        .filter(|(_, family)| family.queue_flags.transfer && !family.queue_flags.graphics)
+        .min_by_key(|(_, family)| family.queue_flags.compute)
+        .map(|(i, _)| i as u32);
+    Ok(SelectedDevice {
+        phys_device: pd.clone(),
+        graphics_queue_family,
+        present_queue_family,
+        transfer_queue_family,
+    })
+}
+
+fn score(pd: &PhysicalDevice) -> u32 {
+    match pd.properties().device_type {
+        PhysicalDeviceType::DiscreteGpu => 4,
+        PhysicalDeviceType::IntegratedGpu => 3,
+        PhysicalDeviceType::VirtualGpu => 2,
+        PhysicalDeviceType::Cpu => 1,
+        _ => 0,
+    }
+}
+
+fn describe(pd: &PhysicalDevice) -> String {
+    let properties = pd.properties();
+    format!(
+        "{} ({:?}, {:04x}:{:04x}, Vulkan {})",
+        properties.device_name,
+        properties.device_type,
+        properties.vendor_id,
+        properties.device_id,
+        pd.api_version()
+    )
+}
+
+// a human readable report of every device, with the queue families, surface formats and present
+// modes available for `window`
+pub fn device_report(window: &Window) -> anyhow::Result<String> {
+    let lib = VulkanLibrary::new()?;
+    let instance = Instance::new(
+        lib.clone(),
+        InstanceCreateInfo {
+            enabled_extensions: required_extensions(&lib),
+            enumerate_portability: true,
+            ..InstanceCreateInfo::application_from_cargo_toml()
+        },
+    )?;
+    let surface = create_surface(window, instance.clone())?;
+
+    let mut report = String::new();
+    for (index, pd) in instance.enumerate_physical_devices()?.enumerate() {
+        let properties = pd.properties();
+        writeln!(report, "[{}] {}", index, describe(&pd))?;
+        writeln!(
+            report,
+            "    driver {} ({})",
+            properties.driver_version,
+            properties
+                .driver_info
+                .as_deref()
+                .unwrap_or("no driver info")
+        )?;
+
+        writeln!(report, "    memory heaps:")?;
+        for heap in &pd.memory_properties().memory_heaps {
+            writeln!(
+                report,
+                "      {} MiB{}",
+                heap.size / (1024 * 1024),
+                if heap.flags.device_local {
+                    ", device local"
+                } else {
+                    ""
+                }
+            )?;
+        }
+
+        writeln!(report, "    queue families:")?;
+        for (i, family) in pd.queue_family_properties().iter().enumerate() {
+            let flags = &family.queue_flags;
+            let names = [
+                (flags.graphics, "graphics"),
+                (flags.compute, "compute"),
+                (flags.transfer, "transfer"),
+                (flags.sparse_binding, "sparse binding"),
+            ]
+            .iter()
+            .filter(|(supported, _)| *supported)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+            writeln!(
+                report,
+                "      [{}] {} queues: {}; timestamp bits {}; present {}",
+                i,
+                family.queue_count,
+                names.join(", "),
+                family.timestamp_valid_bits.unwrap_or(0),
+                if pd.surface_support(i as u32, &surface).unwrap_or(false) {
+                    "yes"
+                } else {
+                    "no"
+                }
+            )?;
+        }
+
+        match pd.surface_formats(&surface, Default::default()) {
+            Ok(formats) => {
+                writeln!(report, "    surface formats:")?;
+                for (format, color_space) in formats {
+                    writeln!(report, "      {:?} {:?}", format, color_space)?;
+                }
+            }
+            Err(e) => writeln!(report, "    surface formats unavailable: {}", e)?,
+        }
+        match pd.surface_present_modes(&surface) {
+            Ok(modes) => writeln!(
+                report,
+                "    present modes: {}",
+                modes
+                    .map(|mode| format!("{:?}", mode))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?,
+            Err(e) => writeln!(report, "    present modes unavailable: {}", e)?,
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeviceSelector;
+
+    #[test]
+    fn parse_selectors() {
+        let parse = |value| DeviceSelector::parse(value).unwrap();
+        assert_eq!(parse("auto"), DeviceSelector::Auto);
+        assert_eq!(parse("cpu"), DeviceSelector::Cpu);
+        assert_eq!(parse("index:1"), DeviceSelector::Index(1));
+        assert_eq!(parse("vendor:0x10de"), DeviceSelector::Vendor(0x10de));
+        assert_eq!(parse("vendor:4318"), DeviceSelector::Vendor(0x10de));
+        assert_eq!(parse("name:0x"), DeviceSelector::Name("0x".into()));
+        assert_eq!(parse("GeForce"), DeviceSelector::Name("geforce".into()));
+    }
+
+    #[test]
+    fn reject_bare_numbers() {
+        for value in ["1", "4318", "0x10de", "index:x", "vendor:0xzz"] {
+            assert!(
+                DeviceSelector::parse(value).is_err(),
+                "{} was accepted",
+                value
+            );
+        }
+    }
+}
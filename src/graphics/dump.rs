@@ -0,0 +1,445 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::utils::clock::VirtualClock;
+
+use super::screenshot::{Screenshot, ScreenshotCallback};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoFormat {
+    // 8-bit 4:4:4 BT.601, playable as is by ffmpeg and mpv
+    Y4m,
+    // headerless RGBA8 frames, e.g. ffmpeg -f rawvideo -pix_fmt rgba -s WxH -r FPS -i dump.rgba
+    RawRgba,
+}
+
+pub struct FrameDumpConfig {
+    pub(crate) video_path: PathBuf,
+    pub(crate) audio_path: Option<PathBuf>,
+    pub(crate) format: VideoFormat,
+    pub(crate) fps: u32,
+    pub(crate) sample_rate: u32,
+    pub(crate) channels: u16,
+}
+
+impl FrameDumpConfig {
+    // the format follows the extension, anything but .y4m is written as raw RGBA
+    pub fn new(video_path: impl Into<PathBuf>) -> Self {
+        let video_path = video_path.into();
+        let format = match video_path.extension().and_then(|ext| ext.to_str()) {
+            Some("y4m") => VideoFormat::Y4m,
+            _ => VideoFormat::RawRgba,
+        };
+        Self {
+            audio_path: Some(video_path.with_extension("wav")),
+            video_path,
+            format,
+            fps: 60,
+            sample_rate: 48000,
+            channels: 2,
+        }
+    }
+
+    // --dump <video path>, with the audio next to it
+    pub fn from_args() -> Option<Self> {
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--dump" {
+                return args.next().map(Self::new);
+            }
+            if let Some(value) = arg.strip_prefix("--dump=") {
+                return Some(Self::new(value));
+            }
+        }
+        None
+    }
+
+    pub fn format(mut self, format: VideoFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.fps = fps.max(1);
+        self
+    }
+
+    // `None` skips the WAV file
+    pub fn audio(mut self, path: Option<PathBuf>) -> Self {
+        self.audio_path = path;
+        self
+    }
+
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = sample_rate.max(1);
+        self
+    }
+
+    pub fn channels(mut self, channels: u16) -> Self {
+        self.channels = channels.max(1);
+        self
+    }
+}
+
+enum DumpMsg {
+    Frame(Screenshot),
+    Audio(Vec<f32>),
+    Finish,
+}
+
+// feeds interleaved samples in -1..1 into the WAV file of a frame dump, from any thread. every
+// video frame takes exactly 1/fps seconds of the samples written so far, missing samples are
+// filled with silence so the audio never drifts from the video
+#[derive(Clone)]
+pub struct AudioSink {
+    sender: Sender<DumpMsg>,
+    clock: VirtualClock,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl AudioSink {
+    pub fn write(&self, samples: &[f32]) {
+        let _ = self.sender.send(DumpMsg::Audio(samples.to_vec()));
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    // the samples per channel that belong to the video up to the current virtual time
+    pub fn sample_frames_due(&self) -> u64 {
+        (self.clock.elapsed().as_nanos() * self.sample_rate as u128 / 1_000_000_000) as u64
+    }
+}
+
+// records every rendered frame at a fixed frame rate. frames are stamped with the time of a
+// virtual clock that advances by exactly one frame per rendered frame, so animations come out
+// smooth however long each frame took to render. frames are read back and written on a
+// background thread, the files stay playable if the process exits without finishing the dump
+pub struct FrameDump {
+    clock: VirtualClock,
+    step: Duration,
+    frames: u64,
+    // sample rate and channels of the WAV file, if there is one
+    audio_format: Option<(u32, u16)>,
+    sender: Sender<DumpMsg>,
+    writer: Option<JoinHandle<anyhow::Result<()>>>,
+}
+
+impl FrameDump {
+    pub fn new(config: FrameDumpConfig) -> anyhow::Result<Self> {
+        let video = create_file(&config.video_path)?;
+        let audio = match &config.audio_path {
+            Some(path) => Some(WavWriter::new(
+                create_file(path)?,
+                config.sample_rate,
+                config.channels,
+            )?),
+            None => None,
+        };
+        log::info!(
+            "Dumping frames to {} at {} fps",
+            config.video_path.display(),
+            config.fps
+        );
+        let (sender, receiver) = mpsc::channel();
+        let writer = DumpWriter {
+            video,
+            format: config.format,
+            fps: config.fps,
+            extent: None,
+            frames: 0,
+            audio,
+            pending_audio: VecDeque::new(),
+            sample_rate: config.sample_rate,
+            channels: config.channels,
+        };
+        let writer = thread::Builder::new()
+            .name("frame dump".into())
+            .spawn(move || writer.run(receiver))?;
+        Ok(Self {
+            clock: VirtualClock::default(),
+            step: Duration::from_secs_f64(1.0 / config.fps as f64),
+            frames: 0,
+            audio_format: config
+                .audio_path
+                .as_ref()
+                .map(|_| (config.sample_rate, config.channels)),
+            sender,
+            writer: Some(writer),
+        })
+    }
+
+    // the time the next frame shows. the game loops are paced by this clock instead of the wall
+    // clock while dumping, see `GameLoopManager::new`
+    pub fn clock(&self) -> VirtualClock {
+        self.clock.clone()
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        self.step
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // None if the dump has no WAV file
+    pub fn audio_sink(&self) -> Option<AudioSink> {
+        let (sample_rate, channels) = self.audio_format?;
+        Some(AudioSink {
+            sender: self.sender.clone(),
+            clock: self.clock.clone(),
+            sample_rate,
+            channels,
+        })
+    }
+
+    // receives the read back frame, frames are read back in the order they were rendered
+    pub(crate) fn capture(&self) -> ScreenshotCallback {
+        let sender = self.sender.clone();
+        Box::new(move |screenshot| {
+            let _ = sender.send(DumpMsg::Frame(screenshot));
+        })
+    }
+
+    pub(crate) fn advance(&mut self) {
+        self.frames += 1;
+        self.clock.advance(self.step);
+    }
+
+    // waits for every frame sent so far to be written. frames still in flight on the GPU are
+    // lost, `RenderContext::stop_frame_dump` waits for them first
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        match self.writer.take() {
+            Some(writer) => {
+                let _ = self.sender.send(DumpMsg::Finish);
+                writer
+                    .join()
+                    .map_err(|_| anyhow::anyhow!("The frame dump thread panicked"))?
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for FrameDump {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("Failed to finish the frame dump: {:?}", e);
+        }
+    }
+}
+
+fn create_file(path: &Path) -> anyhow::Result<BufWriter<File>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Unable to create {}", dir.display()))?;
+    }
+    let file =
+        File::create(path).with_context(|| format!("Unable to create {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+struct DumpWriter {
+    video: BufWriter<File>,
+    format: VideoFormat,
+    fps: u32,
+    // set by the first frame, a stream cannot change its size
+    extent: Option<[u32; 2]>,
+    frames: u64,
+    audio: Option<WavWriter<BufWriter<File>>>,
+    pending_audio: VecDeque<f32>,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl DumpWriter {
+    fn run(mut self, receiver: Receiver<DumpMsg>) -> anyhow::Result<()> {
+        while let Ok(msg) = receiver.recv() {
+            match msg {
+                DumpMsg::Frame(frame) => self.write_frame(&frame)?,
+                DumpMsg::Audio(samples) => {
+                    if self.audio.is_some() {
+                        self.pending_audio.extend(samples)
+                    }
+                }
+                DumpMsg::Finish => break,
+            }
+        }
+        log::info!("Dumped {} frames", self.frames);
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &Screenshot) -> anyhow::Result<()> {
+        let extent = [frame.width, frame.height];
+        match self.extent {
+            None => {
+                self.extent = Some(extent);
+                if self.format == VideoFormat::Y4m {
+                    writeln!(
+                        self.video,
+                        "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                        extent[0], extent[1], self.fps
+                    )?;
+                }
+            }
+            Some(first) if first != extent => {
+                log::warn!(
+                    "Skipping a {}x{} frame in a {}x{} dump, use a fixed resolution to dump \
+                     resizable windows",
+                    extent[0],
+                    extent[1],
+                    first[0],
+                    first[1]
+                );
+                return Ok(());
+            }
+            Some(_) => {}
+        }
+
+        match self.format {
+            VideoFormat::Y4m => {
+                self.video.write_all(b"FRAME\n")?;
+                self.video.write_all(&rgba_to_yuv444(&frame.pixels))?;
+            }
+            VideoFormat::RawRgba => self.video.write_all(&frame.pixels)?,
+        }
+        self.video.flush()?;
+        self.frames += 1;
+
+        if let Some(audio) = &mut self.audio {
+            // the sample frames that belong to the video so far, rounded so they add up exactly
+            let total = self.frames * self.sample_rate as u64 / self.fps as u64;
+            let missing = total.saturating_sub(audio.sample_frames()) as usize;
+            let samples = missing * self.channels as usize;
+            let available = samples.min(self.pending_audio.len());
+            let mut chunk: Vec<f32> = self.pending_audio.drain(..available).collect();
+            chunk.resize(samples, 0.0);
+            audio.write(&chunk)?;
+            audio.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// BT.601 limited range, which is what players assume for Y4M without a colour range tag
+fn rgba_to_yuv444(pixels: &[u8]) -> Vec<u8> {
+    let count = pixels.len() / 4;
+    let mut planes = vec![0; count * 3];
+    let (y, uv) = planes.split_at_mut(count);
+    let (u, v) = uv.split_at_mut(count);
+    for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+        let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|c| c as f32 / 255.0);
+        y[i] = (16.0 + 65.481 * r + 128.553 * g + 24.966 * b).round() as u8;
+        u[i] = (128.0 - 37.797 * r - 74.203 * g + 112.0 * b).round() as u8;
+        v[i] = (128.0 + 112.0 * r - 93.786 * g - 18.214 * b).round() as u8;
+    }
+    planes
+}
+
+// 16-bit PCM. the sizes in the header are rewritten on every flush, so the file is valid
+// without an explicit finish
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    channels: u16,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32, channels: u16) -> anyhow::Result<Self> {
+        let block_align = channels * 2;
+        out.write_all(b"RIFF")?;
+        out.write_all(&36u32.to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&channels.to_le_bytes())?;
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        out.write_all(&block_align.to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            out,
+            channels,
+            data_size: 0,
+        })
+    }
+
+    // interleaved samples in -1..1
+    pub fn write(&mut self, samples: &[f32]) -> anyhow::Result<()> {
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            self.out.write_all(&value.to_le_bytes())?;
+        }
+        self.data_size += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    // samples per channel written so far
+    pub fn sample_frames(&self) -> u64 {
+        self.data_size as u64 / (self.channels as u64 * 2)
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        let end = self.out.stream_position()?;
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + self.data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&self.data_size.to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(end))?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::testing::{offscreen_context, root_dir};
+
+    use super::*;
+
+    // three frames at 30 fps, each taking 1/30 s of virtual time and of audio, silence is padded
+    // in where nothing wrote samples
+    #[test]
+    #[ignore]
+    fn dump_frames_and_audio() {
+        let path = root_dir().join("target/tests/frame_dump.y4m");
+        let mut ctx = offscreen_context(|config| config);
+        ctx.start_frame_dump(FrameDumpConfig::new(&path).fps(30))
+            .unwrap();
+        for _ in 0..3 {
+            ctx.render().unwrap();
+        }
+        let dump = ctx.frame_dump().unwrap();
+        assert_eq!(dump.frames(), 3);
+        assert_eq!(dump.clock().elapsed(), dump.frame_duration() * 3);
+        ctx.stop_frame_dump().unwrap();
+
+        let header = "YUV4MPEG2 W128 H96 F30:1 Ip A1:1 C444\n";
+        let video = fs::read(&path).unwrap();
+        assert!(video.starts_with(header.as_bytes()));
+        assert_eq!(
+            video.len(),
+            header.len() + 3 * ("FRAME\n".len() + 128 * 96 * 3)
+        );
+        let audio = fs::metadata(path.with_extension("wav")).unwrap();
+        assert_eq!(audio.len(), 44 + 4800 * 2 * 2);
+    }
+}
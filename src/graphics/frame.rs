@@ -0,0 +1,69 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use vulkano::{
+    buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer},
+    command_buffer::PrimaryAutoCommandBuffer,
+    device::Device,
+    sync::{FenceSignalFuture, GpuFuture},
+};
+
+use super::{ownership::OwnershipTransfer, screenshot::PendingCapture, uniforms::FrameUniforms};
+
+pub type FrameFence = Arc<FenceSignalFuture<Box<dyn GpuFuture>>>;
+
+// resources owned by one frame in flight, only touched again after its fence is signaled
+pub struct Frame {
+    pub(crate) fence: Option<FrameFence>,
+    pub(crate) command_buffer: Option<Arc<PrimaryAutoCommandBuffer>>,
+    pub(crate) transient_buffers: Vec<Arc<dyn BufferAccess>>,
+    pub(crate) capture: Option<PendingCapture>,
+    pub(crate) uniforms: Arc<CpuAccessibleBuffer<FrameUniforms>>,
+    // async uploads the frame waited for, complete once it has finished
+    pub(crate) uploads_ready: Vec<Arc<AtomicBool>>,
+    // acquire barriers of those uploads, submitted just before the frame
+    pub(crate) ownership_transfers: Vec<OwnershipTransfer>,
+}
+
+impl Frame {
+    pub fn new(device: Arc<Device>) -> anyhow::Result<Self> {
+        Ok(Self {
+            fence: None,
+            command_buffer: None,
+            transient_buffers: Vec::new(),
+            capture: None,
+            uploads_ready: Vec::new(),
+            ownership_transfers: Vec::new(),
+            uniforms: CpuAccessibleBuffer::from_data(
+                device,
+                BufferUsage {
+                    uniform_buffer: true,
+                    ..BufferUsage::empty()
+                },
+                false,
+                FrameUniforms::default(),
+            )?,
+        })
+    }
+
+    pub fn wait(&mut self) -> anyhow::Result<()> {
+        if let Some(fence) = &self.fence {
+            fence.wait(None)?;
+        }
+        self.command_buffer = None;
+        self.transient_buffers.clear();
+        self.ownership_transfers.clear();
+        for ready in self.uploads_ready.drain(..) {
+            ready.store(true, Ordering::Release);
+        }
+        // the copy never ran if the frame failed to submit
+        if let Some(capture) = self.capture.take().filter(|_| self.fence.is_some()) {
+            if let Err(e) = capture.finish() {
+                log::error!("Failed to capture screenshot: {:?}", e);
+            }
+        }
+        Ok(())
+    }
+}
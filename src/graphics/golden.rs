@@ -0,0 +1,278 @@
+// golden image tests: scenes are rendered offscreen on a CPU device such as lavapipe and compared
+// with the references in tests/golden, run with AMK_UPDATE_GOLDEN=1 to rewrite the references.
+// like every test that renders they only run with `cargo test -- --ignored`, as CI does
+use std::{
+    env,
+    f32::consts::{FRAC_PI_2, FRAC_PI_4},
+    path::Path,
+};
+
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::{
+    assets::manager::AssetManager,
+    vfs::{DirectoryMount, Vfs},
+};
+
+use super::{
+    camera::{mul, rotation_z, scale, translation, Camera},
+    config::RenderConfig,
+    context::RenderContext,
+    post::effect::{PostEffect, Tonemapper},
+    renderers::{
+        instanced::{Instance, InstancedRenderer},
+        sprite::{Sprite, SpriteRenderer},
+        triangle::TriangleRenderer,
+    },
+    resolution::ResolutionMode,
+    screenshot::Screenshot,
+    testing::{offscreen_context, root_dir},
+    texture::Texture,
+};
+
+// a channel may differ by this much before the pixel counts as mismatched
+const CHANNEL_TOLERANCE: u8 = 3;
+// implementations are allowed to disagree on a few pixels along primitive edges
+const MAX_MISMATCHED_RATIO: f64 = 0.01;
+
+fn render(setup: impl FnOnce(&mut RenderContext) -> anyhow::Result<()>) -> Screenshot {
+    render_with(|config| config, setup)
+}
+
+fn render_with(
+    configure: impl FnOnce(RenderConfig) -> RenderConfig,
+    setup: impl FnOnce(&mut RenderContext) -> anyhow::Result<()>,
+) -> Screenshot {
+    let mut ctx = offscreen_context(configure);
+    setup(&mut ctx).unwrap();
+    ctx.screenshot().unwrap()
+}
+
+fn load_png(path: &Path) -> anyhow::Result<Screenshot> {
+    let image = image::open(path)?.to_rgba8();
+    Ok(Screenshot {
+        width: image.width(),
+        height: image.height(),
+        pixels: image.into_raw(),
+    })
+}
+
+// alpha is ignored, the presented frame is opaque regardless of what was written to it
+fn compare(actual: &Screenshot, reference: &Screenshot) -> (usize, Screenshot) {
+    let mut mismatched = 0;
+    let mut diff = reference.clone();
+    for (diff_pixel, actual_pixel) in diff
+        .pixels
+        .chunks_exact_mut(4)
+        .zip(actual.pixels.chunks_exact(4))
+    {
+        let matches = (0..3).all(|i| diff_pixel[i].abs_diff(actual_pixel[i]) <= CHANNEL_TOLERANCE);
+        if matches {
+            // darken matching pixels so the mismatches stand out
+            diff_pixel[..3].iter_mut().for_each(|c| *c /= 4);
+        } else {
+            mismatched += 1;
+            diff_pixel[..3].copy_from_slice(&[255, 0, 0]);
+        }
+        diff_pixel[3] = 255;
+    }
+    (mismatched, diff)
+}
+
+fn assert_golden(name: &str, actual: Screenshot) {
+    let reference_path = root_dir()
+        .join("tests/golden")
+        .join(format!("{}.png", name));
+    if env::var_os("AMK_UPDATE_GOLDEN").is_some() {
+        actual.save_png(&reference_path).unwrap();
+        return;
+    }
+
+    let reference = load_png(&reference_path).unwrap();
+    assert_eq!(
+        [actual.width, actual.height],
+        [reference.width, reference.height],
+        "{}: size does not match the reference",
+        name
+    );
+    let (mismatched, diff) = compare(&actual, &reference);
+    let ratio = mismatched as f64 / (actual.width * actual.height) as f64;
+    if ratio > MAX_MISMATCHED_RATIO {
+        let output_dir = root_dir().join("target/golden");
+        let actual_path = output_dir.join(format!("{}-actual.png", name));
+        let diff_path = output_dir.join(format!("{}-diff.png", name));
+        actual.save_png(&actual_path).unwrap();
+        diff.save_png(&diff_path).unwrap();
+        panic!(
+            "{}: {} pixels ({:.2}%) differ from the reference, see {} and {}",
+            name,
+            mismatched,
+            ratio * 100.0,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+// the vertices are staged and copied into device-local memory by the first frame
+#[test]
+#[ignore]
+fn triangle() {
+    let frame = render(|ctx| {
+        let report = ctx.memory_report();
+        assert!(report.heaps.iter().any(|heap| heap.buffers > 0));
+        Ok(())
+    });
+    assert_golden("triangle", frame);
+}
+
+#[test]
+#[ignore]
+fn sprites() {
+    let frame = render(|ctx| {
+        let sprites = ctx
+            .renderer_mut::<SpriteRenderer>(ctx.sprite_renderer)
+            .unwrap();
+        sprites.draw(Sprite::new([30.0, 30.0], [40.0, 30.0]).color([1.0, 0.0, 0.0, 1.0]));
+        sprites.draw(
+            Sprite::new([50.0, 40.0], [50.0, 20.0])
+                .color([0.0, 0.0, 1.0, 0.5])
+                .layer(1),
+        );
+        sprites.draw(
+            Sprite::new([96.0, 60.0], [30.0, 30.0])
+                .color([0.0, 1.0, 0.0, 1.0])
+                .rotation(FRAC_PI_4),
+        );
+        Ok(())
+    });
+    assert_golden("sprites", frame);
+}
+
+#[test]
+#[ignore]
+fn post_chain() {
+    let frame = render(|ctx| {
+        let chain = &mut ctx.post.chain;
+        chain.push("vignette", PostEffect::vignette());
+        chain.push("bloom", PostEffect::bloom());
+        chain.set_enabled("bloom", false);
+        chain.push(
+            "tonemap",
+            PostEffect::Tonemap {
+                operator: Tonemapper::Reinhard,
+                exposure: 1.0,
+            },
+        );
+        // the vignette is applied to tone mapped colours
+        chain.move_to("tonemap", 0);
+        Ok(())
+    });
+    assert_golden("post_chain", frame);
+}
+
+// the logical size fits the width exactly, so the scene is copied 1:1 between two bars
+#[test]
+#[ignore]
+fn letterbox() {
+    let frame = render_with(
+        |config| config.resolution(ResolutionMode::Fixed(PhysicalSize::new(128, 48))),
+        |ctx| {
+            let letterbox = ctx.letterbox();
+            assert_eq!(letterbox.origin, [0.0, 24.0]);
+            assert_eq!(
+                ctx.window_to_logical(PhysicalPosition::new(64.0, 48.0)),
+                Some([64.0, 24.0])
+            );
+            assert_eq!(
+                ctx.window_to_logical(PhysicalPosition::new(64.0, 10.0)),
+                None
+            );
+            Ok(())
+        },
+    );
+    assert_golden("letterbox", frame);
+}
+
+// the frame uniforms carry the camera, the per-draw uniforms the transform and colour
+#[test]
+#[ignore]
+fn camera() {
+    let frame = render(|ctx| {
+        ctx.camera = Camera::perspective(FRAC_PI_2)
+            .position([0.5, 0.25, 1.0])
+            .look_at([0.0, 0.0, 0.0]);
+        let triangle = ctx
+            .renderer_mut::<TriangleRenderer>(ctx.triangle_renderer)
+            .unwrap();
+        triangle.transform = rotation_z(0.3);
+        triangle.color = [1.0, 0.0, 0.0, 1.0];
+        Ok(())
+    });
+    assert_golden("camera", frame);
+}
+
+// a single batch, each instance with its own transform and colour, in a 2D world with +y up
+#[test]
+#[ignore]
+fn instanced() {
+    let frame = render(|ctx| {
+        ctx.camera = Camera::orthographic(2.0);
+        let instanced = ctx
+            .renderer_mut::<InstancedRenderer>(ctx.instanced_renderer)
+            .unwrap();
+        let (quad, material) = (instanced.quad(), instanced.default_material());
+        instanced.draw_all(
+            quad,
+            material,
+            (0..5).map(|i| {
+                let transform = mul(
+                    &translation([-1.0 + i as f32 * 0.5, -0.6, 0.0]),
+                    &mul(&rotation_z(i as f32 * 0.2), &scale([0.3, 0.3, 1.0])),
+                );
+                Instance::new(transform).color([i as f32 / 4.0, 1.0 - i as f32 / 4.0, 0.5, 1.0])
+            }),
+        );
+        Ok(())
+    });
+    assert_golden("instanced", frame);
+}
+
+// the frame waits for the copy on the transfer queue, so the texture shows up in the first frame
+#[test]
+#[ignore]
+fn async_upload() {
+    let mut upload = None;
+    let frame = render(|ctx| {
+        let texture = ctx
+            .uploads
+            .texture_async(2, 2, &[255, 128, 0, 255].repeat(4))?;
+        let sprite = Sprite::new([64.0, 48.0], [32.0, 32.0]).texture(texture.resource.view.clone());
+        ctx.renderer_mut::<SpriteRenderer>(ctx.sprite_renderer)
+            .unwrap()
+            .draw(sprite);
+        upload = Some(texture);
+        Ok(())
+    });
+    assert!(upload.unwrap().is_ready());
+    assert_golden("async_upload", frame);
+}
+
+// the same 2x2 texture as async_upload, decoded from tests/assets/orange.png on the asset thread
+#[test]
+#[ignore]
+fn texture_asset() {
+    let frame = render(|ctx| {
+        let vfs = Vfs::new().mount("", DirectoryMount::new(root_dir().join("tests/assets")));
+        let assets = AssetManager::new(vfs);
+        ctx.register_asset_loaders(&assets);
+        let handle = assets.load_blocking::<Texture>("orange.png")?;
+        let texture = handle.get().unwrap();
+        let sprite = Sprite::new([64.0, 48.0], [32.0, 32.0]).texture(texture.view.clone());
+        ctx.renderer_mut::<SpriteRenderer>(ctx.sprite_renderer)
+            .unwrap()
+            .draw(sprite);
+        Ok(())
+    });
+    assert_golden("texture_asset", frame);
+}
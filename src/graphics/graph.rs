@@ -0,0 +1,517 @@
+use std::{cmp::Reverse, collections::BinaryHeap, sync::Arc};
+
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents,
+    },
+    device::Device,
+    format::{ClearValue, Format},
+    image::{view::ImageView, AttachmentImage, ImageLayout, ImageUsage, ImageViewAbstract},
+    pipeline::{cache::PipelineCache, graphics::viewport::Viewport},
+    render_pass::{
+        AttachmentDescription, AttachmentReference, Framebuffer, FramebufferCreateInfo, LoadOp,
+        RenderPass, RenderPassCreateInfo, StoreOp, SubpassDescription,
+    },
+};
+
+use super::{renderer::RenderFrame, timing::GpuTimer};
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ResourceId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphImageSize {
+    // scale of the logical render extent
+    Relative(f32),
+    Fixed([u32; 2]),
+}
+
+impl GraphImageSize {
+    fn resolve(&self, extent: [u32; 2]) -> [u32; 2] {
+        match *self {
+            GraphImageSize::Relative(scale) => [
+                ((extent[0] as f32 * scale) as u32).max(1),
+                ((extent[1] as f32 * scale) as u32).max(1),
+            ],
+            GraphImageSize::Fixed(size) => size,
+        }
+    }
+}
+
+struct GraphImage {
+    name: String,
+    format: Format,
+    size: GraphImageSize,
+    exported: bool,
+}
+
+pub struct PassDesc {
+    pub name: String,
+    pub reads: Vec<ResourceId>,
+    pub writes: Vec<ResourceId>,
+    pub clear_color: [f32; 4],
+}
+
+impl PassDesc {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+            clear_color: [0.0; 4],
+        }
+    }
+
+    // read images are sampled in shaders
+    pub fn read(mut self, image: ResourceId) -> Self {
+        self.reads.push(image);
+        self
+    }
+
+    // written images become the attachments of the pass, in the order they are declared
+    pub fn write(mut self, image: ResourceId) -> Self {
+        self.writes.push(image);
+        self
+    }
+
+    pub fn clear_color(mut self, color: [f32; 4]) -> Self {
+        self.clear_color = color;
+        self
+    }
+}
+
+pub struct PassBuildContext<'a> {
+    pub device: Arc<Device>,
+    pub render_pass: Arc<RenderPass>,
+    pub pipeline_cache: Arc<PipelineCache>,
+    pub extent: [u32; 2],
+    views: &'a [Option<Arc<dyn ImageViewAbstract>>],
+}
+
+impl<'a> PassBuildContext<'a> {
+    pub fn view(&self, image: ResourceId) -> Arc<dyn ImageViewAbstract> {
+        self.views[image.0].clone().unwrap()
+    }
+}
+
+pub trait GraphPass {
+    // called every time the graph is rebuilt, pipelines and descriptor sets referring to the
+    // images of the graph must be recreated here
+    fn build(&mut self, ctx: &PassBuildContext) -> anyhow::Result<()>;
+
+    // records draw commands inside the render pass of this pass, with the viewport already set
+    fn record(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &RenderFrame,
+    ) -> anyhow::Result<()>;
+}
+
+struct PassNode {
+    desc: PassDesc,
+    pass: Box<dyn GraphPass>,
+}
+
+struct CompiledPass {
+    node: usize,
+    framebuffer: Arc<Framebuffer>,
+    clear_values: Vec<Option<ClearValue>>,
+    extent: [u32; 2],
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+}
+
+// orders passes so that every image is written by all of its writers, in insertion order,
+// before any pass reads it
+fn pass_order(passes: &[PassNode], image_count: usize) -> anyhow::Result<Vec<usize>> {
+    let mut edges = vec![Vec::new(); passes.len()];
+    for image in (0..image_count).map(ResourceId) {
+        let writers = (0..passes.len())
+            .filter(|index| passes[*index].desc.writes.contains(&image))
+            .collect::<Vec<_>>();
+        for pair in writers.windows(2) {
+            edges[pair[0]].push(pair[1]);
+        }
+        for reader in (0..passes.len()).filter(|index| passes[*index].desc.reads.contains(&image)) {
+            anyhow::ensure!(
+                !writers.contains(&reader),
+                "Pass {} reads and writes the same image",
+                passes[reader].desc.name
+            );
+            for writer in &writers {
+                edges[*writer].push(reader);
+            }
+        }
+    }
+
+    let mut in_degree = vec![0; passes.len()];
+    for targets in &edges {
+        for target in targets {
+            in_degree[*target] += 1;
+        }
+    }
+    // always pick the earliest added pass that is ready, so the order is deterministic
+    let mut ready = (0..passes.len())
+        .filter(|index| in_degree[*index] == 0)
+        .map(Reverse)
+        .collect::<BinaryHeap<_>>();
+    let mut order = Vec::with_capacity(passes.len());
+    while let Some(Reverse(index)) = ready.pop() {
+        order.push(index);
+        for target in &edges[index] {
+            in_degree[*target] -= 1;
+            if in_degree[*target] == 0 {
+                ready.push(Reverse(*target));
+            }
+        }
+    }
+    anyhow::ensure!(order.len() == passes.len(), "Render graph contains a cycle");
+    Ok(order)
+}
+
+fn attachment_layout(format: Format) -> ImageLayout {
+    if format.aspects().depth || format.aspects().stencil {
+        ImageLayout::DepthStencilAttachmentOptimal
+    } else {
+        ImageLayout::ColorAttachmentOptimal
+    }
+}
+
+fn access_layout(access: Access, format: Format) -> ImageLayout {
+    match access {
+        Access::Read => ImageLayout::ShaderReadOnlyOptimal,
+        Access::Write => attachment_layout(format),
+    }
+}
+
+pub struct RenderGraph {
+    device: Arc<Device>,
+    pipeline_cache: Arc<PipelineCache>,
+    images: Vec<GraphImage>,
+    passes: Vec<PassNode>,
+    extent: [u32; 2],
+    views: Vec<Option<Arc<dyn ImageViewAbstract>>>,
+    compiled: Vec<CompiledPass>,
+    dirty: bool,
+}
+
+impl RenderGraph {
+    pub fn new(device: Arc<Device>, pipeline_cache: Arc<PipelineCache>, extent: [u32; 2]) -> Self {
+        Self {
+            device,
+            pipeline_cache,
+            images: Vec::new(),
+            passes: Vec::new(),
+            extent,
+            views: Vec::new(),
+            compiled: Vec::new(),
+            dirty: true,
+        }
+    }
+
+    pub fn add_image(
+        &mut self,
+        name: impl Into<String>,
+        format: Format,
+        size: GraphImageSize,
+    ) -> ResourceId {
+        self.images.push(GraphImage {
+            name: name.into(),
+            format,
+            size,
+            exported: false,
+        });
+        self.dirty = true;
+        ResourceId(self.images.len() - 1)
+    }
+
+    // exported images keep their memory for the whole frame and end up in a sampleable layout,
+    // so they can be used after the graph is executed
+    pub fn export(&mut self, image: ResourceId) {
+        self.images[image.0].exported = true;
+        self.dirty = true;
+    }
+
+    pub fn add_pass(&mut self, desc: PassDesc, pass: Box<dyn GraphPass>) {
+        self.passes.push(PassNode { desc, pass });
+        self.dirty = true;
+    }
+
+    pub fn remove_pass(&mut self, name: &str) -> Option<Box<dyn GraphPass>> {
+        let index = self.passes.iter().position(|node| node.desc.name == name)?;
+        self.dirty = true;
+        Some(self.passes.remove(index).pass)
+    }
+
+    pub fn image_view(&self, image: ResourceId) -> Option<Arc<dyn ImageViewAbstract>> {
+        self.views.get(image.0).cloned().flatten()
+    }
+
+    pub fn rebuild(&mut self, extent: [u32; 2]) {
+        if self.extent != extent {
+            self.extent = extent;
+            self.dirty = true;
+        }
+    }
+
+    // images whose lifetimes do not overlap share the same memory if their format and size match
+    fn allocate_images(
+        &self,
+        order: &[usize],
+    ) -> anyhow::Result<Vec<Option<Arc<dyn ImageViewAbstract>>>> {
+        let mut first_use = vec![usize::MAX; self.images.len()];
+        let mut last_use = vec![0; self.images.len()];
+        for (position, node) in order.iter().map(|index| &self.passes[*index]).enumerate() {
+            for image in node.desc.reads.iter().chain(&node.desc.writes) {
+                first_use[image.0] = first_use[image.0].min(position);
+                last_use[image.0] = last_use[image.0].max(position);
+            }
+        }
+
+        let mut by_first_use = (0..self.images.len())
+            .filter(|index| first_use[*index] != usize::MAX)
+            .collect::<Vec<_>>();
+        by_first_use.sort_by_key(|index| first_use[*index]);
+
+        let mut physical: Vec<(Format, [u32; 2], usize, Arc<dyn ImageViewAbstract>)> = Vec::new();
+        let mut views = vec![None; self.images.len()];
+        for index in by_first_use {
+            let image = &self.images[index];
+            let dimensions = image.size.resolve(self.extent);
+            let free_after = match image.exported {
+                true => usize::MAX,
+                false => last_use[index],
+            };
+            let reusable = physical.iter_mut().find(|(format, size, busy_until, _)| {
+                *format == image.format && *size == dimensions && *busy_until < first_use[index]
+            });
+            views[index] = Some(match reusable {
+                Some((_, _, busy_until, view)) => {
+                    *busy_until = free_after;
+                    view.clone()
+                }
+                None => {
+                    let depth = image.format.aspects().depth || image.format.aspects().stencil;
+                    let view: Arc<dyn ImageViewAbstract> =
+                        ImageView::new_default(AttachmentImage::with_usage(
+                            self.device.clone(),
+                            dimensions,
+                            image.format,
+                            ImageUsage {
+                                color_attachment: !depth,
+                                depth_stencil_attachment: depth,
+                                sampled: true,
+                                transfer_src: true,
+                                ..ImageUsage::empty()
+                            },
+                        )?)?;
+                    physical.push((image.format, dimensions, free_after, view.clone()));
+                    view
+                }
+            });
+        }
+        Ok(views)
+    }
+
+    // the access to an image by the first of the given passes that uses it
+    fn neighbour_access<'a>(
+        &self,
+        image: ResourceId,
+        mut passes: impl Iterator<Item = &'a usize>,
+    ) -> Option<Access> {
+        passes.find_map(|index| {
+            let desc = &self.passes[*index].desc;
+            if desc.writes.contains(&image) {
+                Some(Access::Write)
+            } else if desc.reads.contains(&image) {
+                Some(Access::Read)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn create_render_pass(
+        &self,
+        order: &[usize],
+        position: usize,
+    ) -> anyhow::Result<Arc<RenderPass>> {
+        let desc = &self.passes[order[position]].desc;
+        let mut attachments = Vec::new();
+        let mut subpass = SubpassDescription::default();
+        for image in &desc.writes {
+            let format = self.images[image.0].format;
+            let previous = self.neighbour_access(*image, order[..position].iter().rev());
+            let next = self.neighbour_access(*image, order[position + 1..].iter());
+            let final_layout = match next {
+                Some(access) => access_layout(access, format),
+                None if self.images[image.0].exported => ImageLayout::ShaderReadOnlyOptimal,
+                None => attachment_layout(format),
+            };
+            let reference = Some(AttachmentReference {
+                attachment: attachments.len() as u32,
+                layout: attachment_layout(format),
+                ..Default::default()
+            });
+            if attachment_layout(format) == ImageLayout::DepthStencilAttachmentOptimal {
+                anyhow::ensure!(
+                    subpass.depth_stencil_attachment.is_none(),
+                    "Pass {} writes more than one depth/stencil image",
+                    desc.name
+                );
+                subpass.depth_stencil_attachment = reference;
+            } else {
+                subpass.color_attachments.push(reference);
+            }
+            // the first write of an image discards whatever was in its memory before
+            let (load_op, initial_layout) = match previous {
+                Some(access) => (LoadOp::Load, access_layout(access, format)),
+                None => (LoadOp::Clear, ImageLayout::Undefined),
+            };
+            attachments.push(AttachmentDescription {
+                format: Some(format),
+                load_op,
+                store_op: StoreOp::Store,
+                stencil_load_op: load_op,
+                stencil_store_op: StoreOp::Store,
+                initial_layout,
+                final_layout,
+                ..Default::default()
+            });
+        }
+
+        Ok(RenderPass::new(
+            self.device.clone(),
+            RenderPassCreateInfo {
+                attachments,
+                subpasses: vec![subpass],
+                ..Default::default()
+            },
+        )?)
+    }
+
+    fn compile(&mut self) -> anyhow::Result<()> {
+        let order = pass_order(&self.passes, self.images.len())?;
+        for node in &self.passes {
+            for image in &node.desc.reads {
+                anyhow::ensure!(
+                    self.passes
+                        .iter()
+                        .any(|other| other.desc.writes.contains(image)),
+                    "Pass {} reads image {} which is never written",
+                    node.desc.name,
+                    self.images[image.0].name
+                );
+            }
+        }
+        self.views = self.allocate_images(&order)?;
+
+        let mut compiled = Vec::with_capacity(order.len());
+        for position in 0..order.len() {
+            let render_pass = self.create_render_pass(&order, position)?;
+            let node = &mut self.passes[order[position]];
+            let extent = node
+                .desc
+                .writes
+                .first()
+                .map(|image| self.images[image.0].size.resolve(self.extent))
+                .unwrap_or(self.extent);
+            let framebuffer = Framebuffer::new(
+                render_pass.clone(),
+                FramebufferCreateInfo {
+                    attachments: node
+                        .desc
+                        .writes
+                        .iter()
+                        .map(|image| self.views[image.0].clone().unwrap())
+                        .collect(),
+                    ..Default::default()
+                },
+            )?;
+            let clear_values = node
+                .desc
+                .writes
+                .iter()
+                .zip(render_pass.attachments())
+                .map(|(image, attachment)| match attachment.load_op {
+                    LoadOp::Clear => Some(
+                        if attachment_layout(self.images[image.0].format)
+                            == ImageLayout::DepthStencilAttachmentOptimal
+                        {
+                            ClearValue::DepthStencil((1.0, 0))
+                        } else {
+                            node.desc.clear_color.into()
+                        },
+                    ),
+                    _ => None,
+                })
+                .collect();
+            node.pass.build(&PassBuildContext {
+                device: self.device.clone(),
+                render_pass,
+                pipeline_cache: self.pipeline_cache.clone(),
+                extent,
+                views: &self.views,
+            })?;
+            compiled.push(CompiledPass {
+                node: order[position],
+                framebuffer,
+                clear_values,
+                extent,
+            });
+        }
+        self.compiled = compiled;
+        self.dirty = false;
+        log::debug!(
+            "Render graph compiled: {}",
+            self.compiled
+                .iter()
+                .map(|pass| self.passes[pass.node].desc.name.as_str())
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        );
+        Ok(())
+    }
+
+    // memory barriers and layout transitions between the passes are inserted by vulkano, from
+    // the layouts declared in the render passes and descriptor sets
+    pub fn execute(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &RenderFrame,
+        timer: &mut GpuTimer,
+    ) -> anyhow::Result<()> {
+        if self.dirty {
+            self.compile()?;
+        }
+        for compiled in &self.compiled {
+            let node = &mut self.passes[compiled.node];
+            timer.scope(builder, &node.desc.name, |builder| {
+                builder
+                    .begin_render_pass(
+                        RenderPassBeginInfo {
+                            clear_values: compiled.clear_values.clone(),
+                            ..RenderPassBeginInfo::framebuffer(compiled.framebuffer.clone())
+                        },
+                        SubpassContents::Inline,
+                    )?
+                    .set_viewport(
+                        0,
+                        [Viewport {
+                            origin: [0.0, 0.0],
+                            dimensions: [compiled.extent[0] as f32, compiled.extent[1] as f32],
+                            depth_range: 0.0..1.0,
+                        }],
+                    );
+                node.pass.record(builder, frame)?;
+                builder.end_render_pass()?;
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+}
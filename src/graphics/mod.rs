@@ -1,2 +1,28 @@
+pub mod attachments;
+pub mod camera;
+pub mod color;
+pub mod config;
 pub mod context;
-pub mod renderers;
\ No newline at end of file
+pub mod device;
+pub mod dump;
+pub mod frame;
+#[cfg(test)]
+mod golden;
+pub mod graph;
+mod ownership;
+pub mod pipeline_cache;
+pub mod post;
+pub mod renderer;
+pub mod renderers;
+pub mod resolution;
+pub mod screenshot;
+pub mod shader;
+pub mod target;
+#[cfg(test)]
+mod testing;
+pub mod text;
+pub mod texture;
+pub mod timing;
+pub mod uniforms;
+pub mod upload;
+pub mod validation;
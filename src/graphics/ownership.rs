@@ -0,0 +1,292 @@
+use std::{ptr, sync::Arc};
+
+use ash::vk;
+use vulkano::{
+    buffer::BufferAccess,
+    device::{Device, Queue},
+    image::ImageAccess,
+    VulkanObject,
+};
+
+// a resource that changes queue family, uploads only ever write whole colour images and buffers
+pub(crate) enum OwnedResource {
+    Buffer(Arc<dyn BufferAccess>),
+    Image(Arc<dyn ImageAccess>),
+}
+
+// a one-off command buffer with its own pool, and the fence of its submission
+struct RawCommands {
+    pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+    submitted: bool,
+}
+
+impl RawCommands {
+    const NULL: Self = Self {
+        pool: vk::CommandPool::null(),
+        command_buffer: vk::CommandBuffer::null(),
+        fence: vk::Fence::null(),
+        submitted: false,
+    };
+}
+
+// moves exclusively shared resources from the transfer queue family to the graphics queue family
+// after an async upload. the release barrier is submitted to the transfer queue right after the
+// copies and signals a semaphore, the acquire barrier waits for it on the graphics queue and is
+// submitted before the first frame that may use the resources. vulkano's auto command buffers
+// cannot record either barrier, so both are recorded into raw command buffers of their own.
+// dropping the transfer waits for both submissions
+pub(crate) struct OwnershipTransfer {
+    device: Arc<Device>,
+    resources: Vec<OwnedResource>,
+    src_family: u32,
+    dst_family: u32,
+    semaphore: vk::Semaphore,
+    release: RawCommands,
+    acquire: RawCommands,
+}
+
+impl OwnershipTransfer {
+    // must be called right after the copies were submitted to `transfer_queue`, barriers only
+    // wait for work submitted before them
+    pub fn release(
+        transfer_queue: &Arc<Queue>,
+        dst_family: u32,
+        resources: Vec<OwnedResource>,
+    ) -> anyhow::Result<Self> {
+        let device = transfer_queue.device().clone();
+        let mut transfer = Self {
+            device,
+            resources,
+            src_family: transfer_queue.queue_family_index(),
+            dst_family,
+            semaphore: vk::Semaphore::null(),
+            release: RawCommands::NULL,
+            acquire: RawCommands::NULL,
+        };
+        let fns = transfer.device.fns();
+        unsafe {
+            (fns.v1_0.create_semaphore)(
+                transfer.device.internal_object(),
+                &vk::SemaphoreCreateInfo::default(),
+                ptr::null(),
+                &mut transfer.semaphore,
+            )
+            .result()?;
+        }
+        transfer.release = transfer.record(
+            transfer.src_family,
+            (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::AccessFlags::empty(),
+            ),
+        )?;
+        let signal = [transfer.semaphore];
+        transfer.submit(transfer_queue, true, &[], &signal)?;
+        Ok(transfer)
+    }
+
+    // must be called before the first use of the resources is submitted to `graphics_queue`
+    pub fn acquire(&mut self, graphics_queue: &Arc<Queue>) -> anyhow::Result<()> {
+        debug_assert_eq!(graphics_queue.queue_family_index(), self.dst_family);
+        if self.acquire.submitted {
+            return Ok(());
+        }
+        self.acquire = self.record(
+            self.dst_family,
+            (
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::AccessFlags::empty(),
+            ),
+            (
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::AccessFlags::MEMORY_READ,
+            ),
+        )?;
+        let wait = [self.semaphore];
+        self.submit(graphics_queue, false, &wait, &[])
+    }
+
+    // the same barriers on both sides, only the stages and accesses of the submitting side count
+    fn record(
+        &self,
+        family: u32,
+        (src_stage, src_access): (vk::PipelineStageFlags, vk::AccessFlags),
+        (dst_stage, dst_access): (vk::PipelineStageFlags, vk::AccessFlags),
+    ) -> anyhow::Result<RawCommands> {
+        let mut buffer_barriers = Vec::new();
+        let mut image_barriers = Vec::new();
+        for resource in &self.resources {
+            match resource {
+                OwnedResource::Buffer(buffer) => {
+                    let inner = buffer.inner();
+                    buffer_barriers.push(vk::BufferMemoryBarrier {
+                        src_access_mask: src_access,
+                        dst_access_mask: dst_access,
+                        src_queue_family_index: self.src_family,
+                        dst_queue_family_index: self.dst_family,
+                        buffer: inner.buffer.internal_object(),
+                        offset: inner.offset,
+                        size: buffer.size(),
+                        ..Default::default()
+                    });
+                }
+                OwnedResource::Image(image) => {
+                    let inner = image.inner();
+                    // vulkano leaves the image in this layout at the end of the copies, and
+                    // expects it there when the frame uses it
+                    let layout = image.final_layout_requirement().into();
+                    image_barriers.push(vk::ImageMemoryBarrier {
+                        src_access_mask: src_access,
+                        dst_access_mask: dst_access,
+                        old_layout: layout,
+                        new_layout: layout,
+                        src_queue_family_index: self.src_family,
+                        dst_queue_family_index: self.dst_family,
+                        image: inner.image.internal_object(),
+                        subresource_range: vk::ImageSubresourceRange {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            base_mip_level: inner.first_mipmap_level,
+                            level_count: inner.num_mipmap_levels,
+                            base_array_layer: inner.first_layer,
+                            layer_count: inner.num_layers,
+                        },
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        let device = self.device.internal_object();
+        let fns = self.device.fns();
+        let mut commands = RawCommands::NULL;
+        // on error, whatever was created so far is destroyed right away
+        let result = unsafe {
+            (|| {
+                (fns.v1_0.create_command_pool)(
+                    device,
+                    &vk::CommandPoolCreateInfo {
+                        flags: vk::CommandPoolCreateFlags::TRANSIENT,
+                        queue_family_index: family,
+                        ..Default::default()
+                    },
+                    ptr::null(),
+                    &mut commands.pool,
+                )
+                .result()?;
+                (fns.v1_0.allocate_command_buffers)(
+                    device,
+                    &vk::CommandBufferAllocateInfo {
+                        command_pool: commands.pool,
+                        level: vk::CommandBufferLevel::PRIMARY,
+                        command_buffer_count: 1,
+                        ..Default::default()
+                    },
+                    &mut commands.command_buffer,
+                )
+                .result()?;
+                (fns.v1_0.begin_command_buffer)(
+                    commands.command_buffer,
+                    &vk::CommandBufferBeginInfo {
+                        flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+                        ..Default::default()
+                    },
+                )
+                .result()?;
+                (fns.v1_0.cmd_pipeline_barrier)(
+                    commands.command_buffer,
+                    src_stage,
+                    dst_stage,
+                    vk::DependencyFlags::empty(),
+                    0,
+                    ptr::null(),
+                    buffer_barriers.len() as u32,
+                    buffer_barriers.as_ptr(),
+                    image_barriers.len() as u32,
+                    image_barriers.as_ptr(),
+                );
+                (fns.v1_0.end_command_buffer)(commands.command_buffer).result()?;
+                (fns.v1_0.create_fence)(
+                    device,
+                    &vk::FenceCreateInfo::default(),
+                    ptr::null(),
+                    &mut commands.fence,
+                )
+                .result()
+            })()
+        };
+        match result {
+            Ok(()) => Ok(commands),
+            Err(e) => {
+                self.destroy(&commands);
+                Err(e.into())
+            }
+        }
+    }
+
+    fn submit(
+        &mut self,
+        queue: &Arc<Queue>,
+        release: bool,
+        wait: &[vk::Semaphore],
+        signal: &[vk::Semaphore],
+    ) -> anyhow::Result<()> {
+        let commands = match release {
+            true => &mut self.release,
+            false => &mut self.acquire,
+        };
+        let wait_stages = vec![vk::PipelineStageFlags::ALL_COMMANDS; wait.len()];
+        let submit_info = vk::SubmitInfo {
+            wait_semaphore_count: wait.len() as u32,
+            p_wait_semaphores: wait.as_ptr(),
+            p_wait_dst_stage_mask: wait_stages.as_ptr(),
+            command_buffer_count: 1,
+            p_command_buffers: &commands.command_buffer,
+            signal_semaphore_count: signal.len() as u32,
+            p_signal_semaphores: signal.as_ptr(),
+            ..Default::default()
+        };
+        let fns = self.device.fns();
+        // holding the queue keeps vulkano from submitting to it at the same time
+        queue
+            .with(|_queue| unsafe {
+                (fns.v1_0.queue_submit)(queue.internal_object(), 1, &submit_info, commands.fence)
+            })
+            .result()?;
+        commands.submitted = true;
+        Ok(())
+    }
+
+    fn destroy(&self, commands: &RawCommands) {
+        let device = self.device.internal_object();
+        let fns = self.device.fns();
+        unsafe {
+            if commands.submitted {
+                let _ = (fns.v1_0.wait_for_fences)(device, 1, &commands.fence, vk::TRUE, u64::MAX);
+            }
+            (fns.v1_0.destroy_fence)(device, commands.fence, ptr::null());
+            // frees the command buffer with it
+            (fns.v1_0.destroy_command_pool)(device, commands.pool, ptr::null());
+        }
+    }
+}
+
+impl Drop for OwnershipTransfer {
+    fn drop(&mut self) {
+        self.destroy(&self.release);
+        self.destroy(&self.acquire);
+        let fns = self.device.fns();
+        unsafe {
+            (fns.v1_0.destroy_semaphore)(
+                self.device.internal_object(),
+                self.semaphore,
+                ptr::null(),
+            );
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::Context;
+use vulkano::{
+    device::{physical::PhysicalDevice, Device},
+    pipeline::cache::PipelineCache,
+};
+
+// VK_PIPELINE_CACHE_HEADER_VERSION_ONE
+const HEADER_VERSION_ONE: u32 = 1;
+// length, version, vendor id, device id and the pipeline cache UUID
+const HEADER_SIZE: usize = 16 + 16;
+
+// a Vulkan pipeline cache that is loaded from and saved to a file per device and driver
+pub struct PersistentPipelineCache {
+    cache: Arc<PipelineCache>,
+    path: Option<PathBuf>,
+    // bytes in the file, drivers only ever add to a cache so unchanged sizes are not saved again
+    saved_size: AtomicUsize,
+}
+
+impl PersistentPipelineCache {
+    // a missing, unreadable or mismatching file results in an empty cache
+    pub fn load(device: Arc<Device>, dir: Option<&Path>) -> anyhow::Result<Self> {
+        let path = dir.map(|dir| dir.join(file_name(device.physical_device())));
+        let data = path.as_ref().and_then(|path| match fs::read(path) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                log::debug!("No pipeline cache loaded from {}: {}", path.display(), e);
+                None
+            }
+        });
+
+        let (cache, saved_size) = match data {
+            Some(data) => match validate_header(device.physical_device(), &data) {
+                // the header matches this device, so the driver can safely parse the rest
+                Ok(()) => {
+                    log::info!(
+                        "Loaded {} byte pipeline cache from {}",
+                        data.len(),
+                        path.as_ref().unwrap().display()
+                    );
+                    (
+                        unsafe { PipelineCache::with_data(device, &data)? },
+                        data.len(),
+                    )
+                }
+                Err(reason) => {
+                    log::warn!("Discarding pipeline cache: {}", reason);
+                    (PipelineCache::empty(device)?, 0)
+                }
+            },
+            None => (PipelineCache::empty(device)?, 0),
+        };
+        Ok(Self {
+            cache,
+            path,
+            saved_size: AtomicUsize::new(saved_size),
+        })
+    }
+
+    pub fn cache(&self) -> Arc<PipelineCache> {
+        self.cache.clone()
+    }
+
+    // the size of the file the cache was loaded from or last saved to, 0 if there is none
+    pub fn saved_size(&self) -> usize {
+        self.saved_size.load(Ordering::Relaxed)
+    }
+
+    // writes the cache to its file if pipelines were added since it was loaded or last saved
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let data = self.cache.get_data()?;
+        if data.len() == self.saved_size() {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Unable to create {}", dir.display()))?;
+        }
+        // write to a temporary file first so a crash never leaves a truncated cache behind
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, &data)
+            .with_context(|| format!("Unable to write {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Unable to write {}", path.display()))?;
+        self.saved_size.store(data.len(), Ordering::Relaxed);
+        log::info!(
+            "Saved {} byte pipeline cache to {}",
+            data.len(),
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+impl Drop for PersistentPipelineCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            log::warn!("Failed to save pipeline cache: {:?}", e);
+        }
+    }
+}
+
+// $XDG_CACHE_HOME/amk, falling back to ~/.cache/amk
+pub fn default_cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|dir| dir.join("amk"))
+}
+
+fn file_name(phys_device: &PhysicalDevice) -> String {
+    let properties = phys_device.properties();
+    format!(
+        "pipeline-cache-{:04x}-{:04x}-{:08x}.bin",
+        properties.vendor_id, properties.device_id, properties.driver_version
+    )
+}
+
+fn validate_header(phys_device: &PhysicalDevice, data: &[u8]) -> Result<(), String> {
+    if data.len() < HEADER_SIZE {
+        return Err(format!("file is too short ({} bytes)", data.len()));
+    }
+    let read_u32 = |offset: usize| u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+    let properties = phys_device.properties();
+
+    let header_size = read_u32(0) as usize;
+    if header_size < HEADER_SIZE || header_size > data.len() {
+        return Err(format!("invalid header size {}", header_size));
+    }
+    let version = read_u32(4);
+    if version != HEADER_VERSION_ONE {
+        return Err(format!("unsupported header version {}", version));
+    }
+    let (vendor_id, device_id) = (read_u32(8), read_u32(12));
+    if vendor_id != properties.vendor_id || device_id != properties.device_id {
+        return Err(format!(
+            "created for device {:04x}:{:04x}, expected {:04x}:{:04x}",
+            vendor_id, device_id, properties.vendor_id, properties.device_id
+        ));
+    }
+    if data[16..32] != properties.pipeline_cache_uuid {
+        return Err("pipeline cache UUID does not match the driver".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::testing::{offscreen_context, root_dir};
+
+    use super::*;
+
+    // the cache is saved while rendering, the app exits without dropping the context
+    #[test]
+    #[ignore]
+    fn saved_while_rendering() {
+        let dir = root_dir().join("target/tests/pipeline_cache");
+        let _ = fs::remove_dir_all(&dir);
+        let mut ctx = offscreen_context(|config| config.pipeline_cache_dir(Some(dir.clone())));
+        ctx.render().unwrap();
+        ctx.wait_idle().unwrap();
+        let saved = ctx.pipeline_cache.saved_size();
+        assert!(saved > 0, "the pipeline cache was not saved");
+        let reloaded = PersistentPipelineCache::load(ctx.device.clone(), Some(&dir)).unwrap();
+        assert_eq!(reloaded.saved_size(), saved);
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::Arc;
+
+use crate::graphics::texture::Texture;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tonemapper {
+    Reinhard,
+    // the filmic curve fitted to the ACES reference transform by Krzysztof Narkowicz
+    Aces,
+}
+
+// every effect reads the output of the previous enabled effect, colours are linear and may exceed
+// 1.0 until a tone mapping effect maps them into display range
+#[derive(Clone)]
+pub enum PostEffect {
+    // the part of every colour above `threshold` bleeds into the surrounding `radius` pixels
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+        radius: f32,
+    },
+    Tonemap {
+        operator: Tonemapper,
+        exposure: f32,
+    },
+    // `lut` is a 3D lookup table unwrapped into a strip of square slices ordered by blue, e.g.
+    // 256x16 for 16 entries per channel, `strength` blends between the input and graded colour
+    ColorGrade {
+        lut: Arc<Texture>,
+        strength: f32,
+    },
+    // edges whose local contrast is below `edge_threshold` are left alone, the others are
+    // blended along the edge over at most `span_max` pixels
+    Fxaa {
+        edge_threshold: f32,
+        span_max: f32,
+    },
+    // darkens the image outside of `radius`, in units of half the diagonal, over `softness`
+    Vignette {
+        intensity: f32,
+        radius: f32,
+        softness: f32,
+    },
+}
+
+impl PostEffect {
+    pub fn bloom() -> Self {
+        Self::Bloom {
+            threshold: 1.0,
+            intensity: 0.5,
+            radius: 16.0,
+        }
+    }
+
+    pub fn tonemap() -> Self {
+        Self::Tonemap {
+            operator: Tonemapper::Aces,
+            exposure: 1.0,
+        }
+    }
+
+    pub fn color_grade(lut: Arc<Texture>) -> Self {
+        Self::ColorGrade { lut, strength: 1.0 }
+    }
+
+    pub fn fxaa() -> Self {
+        Self::Fxaa {
+            edge_threshold: 0.125,
+            span_max: 8.0,
+        }
+    }
+
+    pub fn vignette() -> Self {
+        Self::Vignette {
+            intensity: 0.5,
+            radius: 0.75,
+            softness: 0.5,
+        }
+    }
+
+    // the fragment shaders of every kind of effect
+    pub(crate) const SHADERS: [&'static str; 5] = [
+        "post/bloom.frag",
+        "post/tonemap.frag",
+        "post/color_grade.frag",
+        "post/fxaa.frag",
+        "post/vignette.frag",
+    ];
+
+    pub(crate) fn shader(&self) -> &'static str {
+        match self {
+            Self::Bloom { .. } => "post/bloom.frag",
+            Self::Tonemap { .. } => "post/tonemap.frag",
+            Self::ColorGrade { .. } => "post/color_grade.frag",
+            Self::Fxaa { .. } => "post/fxaa.frag",
+            Self::Vignette { .. } => "post/vignette.frag",
+        }
+    }
+
+    // packed into the `params` push constant shared by every post shader
+    pub(crate) fn params(&self) -> [f32; 4] {
+        match *self {
+            Self::Bloom {
+                threshold,
+                intensity,
+                radius,
+            } => [threshold, intensity, radius, 0.0],
+            Self::Tonemap { operator, exposure } => [exposure, operator as u32 as f32, 0.0, 0.0],
+            Self::ColorGrade { strength, .. } => [strength, 0.0, 0.0, 0.0],
+            Self::Fxaa {
+                edge_threshold,
+                span_max,
+            } => [edge_threshold, span_max, 0.0, 0.0],
+            Self::Vignette {
+                intensity,
+                radius,
+                softness,
+            } => [intensity, radius, softness, 0.0],
+        }
+    }
+
+    pub(crate) fn lut(&self) -> Option<&Arc<Texture>> {
+        match self {
+            Self::ColorGrade { lut, .. } => Some(lut),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) struct PostStage {
+    pub name: String,
+    pub effect: PostEffect,
+    pub enabled: bool,
+}
+
+// the effects applied to the scene, in order. stages are identified by the name they were added
+// with and may be changed at any time, the changes are picked up by the next frame
+#[derive(Default)]
+pub struct PostChain {
+    pub(crate) stages: Vec<PostStage>,
+}
+
+impl PostChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: impl Into<String>, effect: PostEffect) -> Self {
+        self.push(name, effect);
+        self
+    }
+
+    // replaces the effect of an existing stage with the same name, keeping its position
+    pub fn push(&mut self, name: impl Into<String>, effect: PostEffect) {
+        let name = name.into();
+        match self.stages.iter_mut().find(|stage| stage.name == name) {
+            Some(stage) => stage.effect = effect,
+            None => self.stages.push(PostStage {
+                name,
+                effect,
+                enabled: true,
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<PostEffect> {
+        let index = self.position(name)?;
+        Some(self.stages.remove(index).effect)
+    }
+
+    // moves a stage to `index` in the chain, clamped to the last position
+    pub fn move_to(&mut self, name: &str, index: usize) -> bool {
+        match self.position(name) {
+            Some(from) => {
+                let stage = self.stages.remove(from);
+                self.stages.insert(index.min(self.stages.len()), stage);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.stages.iter_mut().find(|stage| stage.name == name) {
+            Some(stage) => {
+                stage.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.stages
+            .iter()
+            .any(|stage| stage.name == name && stage.enabled)
+    }
+
+    pub fn effect_mut(&mut self, name: &str) -> Option<&mut PostEffect> {
+        self.stages
+            .iter_mut()
+            .find(|stage| stage.name == name)
+            .map(|stage| &mut stage.effect)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.stages.iter().map(|stage| stage.name.as_str())
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.stages.iter().position(|stage| stage.name == name)
+    }
+}
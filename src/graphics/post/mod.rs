@@ -0,0 +1,464 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Device,
+    format::Format,
+    image::{
+        view::ImageView, AttachmentImage, ImageAccess, ImageUsage, ImageViewAbstract, SampleCount,
+    },
+    pipeline::{
+        cache::PipelineCache,
+        graphics::{
+            input_assembly::InputAssemblyState,
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, LoadOp, RenderPass, Subpass},
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+};
+
+use self::effect::{PostChain, PostEffect};
+use super::{
+    attachments::create_fullscreen_render_pass, color::OutputTransform, resolution::Letterbox,
+    shader::ShaderManager, target::RenderTarget, texture::Texture, timing::GpuTimer,
+};
+
+pub mod effect;
+
+// colour format of the scene and of the images between post effects
+pub const HDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+const VERTEX_SHADER: &str = "post/fullscreen.vert";
+const OUTPUT_SHADER: &str = "post/output.frag";
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct PushConstants {
+    params: [f32; 4],
+    texel_size: [f32; 2],
+}
+
+// a framebuffer and the part of it a pass draws to
+struct DrawTarget {
+    framebuffer: Arc<Framebuffer>,
+    viewport: Viewport,
+}
+
+struct PostImage {
+    view: Arc<dyn ImageViewAbstract>,
+    target: DrawTarget,
+}
+
+// a descriptor set by the addresses of its layout and image views, which the set keeps alive
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct SetKey {
+    layout: usize,
+    input: usize,
+    lut: Option<usize>,
+}
+
+type DescriptorSets = HashMap<SetKey, Arc<PersistentDescriptorSet>>;
+
+// the images of one frame in flight, so that a frame never writes an image that the frame
+// before it may still be reading
+struct PostFrame {
+    scene: Arc<AttachmentImage>,
+    scene_view: Arc<dyn ImageViewAbstract>,
+    // effects alternate between writing these two images
+    images: Vec<PostImage>,
+    // the sets the last recording with these images used, sets of passes that were changed or
+    // disabled since are dropped at the next one
+    descriptor_sets: DescriptorSets,
+}
+
+fn full_viewport(extent: [u32; 2]) -> Viewport {
+    Viewport {
+        origin: [0.0, 0.0],
+        dimensions: [extent[0] as f32, extent[1] as f32],
+        depth_range: 0.0..1.0,
+    }
+}
+
+fn create_image(device: Arc<Device>, extent: [u32; 2]) -> anyhow::Result<Arc<AttachmentImage>> {
+    Ok(AttachmentImage::with_usage(
+        device,
+        extent,
+        HDR_FORMAT,
+        ImageUsage {
+            color_attachment: true,
+            sampled: true,
+            ..ImageUsage::empty()
+        },
+    )?)
+}
+
+fn create_pipeline(
+    shaders: &ShaderManager,
+    pipeline_cache: &Arc<PipelineCache>,
+    render_pass: &Arc<RenderPass>,
+    fragment_shader: &str,
+) -> anyhow::Result<Arc<GraphicsPipeline>> {
+    let vs = shaders.module(VERTEX_SHADER)?;
+    let fs = shaders.module(fragment_shader)?;
+    Ok(GraphicsPipeline::start()
+        .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+        .vertex_input_state(BuffersDefinition::new())
+        .input_assembly_state(InputAssemblyState::new())
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(fs.entry_point("main").unwrap(), ())
+        .build_with_cache(pipeline_cache.clone())
+        .build(render_pass.device().clone())?)
+}
+
+// owns the HDR images the scene is rendered into, one per frame in flight, runs the enabled
+// effects of `chain` on them and writes the result to the letterboxed part of the render target
+// in a final output pass
+pub struct PostProcessor {
+    pub chain: PostChain,
+    pub output_transform: OutputTransform,
+    paper_white: f32,
+    device: Arc<Device>,
+    shaders: Arc<ShaderManager>,
+    pipeline_cache: Arc<PipelineCache>,
+    sampler: Arc<Sampler>,
+    descriptor_set_allocator: StandardDescriptorSetAllocator,
+    effect_pass: Arc<RenderPass>,
+    output_pass: Arc<RenderPass>,
+    frames: Vec<PostFrame>,
+    frames_in_flight: usize,
+    // the bars around the letterboxed scene are cleared to black
+    output_targets: Vec<DrawTarget>,
+    // effect pipelines by fragment shader, built on first use
+    pipelines: HashMap<&'static str, Arc<GraphicsPipeline>>,
+    output_pipeline: Arc<GraphicsPipeline>,
+}
+
+impl PostProcessor {
+    pub fn new(
+        device: Arc<Device>,
+        shaders: Arc<ShaderManager>,
+        pipeline_cache: Arc<PipelineCache>,
+        target: &RenderTarget,
+        letterbox: &Letterbox,
+        chain: PostChain,
+        paper_white: f32,
+        frames_in_flight: usize,
+    ) -> anyhow::Result<Self> {
+        // effect pipelines are built when an effect is first enabled, which should not wait for
+        // its shader to compile
+        shaders.precompile(
+            [VERTEX_SHADER, OUTPUT_SHADER]
+                .into_iter()
+                .chain(PostEffect::SHADERS),
+        );
+        let effect_pass = create_fullscreen_render_pass(device.clone(), HDR_FORMAT, false)?;
+        let output_pass =
+            create_fullscreen_render_pass(device.clone(), target.image_format(), true)?;
+        let output_pipeline =
+            create_pipeline(&shaders, &pipeline_cache, &output_pass, OUTPUT_SHADER)?;
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )?;
+        let mut post = Self {
+            chain,
+            output_transform: OutputTransform::Linear,
+            paper_white,
+            descriptor_set_allocator: StandardDescriptorSetAllocator::new(device.clone()),
+            device,
+            shaders,
+            pipeline_cache,
+            sampler,
+            effect_pass,
+            output_pass,
+            frames: Vec::new(),
+            frames_in_flight,
+            output_targets: Vec::new(),
+            pipelines: HashMap::new(),
+            output_pipeline,
+        };
+        post.resize(target, letterbox)?;
+        Ok(post)
+    }
+
+    // the main render pass of each frame in flight resolves into its image
+    pub fn scene_images(&self) -> Vec<Arc<AttachmentImage>> {
+        self.frames
+            .iter()
+            .map(|frame| frame.scene.clone())
+            .collect()
+    }
+
+    // must be called after the render target was recreated or the logical size changed
+    pub fn resize(&mut self, target: &RenderTarget, letterbox: &Letterbox) -> anyhow::Result<()> {
+        let extent: [u32; 2] = letterbox.logical_size.into();
+        self.frames = (0..self.frames_in_flight)
+            .map(|_| self.create_frame(extent))
+            .collect::<anyhow::Result<_>>()?;
+        self.output_targets = target
+            .create_framebuffers(&self.output_pass, SampleCount::Sample1, None)?
+            .into_iter()
+            .map(|framebuffer| DrawTarget {
+                framebuffer,
+                viewport: Viewport {
+                    origin: letterbox.origin,
+                    dimensions: letterbox.size,
+                    depth_range: 0.0..1.0,
+                },
+            })
+            .collect();
+        self.output_transform = OutputTransform::new(
+            target.image_format(),
+            target.color_space(),
+            self.paper_white,
+        );
+        Ok(())
+    }
+
+    fn create_frame(&self, extent: [u32; 2]) -> anyhow::Result<PostFrame> {
+        let scene = create_image(self.device.clone(), extent)?;
+        let images = (0..2)
+            .map(|_| -> anyhow::Result<PostImage> {
+                let view: Arc<dyn ImageViewAbstract> =
+                    ImageView::new_default(create_image(self.device.clone(), extent)?)?;
+                Ok(PostImage {
+                    target: DrawTarget {
+                        framebuffer: Framebuffer::new(
+                            self.effect_pass.clone(),
+                            FramebufferCreateInfo {
+                                attachments: vec![view.clone()],
+                                ..Default::default()
+                            },
+                        )?,
+                        viewport: full_viewport(extent),
+                    },
+                    view,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(PostFrame {
+            scene_view: ImageView::new_default(scene.clone())?,
+            scene,
+            images,
+            descriptor_sets: HashMap::new(),
+        })
+    }
+
+    // a pipeline whose shader fails to compile keeps being used
+    pub fn shaders_changed(&mut self, changed: &[PathBuf]) {
+        let vertex_changed = changed.contains(&self.shaders.path(VERTEX_SHADER));
+        if vertex_changed || changed.contains(&self.shaders.path(OUTPUT_SHADER)) {
+            match create_pipeline(
+                &self.shaders,
+                &self.pipeline_cache,
+                &self.output_pass,
+                OUTPUT_SHADER,
+            ) {
+                Ok(pipeline) => self.output_pipeline = pipeline,
+                Err(e) => log::error!("Failed to rebuild pipeline after shader reload: {:?}", e),
+            }
+        }
+        let stale = self
+            .pipelines
+            .keys()
+            .copied()
+            .filter(|shader| vertex_changed || changed.contains(&self.shaders.path(shader)))
+            .collect::<Vec<_>>();
+        for shader in stale {
+            match create_pipeline(
+                &self.shaders,
+                &self.pipeline_cache,
+                &self.effect_pass,
+                shader,
+            ) {
+                Ok(pipeline) => {
+                    self.pipelines.insert(shader, pipeline);
+                }
+                Err(e) => log::error!("Failed to rebuild pipeline after shader reload: {:?}", e),
+            }
+        }
+    }
+
+    fn pipeline(&mut self, shader: &'static str) -> anyhow::Result<Arc<GraphicsPipeline>> {
+        if let Some(pipeline) = self.pipelines.get(shader) {
+            return Ok(pipeline.clone());
+        }
+        let pipeline = create_pipeline(
+            &self.shaders,
+            &self.pipeline_cache,
+            &self.effect_pass,
+            shader,
+        )?;
+        self.pipelines.insert(shader, pipeline.clone());
+        Ok(pipeline)
+    }
+
+    // must be called outside of a render pass, after the scene of the frame was rendered
+    pub fn record(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame_index: usize,
+        image_idx: usize,
+        timer: &mut GpuTimer,
+    ) -> anyhow::Result<()> {
+        let mut stages = Vec::new();
+        for index in 0..self.chain.stages.len() {
+            if self.chain.stages[index].enabled {
+                let shader = self.chain.stages[index].effect.shader();
+                stages.push((index, self.pipeline(shader)?));
+            }
+        }
+
+        let mut cached = std::mem::take(&mut self.frames[frame_index].descriptor_sets);
+        let mut used = DescriptorSets::new();
+        let frame = &self.frames[frame_index];
+        let mut input = frame.scene_view.clone();
+        let mut next = 0;
+        for (index, pipeline) in stages {
+            let stage = &self.chain.stages[index];
+            let output = &frame.images[next];
+            let set = self.descriptor_set(
+                &mut cached,
+                &mut used,
+                &pipeline,
+                &input,
+                stage.effect.lut(),
+            )?;
+            timer.scope(builder, &stage.name, |builder| {
+                self.draw(
+                    builder,
+                    &pipeline,
+                    &output.target,
+                    set,
+                    &input,
+                    stage.effect.params(),
+                )
+            })?;
+            input = output.view.clone();
+            next = 1 - next;
+        }
+        let set =
+            self.descriptor_set(&mut cached, &mut used, &self.output_pipeline, &input, None)?;
+        timer.scope(builder, "output", |builder| {
+            self.draw(
+                builder,
+                &self.output_pipeline,
+                &self.output_targets[image_idx],
+                set,
+                &input,
+                self.output_transform.params(),
+            )
+        })?;
+        self.frames[frame_index].descriptor_sets = used;
+        Ok(())
+    }
+
+    // the set sampling `input` at binding 0 and the lut at binding 1, reused from the last
+    // recording of the frame if it had one for the same images
+    fn descriptor_set(
+        &self,
+        cached: &mut DescriptorSets,
+        used: &mut DescriptorSets,
+        pipeline: &Arc<GraphicsPipeline>,
+        input: &Arc<dyn ImageViewAbstract>,
+        lut: Option<&Arc<Texture>>,
+    ) -> anyhow::Result<Arc<PersistentDescriptorSet>> {
+        let layout = &pipeline.layout().set_layouts()[0];
+        let key = SetKey {
+            layout: Arc::as_ptr(layout) as usize,
+            input: Arc::as_ptr(input) as *const () as usize,
+            lut: lut.map(|lut| Arc::as_ptr(&lut.view) as *const () as usize),
+        };
+        if let Some(set) = used.get(&key) {
+            return Ok(set.clone());
+        }
+        let set = match cached.remove(&key) {
+            Some(set) => set,
+            None => {
+                let mut writes = vec![WriteDescriptorSet::image_view_sampler(
+                    0,
+                    input.clone(),
+                    self.sampler.clone(),
+                )];
+                if let Some(lut) = lut {
+                    writes.push(WriteDescriptorSet::image_view_sampler(
+                        1,
+                        lut.view.clone(),
+                        self.sampler.clone(),
+                    ));
+                }
+                PersistentDescriptorSet::new(
+                    &self.descriptor_set_allocator,
+                    layout.clone(),
+                    writes,
+                )?
+            }
+        };
+        used.insert(key, set.clone());
+        Ok(set)
+    }
+
+    // one full-screen triangle sampling `input` through `set`
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pipeline: &Arc<GraphicsPipeline>,
+        target: &DrawTarget,
+        set: Arc<PersistentDescriptorSet>,
+        input: &Arc<dyn ImageViewAbstract>,
+        params: [f32; 4],
+    ) -> anyhow::Result<()> {
+        let input_extent = input.image().dimensions().width_height();
+        let clear_values = target
+            .framebuffer
+            .render_pass()
+            .attachments()
+            .iter()
+            .map(|attachment| {
+                (attachment.load_op == LoadOp::Clear).then(|| [0.0, 0.0, 0.0, 1.0].into())
+            })
+            .collect();
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values,
+                    ..RenderPassBeginInfo::framebuffer(target.framebuffer.clone())
+                },
+                SubpassContents::Inline,
+            )?
+            .set_viewport(0, [target.viewport.clone()])
+            .bind_pipeline_graphics(pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                set,
+            )
+            .push_constants(
+                pipeline.layout().clone(),
+                0,
+                PushConstants {
+                    params,
+                    texel_size: [1.0 / input_extent[0] as f32, 1.0 / input_extent[1] as f32],
+                },
+            )
+            .draw(3, 1, 0, 0)?;
+        builder.end_render_pass()?;
+        Ok(())
+    }
+}
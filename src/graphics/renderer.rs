@@ -0,0 +1,162 @@
+use std::{any::Any, path::PathBuf, sync::Arc};
+
+use vulkano::{
+    buffer::CpuAccessibleBuffer,
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        PrimaryAutoCommandBuffer,
+    },
+    descriptor_set::{allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet},
+    device::{Device, Queue},
+    pipeline::{cache::PipelineCache, Pipeline},
+    render_pass::RenderPass,
+};
+use winit::dpi::PhysicalSize;
+
+use crate::graphics::{
+    shader::ShaderManager,
+    timing::GpuTimer,
+    uniforms::{uniform_set, FrameUniforms},
+    upload::UploadQueue,
+};
+
+// everything a renderer needs to build its pipelines against the main render pass
+#[derive(Clone)]
+pub struct RendererCreateInfo {
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+    pub command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    pub descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    pub render_pass: Arc<RenderPass>,
+    pub pipeline_cache: Arc<PipelineCache>,
+    pub frames_in_flight: usize,
+    pub extent: PhysicalSize<u32>,
+    pub shaders: Arc<ShaderManager>,
+    pub uploads: Arc<UploadQueue>,
+}
+
+pub struct RenderFrame {
+    pub frame_index: usize,
+    pub extent: PhysicalSize<u32>,
+    pub uniforms: FrameUniforms,
+    pub(crate) uniform_buffer: Arc<CpuAccessibleBuffer<FrameUniforms>>,
+    pub descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+}
+
+impl RenderFrame {
+    // the frame uniforms as set 0, for shaders including frame.glsl
+    pub fn uniform_set(
+        &self,
+        pipeline: &impl Pipeline,
+    ) -> anyhow::Result<Arc<PersistentDescriptorSet>> {
+        uniform_set(
+            &self.descriptor_set_allocator,
+            pipeline.layout(),
+            0,
+            self.uniform_buffer.clone(),
+        )
+    }
+}
+
+pub trait Renderer: Any {
+    fn create(info: &RendererCreateInfo) -> anyhow::Result<Self>
+    where
+        Self: Sized;
+
+    // called after the swapchain is recreated
+    fn recreate(&mut self, _info: &RendererCreateInfo) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    // called with the paths of shaders that were recompiled, renderers using one of them should
+    // rebuild their pipelines
+    fn shaders_changed(
+        &mut self,
+        _info: &RendererCreateInfo,
+        _changed: &[PathBuf],
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    // records draw commands inside the main render pass, with the viewport already set
+    fn render(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &RenderFrame,
+    ) -> anyhow::Result<()>;
+
+    // shown in GPU timings
+    fn name(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct RendererId(u64);
+
+// renderers record their commands in insertion order
+pub struct RendererList {
+    renderers: Vec<(RendererId, Box<dyn Renderer>)>,
+    next_id: u64,
+}
+
+impl RendererList {
+    pub fn new() -> Self {
+        Self {
+            renderers: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn push(&mut self, renderer: Box<dyn Renderer>) -> RendererId {
+        let id = RendererId(self.next_id);
+        self.next_id += 1;
+        self.renderers.push((id, renderer));
+        id
+    }
+
+    pub fn remove(&mut self, id: RendererId) -> Option<Box<dyn Renderer>> {
+        let index = self.renderers.iter().position(|(r_id, _)| *r_id == id)?;
+        Some(self.renderers.remove(index).1)
+    }
+
+    pub fn get_mut<R: Renderer>(&mut self, id: RendererId) -> Option<&mut R> {
+        self.renderers
+            .iter_mut()
+            .find(|(r_id, _)| *r_id == id)
+            .and_then(|(_, renderer)| renderer.as_any_mut().downcast_mut())
+    }
+
+    pub fn recreate(&mut self, info: &RendererCreateInfo) -> anyhow::Result<()> {
+        for (_, renderer) in &mut self.renderers {
+            renderer.recreate(info)?;
+        }
+        Ok(())
+    }
+
+    // a renderer whose pipeline fails to build keeps drawing with its previous one
+    pub fn shaders_changed(&mut self, info: &RendererCreateInfo, changed: &[PathBuf]) {
+        for (_, renderer) in &mut self.renderers {
+            if let Err(e) = renderer.shaders_changed(info, changed) {
+                log::error!("Failed to rebuild pipeline after shader reload: {:?}", e);
+            }
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &RenderFrame,
+        timer: &mut GpuTimer,
+    ) -> anyhow::Result<()> {
+        for (_, renderer) in &mut self.renderers {
+            timer.scope(builder, renderer.name(), |builder| {
+                renderer.render(builder, frame)
+            })?;
+        }
+        Ok(())
+    }
+}
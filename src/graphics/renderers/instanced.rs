@@ -0,0 +1,429 @@
+use std::{any::Any, collections::HashMap, path::PathBuf, sync::Arc};
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer, DeviceLocalBuffer, TypedBufferAccess},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    image::SampleCount,
+    impl_vertex,
+    pipeline::{
+        graphics::{
+            color_blend::ColorBlendState, depth_stencil::DepthStencilState,
+            input_assembly::InputAssemblyState, multisample::MultisampleState,
+            vertex_input::BuffersDefinition, viewport::ViewportState,
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::Subpass,
+};
+
+use crate::graphics::{
+    camera::Mat4,
+    renderer::{RenderFrame, Renderer, RendererCreateInfo},
+};
+
+const VERTEX_SHADER: &str = "instanced.vert";
+const FRAGMENT_SHADER: &str = "instanced.frag";
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+impl_vertex!(MeshVertex, position, uv);
+
+impl MeshVertex {
+    pub fn new(position: [f32; 3], uv: [f32; 2]) -> Self {
+        Self { position, uv }
+    }
+}
+
+// the transform is passed as four columns, one attribute location each
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub(crate) struct InstanceVertex {
+    transform_0: [f32; 4],
+    transform_1: [f32; 4],
+    transform_2: [f32; 4],
+    transform_3: [f32; 4],
+    color: [f32; 4],
+    params: [f32; 4],
+}
+
+impl_vertex!(
+    InstanceVertex,
+    transform_0,
+    transform_1,
+    transform_2,
+    transform_3,
+    color,
+    params
+);
+
+// one copy of a mesh. colours are linear, `params` are passed to the shaders of the material
+// as they are
+#[derive(Clone, Copy, Debug)]
+pub struct Instance {
+    pub transform: Mat4,
+    pub color: [f32; 4],
+    pub params: [f32; 4],
+}
+
+impl Instance {
+    pub fn new(transform: Mat4) -> Self {
+        Self {
+            transform,
+            color: [1.0; 4],
+            params: [0.0; 4],
+        }
+    }
+
+    pub fn color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn params(mut self, params: [f32; 4]) -> Self {
+        self.params = params;
+        self
+    }
+
+    fn vertex(&self) -> InstanceVertex {
+        let [transform_0, transform_1, transform_2, transform_3] = self.transform;
+        InstanceVertex {
+            transform_0,
+            transform_1,
+            transform_2,
+            transform_3,
+            color: self.color,
+            params: self.params,
+        }
+    }
+}
+
+// the shaders an instance is drawn with. custom shaders get the same vertex inputs as
+// instanced.vert, and the frame uniforms as set 0 if they include frame.glsl
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Material {
+    pub vertex_shader: String,
+    pub fragment_shader: String,
+    // alpha blending, instances are drawn in submission order within a batch
+    pub blend: bool,
+}
+
+impl Material {
+    pub fn new() -> Self {
+        Self {
+            vertex_shader: VERTEX_SHADER.to_string(),
+            fragment_shader: FRAGMENT_SHADER.to_string(),
+            blend: false,
+        }
+    }
+
+    pub fn shaders(mut self, vertex: impl Into<String>, fragment: impl Into<String>) -> Self {
+        self.vertex_shader = vertex.into();
+        self.fragment_shader = fragment.into();
+        self
+    }
+
+    pub fn blend(mut self, blend: bool) -> Self {
+        self.blend = blend;
+        self
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct MeshId(usize);
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct MaterialId(usize);
+
+struct Mesh {
+    vertex_buffer: Arc<DeviceLocalBuffer<[MeshVertex]>>,
+    index_buffer: Option<Arc<DeviceLocalBuffer<[u32]>>>,
+}
+
+struct MaterialPipeline {
+    material: Material,
+    pipeline: Arc<GraphicsPipeline>,
+}
+
+struct Batch {
+    mesh: MeshId,
+    material: MaterialId,
+    instances: Vec<InstanceVertex>,
+}
+
+// draws many copies of the same meshes, with one draw call per mesh and material. instances
+// are collected during the frame and written into a per-frame instance buffer when rendering
+pub struct InstancedRenderer {
+    info: RendererCreateInfo,
+    meshes: Vec<Mesh>,
+    materials: Vec<MaterialPipeline>,
+    batches: Vec<Batch>,
+    batch_index: HashMap<(MeshId, MaterialId), usize>,
+    instance_buffers: Vec<Option<Arc<CpuAccessibleBuffer<[InstanceVertex]>>>>,
+    quad: MeshId,
+    default_material: MaterialId,
+}
+
+impl InstancedRenderer {
+    fn create_pipeline(
+        info: &RendererCreateInfo,
+        material: &Material,
+    ) -> anyhow::Result<Arc<GraphicsPipeline>> {
+        let subpass = Subpass::from(info.render_pass.clone(), 0).unwrap();
+        let vs = info.shaders.module(&material.vertex_shader)?;
+        let fs = info.shaders.module(&material.fragment_shader)?;
+        let color_blend_state = if material.blend {
+            ColorBlendState::new(1).blend_alpha()
+        } else {
+            ColorBlendState::new(1)
+        };
+        Ok(GraphicsPipeline::start()
+            .multisample_state(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                ..Default::default()
+            })
+            .depth_stencil_state(if subpass.has_depth() {
+                DepthStencilState::simple_depth_test()
+            } else {
+                DepthStencilState::disabled()
+            })
+            .color_blend_state(color_blend_state)
+            .render_pass(subpass)
+            .vertex_input_state(
+                BuffersDefinition::new()
+                    .vertex::<MeshVertex>()
+                    .instance::<InstanceVertex>(),
+            )
+            .input_assembly_state(InputAssemblyState::new())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .build_with_cache(info.pipeline_cache.clone())
+            .build(info.device.clone())?)
+    }
+
+    // triangle lists, drawn without an index buffer if `indices` is empty. the mesh is uploaded
+    // into device-local memory at the start of the next frame
+    pub fn add_mesh(&mut self, vertices: &[MeshVertex], indices: &[u32]) -> anyhow::Result<MeshId> {
+        let vertex_buffer = self.info.uploads.buffer(
+            BufferUsage {
+                vertex_buffer: true,
+                ..BufferUsage::empty()
+            },
+            vertices,
+        )?;
+        let index_buffer = if indices.is_empty() {
+            None
+        } else {
+            Some(self.info.uploads.buffer(
+                BufferUsage {
+                    index_buffer: true,
+                    ..BufferUsage::empty()
+                },
+                indices,
+            )?)
+        };
+        self.meshes.push(Mesh {
+            vertex_buffer,
+            index_buffer,
+        });
+        Ok(MeshId(self.meshes.len() - 1))
+    }
+
+    // materials with the same shaders and blending share a pipeline. shaders that were not
+    // passed to `ShaderManager::precompile` are compiled on the calling thread
+    pub fn add_material(&mut self, material: Material) -> anyhow::Result<MaterialId> {
+        if let Some(index) = self.materials.iter().position(|m| m.material == material) {
+            return Ok(MaterialId(index));
+        }
+        let pipeline = Self::create_pipeline(&self.info, &material)?;
+        self.materials.push(MaterialPipeline { material, pipeline });
+        Ok(MaterialId(self.materials.len() - 1))
+    }
+
+    // a unit square in the xy plane centred on the origin, uv 0,0 at the top left corner
+    pub fn quad(&self) -> MeshId {
+        self.quad
+    }
+
+    pub fn default_material(&self) -> MaterialId {
+        self.default_material
+    }
+
+    pub fn draw(&mut self, mesh: MeshId, material: MaterialId, instance: Instance) {
+        self.batch(mesh, material).push(instance.vertex());
+    }
+
+    pub fn draw_all(
+        &mut self,
+        mesh: MeshId,
+        material: MaterialId,
+        instances: impl IntoIterator<Item = Instance>,
+    ) {
+        self.batch(mesh, material)
+            .extend(instances.into_iter().map(|instance| instance.vertex()));
+    }
+
+    // batches are drawn in the order they were first drawn to
+    fn batch(&mut self, mesh: MeshId, material: MaterialId) -> &mut Vec<InstanceVertex> {
+        let batches = &mut self.batches;
+        let index = *self.batch_index.entry((mesh, material)).or_insert_with(|| {
+            batches.push(Batch {
+                mesh,
+                material,
+                instances: Vec::new(),
+            });
+            batches.len() - 1
+        });
+        &mut self.batches[index].instances
+    }
+
+    fn instance_buffer(
+        &mut self,
+        frame_index: usize,
+        count: usize,
+    ) -> anyhow::Result<Arc<CpuAccessibleBuffer<[InstanceVertex]>>> {
+        let instances = self
+            .batches
+            .iter()
+            .flat_map(|batch| batch.instances.iter().copied());
+        let slot = &mut self.instance_buffers[frame_index];
+        let reusable = matches!(slot, Some(buffer) if buffer.len() >= count as u64);
+        if reusable {
+            let buffer = slot.as_ref().unwrap();
+            let mut contents = buffer.write()?;
+            for (dst, src) in contents.iter_mut().zip(instances) {
+                *dst = src;
+            }
+        } else {
+            // grow to the next power of two so the buffer is not reallocated every frame
+            let capacity = count.next_power_of_two();
+            *slot = Some(CpuAccessibleBuffer::from_iter(
+                self.info.device.clone(),
+                BufferUsage {
+                    vertex_buffer: true,
+                    ..BufferUsage::empty()
+                },
+                false,
+                instances
+                    .chain(std::iter::repeat(InstanceVertex::default()))
+                    .take(capacity)
+                    .collect::<Vec<_>>(),
+            )?);
+        }
+        Ok(slot.clone().unwrap())
+    }
+}
+
+impl Renderer for InstancedRenderer {
+    fn create(info: &RendererCreateInfo) -> anyhow::Result<Self> {
+        let mut renderer = Self {
+            info: info.clone(),
+            meshes: Vec::new(),
+            materials: Vec::new(),
+            batches: Vec::new(),
+            batch_index: HashMap::new(),
+            instance_buffers: vec![None; info.frames_in_flight],
+            quad: MeshId(0),
+            default_material: MaterialId(0),
+        };
+        renderer.quad = renderer.add_mesh(
+            &[
+                MeshVertex::new([-0.5, 0.5, 0.0], [0.0, 0.0]),
+                MeshVertex::new([-0.5, -0.5, 0.0], [0.0, 1.0]),
+                MeshVertex::new([0.5, -0.5, 0.0], [1.0, 1.0]),
+                MeshVertex::new([0.5, 0.5, 0.0], [1.0, 0.0]),
+            ],
+            &[0, 1, 2, 2, 3, 0],
+        )?;
+        renderer.default_material = renderer.add_material(Material::new())?;
+        Ok(renderer)
+    }
+
+    fn recreate(&mut self, info: &RendererCreateInfo) -> anyhow::Result<()> {
+        self.info = info.clone();
+        Ok(())
+    }
+
+    fn shaders_changed(
+        &mut self,
+        info: &RendererCreateInfo,
+        changed: &[PathBuf],
+    ) -> anyhow::Result<()> {
+        self.info = info.clone();
+        for material in &mut self.materials {
+            if changed.contains(&info.shaders.path(&material.material.vertex_shader))
+                || changed.contains(&info.shaders.path(&material.material.fragment_shader))
+            {
+                material.pipeline = Self::create_pipeline(info, &material.material)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn render(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &RenderFrame,
+    ) -> anyhow::Result<()> {
+        let count = self.batches.iter().map(|batch| batch.instances.len()).sum();
+        if count == 0 {
+            return Ok(());
+        }
+        let instance_buffer = self.instance_buffer(frame.frame_index, count)?;
+
+        let mut first = 0;
+        for batch in &self.batches {
+            let instance_count = batch.instances.len() as u32;
+            if instance_count == 0 {
+                continue;
+            }
+            let mesh = &self.meshes[batch.mesh.0];
+            let pipeline = &self.materials[batch.material.0].pipeline;
+            builder
+                .bind_pipeline_graphics(pipeline.clone())
+                .bind_vertex_buffers(0, (mesh.vertex_buffer.clone(), instance_buffer.clone()));
+            // custom shaders are not required to use the frame uniforms
+            if !pipeline.layout().set_layouts().is_empty() {
+                builder.bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    0,
+                    frame.uniform_set(pipeline.as_ref())?,
+                );
+            }
+            match &mesh.index_buffer {
+                Some(index_buffer) => {
+                    builder
+                        .bind_index_buffer(index_buffer.clone())
+                        .draw_indexed(index_buffer.len() as u32, instance_count, 0, 0, first)?;
+                }
+                None => {
+                    builder.draw(mesh.vertex_buffer.len() as u32, instance_count, 0, first)?;
+                }
+            }
+            first += instance_count;
+        }
+
+        // batches keep their allocations for the next frame
+        for batch in &mut self.batches {
+            batch.instances.clear();
+        }
+        Ok(())
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -1 +1,3 @@
-pub mod triangle;
\ No newline at end of file
+pub mod instanced;
+pub mod sprite;
+pub mod triangle;
@@ -0,0 +1,366 @@
+use std::{any::Any, collections::HashMap, path::PathBuf, sync::Arc};
+
+use crate::graphics::{
+    renderer::{RenderFrame, Renderer, RendererCreateInfo},
+    texture::TextureRegion,
+};
+use bytemuck::{Pod, Zeroable};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer, TypedBufferAccess},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer,
+        PrimaryCommandBufferAbstract,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::DeviceOwned,
+    format::Format,
+    image::{
+        view::ImageView, ImageDimensions, ImageViewAbstract, ImmutableImage, MipmapsCount,
+        SampleCount,
+    },
+    impl_vertex,
+    pipeline::{
+        graphics::{
+            color_blend::ColorBlendState,
+            depth_stencil::DepthStencilState,
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            vertex_input::BuffersDefinition,
+            viewport::ViewportState,
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::{RenderPass, Subpass},
+    sampler::{
+        Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode, LOD_CLAMP_NONE,
+    },
+    sync::GpuFuture,
+};
+
+const VERTEX_SHADER: &str = "sprite.vert";
+const FRAGMENT_SHADER: &str = "sprite.frag";
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+struct PushConstants {
+    screen_size: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub(crate) struct SpriteInstance {
+    position: [f32; 2],
+    size: [f32; 2],
+    rotation: f32,
+    uv: [f32; 4],
+    color: [f32; 4],
+}
+
+impl_vertex!(SpriteInstance, position, size, rotation, uv, color);
+
+// positions and sizes are in pixels, with the origin at the top left corner of the viewport.
+// colours are linear, textures are decoded from sRGB when sampled
+#[derive(Clone)]
+pub struct Sprite {
+    pub texture: Option<Arc<dyn ImageViewAbstract>>,
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    pub rotation: f32,
+    pub uv: [f32; 4],
+    pub color: [f32; 4],
+    pub layer: i32,
+}
+
+impl Sprite {
+    pub fn new(position: [f32; 2], size: [f32; 2]) -> Self {
+        Self {
+            texture: None,
+            position,
+            size,
+            rotation: 0.0,
+            uv: [0.0, 0.0, 1.0, 1.0],
+            color: [1.0; 4],
+            layer: 0,
+        }
+    }
+
+    pub fn texture(mut self, texture: Arc<dyn ImageViewAbstract>) -> Self {
+        self.texture = Some(texture);
+        self
+    }
+
+    pub fn region(mut self, region: &TextureRegion) -> Self {
+        self.texture = Some(region.texture.view.clone());
+        self.uv = region.uv;
+        self
+    }
+
+    pub fn rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn uv(mut self, uv: [f32; 4]) -> Self {
+        self.uv = uv;
+        self
+    }
+
+    pub fn color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    fn instance(&self) -> SpriteInstance {
+        SpriteInstance {
+            position: self.position,
+            size: self.size,
+            rotation: self.rotation,
+            uv: self.uv,
+            color: self.color,
+        }
+    }
+}
+
+fn texture_key(texture: &Arc<dyn ImageViewAbstract>) -> usize {
+    Arc::as_ptr(texture) as *const () as usize
+}
+
+pub struct SpriteRenderer {
+    pub(crate) pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    white_texture: Arc<dyn ImageViewAbstract>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    descriptor_sets: HashMap<usize, (Arc<dyn ImageViewAbstract>, Arc<PersistentDescriptorSet>)>,
+    instance_buffers: Vec<Option<Arc<CpuAccessibleBuffer<[SpriteInstance]>>>>,
+    sprites: Vec<Sprite>,
+}
+
+impl SpriteRenderer {
+    fn create_pipeline(info: &RendererCreateInfo) -> anyhow::Result<Arc<GraphicsPipeline>> {
+        let device = info.device.clone();
+        let subpass = Subpass::from(info.render_pass.clone(), 0).unwrap();
+        let vs = info.shaders.module(VERTEX_SHADER)?;
+        let fs = info.shaders.module(FRAGMENT_SHADER)?;
+        Ok(GraphicsPipeline::start()
+            .multisample_state(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                ..Default::default()
+            })
+            .depth_stencil_state(DepthStencilState::disabled())
+            .color_blend_state(ColorBlendState::new(1).blend_alpha())
+            .render_pass(subpass)
+            .vertex_input_state(BuffersDefinition::new().instance::<SpriteInstance>())
+            .input_assembly_state(
+                InputAssemblyState::new().topology(PrimitiveTopology::TriangleStrip),
+            )
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .build_with_cache(info.pipeline_cache.clone())
+            .build(device)?)
+    }
+
+    pub fn draw(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    pub fn draw_all(&mut self, sprites: impl IntoIterator<Item = Sprite>) {
+        self.sprites.extend(sprites);
+    }
+
+    fn instance_buffer(
+        &mut self,
+        frame_index: usize,
+    ) -> anyhow::Result<Arc<CpuAccessibleBuffer<[SpriteInstance]>>> {
+        let instances = self.sprites.iter().map(Sprite::instance);
+        let slot = &mut self.instance_buffers[frame_index];
+        let reusable = matches!(slot, Some(buffer) if buffer.len() >= self.sprites.len() as u64);
+        if reusable {
+            let buffer = slot.as_ref().unwrap();
+            let mut contents = buffer.write()?;
+            for (dst, src) in contents.iter_mut().zip(instances) {
+                *dst = src;
+            }
+        } else {
+            // grow to the next power of two so the buffer is not reallocated every frame
+            let capacity = self.sprites.len().next_power_of_two();
+            *slot = Some(CpuAccessibleBuffer::from_iter(
+                self.pipeline.device().clone(),
+                BufferUsage {
+                    vertex_buffer: true,
+                    ..BufferUsage::empty()
+                },
+                false,
+                instances
+                    .chain(std::iter::repeat(SpriteInstance::default()))
+                    .take(capacity)
+                    .collect::<Vec<_>>(),
+            )?);
+        }
+        Ok(slot.clone().unwrap())
+    }
+
+    fn descriptor_set(
+        &mut self,
+        texture: &Arc<dyn ImageViewAbstract>,
+    ) -> anyhow::Result<Arc<PersistentDescriptorSet>> {
+        let key = texture_key(texture);
+        if let Some((_, set)) = self.descriptor_sets.get(&key) {
+            return Ok(set.clone());
+        }
+        let set = PersistentDescriptorSet::new(
+            self.descriptor_set_allocator.as_ref(),
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                texture.clone(),
+                self.sampler.clone(),
+            )],
+        )?;
+        self.descriptor_sets
+            .insert(key, (texture.clone(), set.clone()));
+        Ok(set)
+    }
+}
+
+impl Renderer for SpriteRenderer {
+    fn create(info: &RendererCreateInfo) -> anyhow::Result<Self> {
+        let queue = info.queue.clone();
+        let pipeline = Self::create_pipeline(info)?;
+        let sampler = Sampler::new(
+            info.device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                mipmap_mode: SamplerMipmapMode::Linear,
+                lod: 0.0..=LOD_CLAMP_NONE,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )?;
+
+        let mut uploads = AutoCommandBufferBuilder::primary(
+            info.command_buffer_allocator.as_ref(),
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        let white_image = ImmutableImage::from_iter(
+            [255u8; 4],
+            ImageDimensions::Dim2d {
+                width: 1,
+                height: 1,
+                array_layers: 1,
+            },
+            MipmapsCount::One,
+            Format::R8G8B8A8_UNORM,
+            &mut uploads,
+        )?;
+        uploads
+            .build()?
+            .execute(queue)?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+
+        Ok(Self {
+            pipeline,
+            sampler,
+            white_texture: ImageView::new_default(white_image)?,
+            descriptor_set_allocator: info.descriptor_set_allocator.clone(),
+            descriptor_sets: HashMap::new(),
+            instance_buffers: vec![None; info.frames_in_flight],
+            sprites: Vec::new(),
+        })
+    }
+
+    fn shaders_changed(
+        &mut self,
+        info: &RendererCreateInfo,
+        changed: &[PathBuf],
+    ) -> anyhow::Result<()> {
+        if changed.contains(&info.shaders.path(VERTEX_SHADER))
+            || changed.contains(&info.shaders.path(FRAGMENT_SHADER))
+        {
+            self.pipeline = Self::create_pipeline(info)?;
+            // cached sets were allocated against the old pipeline layout
+            self.descriptor_sets.clear();
+        }
+        Ok(())
+    }
+
+    // sprites are drawn in layer order, and sprites sharing a texture within a layer are drawn
+    // with a single instanced draw call
+    fn render(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &RenderFrame,
+    ) -> anyhow::Result<()> {
+        if self.sprites.is_empty() {
+            self.descriptor_sets.clear();
+            return Ok(());
+        }
+
+        let white_texture = self.white_texture.clone();
+        self.sprites.sort_by_key(|sprite| {
+            (
+                sprite.layer,
+                texture_key(sprite.texture.as_ref().unwrap_or(&white_texture)),
+            )
+        });
+        let instance_buffer = self.instance_buffer(frame.frame_index)?;
+
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                PushConstants {
+                    screen_size: [frame.extent.width as f32, frame.extent.height as f32],
+                },
+            )
+            .bind_vertex_buffers(0, instance_buffer);
+
+        let mut used_textures = HashMap::new();
+        let mut first = 0;
+        while first < self.sprites.len() {
+            let texture = self.sprites[first]
+                .texture
+                .clone()
+                .unwrap_or_else(|| white_texture.clone());
+            let key = texture_key(&texture);
+            let count = self.sprites[first..]
+                .iter()
+                .take_while(|sprite| {
+                    texture_key(sprite.texture.as_ref().unwrap_or(&white_texture)) == key
+                        && sprite.layer == self.sprites[first].layer
+                })
+                .count();
+            let set = self.descriptor_set(&texture)?;
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    self.pipeline.layout().clone(),
+                    0,
+                    set.clone(),
+                )
+                .draw(4, count as u32, 0, first as u32)?;
+            used_textures.insert(key, (texture, set));
+            first += count;
+        }
+
+        // only keep descriptor sets of textures that are still in use
+        self.descriptor_sets = used_textures;
+        self.sprites.clear();
+        Ok(())
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -1,47 +1,30 @@
-use std::sync::Arc;
+use std::{any::Any, path::PathBuf, sync::Arc};
 
 use bytemuck::{Pod, Zeroable};
 use vulkano::{
-    buffer::{BufferUsage, CpuAccessibleBuffer},
-    device::Device,
+    buffer::{BufferUsage, DeviceLocalBuffer, TypedBufferAccess},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    image::SampleCount,
     impl_vertex,
     pipeline::{
         graphics::{
-            input_assembly::InputAssemblyState, vertex_input::BuffersDefinition,
+            depth_stencil::DepthStencilState, input_assembly::InputAssemblyState,
+            multisample::MultisampleState, vertex_input::BuffersDefinition,
             viewport::ViewportState,
         },
-        GraphicsPipeline,
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
     },
-    render_pass::{RenderPass, Subpass},
+    render_pass::Subpass,
 };
 
-mod vs {
-    vulkano_shaders::shader! {
-        ty: "vertex",
-        src: "
-        #version 450
-        layout (location = 0) in vec2 position;
-        void main() {
-            gl_Position = vec4(position, 0.0, 1.0);
-        }
-        "
-    }
-}
-
-mod fs {
-    vulkano_shaders::shader! {
-        ty: "fragment",
-        src: "
-        #version 450
+use crate::graphics::{
+    camera::{Mat4, IDENTITY},
+    renderer::{RenderFrame, Renderer, RendererCreateInfo},
+    uniforms::UniformPool,
+};
 
-        layout (location = 0) out vec4 color;
-
-        void main() {
-            color = vec4(1.0);
-        }
-        "
-    }
-}
+const VERTEX_SHADER: &str = "triangle.vert";
+const FRAGMENT_SHADER: &str = "triangle.frag";
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
@@ -51,30 +34,60 @@ pub(crate) struct Vertex {
 
 impl_vertex!(Vertex, position);
 
+// the `Draw` block of the triangle shaders
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Zeroable, Pod)]
+struct DrawUniforms {
+    model: Mat4,
+    color: [f32; 4],
+}
+
+// draws a triangle in world space, seen through the camera of the context
 pub struct TriangleRenderer {
     pub(crate) pipeline: Arc<GraphicsPipeline>,
-    pub(crate) vertex_buffer: Arc<CpuAccessibleBuffer<[Vertex]>>,
+    pub(crate) vertex_buffer: Arc<DeviceLocalBuffer<[Vertex]>>,
+    draw_uniforms: UniformPool<DrawUniforms>,
+    pub transform: Mat4,
+    // linear
+    pub color: [f32; 4],
 }
 
 impl TriangleRenderer {
-    pub fn new(device: Arc<Device>, render_pass: Arc<RenderPass>) -> anyhow::Result<Self> {
+    fn create_pipeline(info: &RendererCreateInfo) -> anyhow::Result<Arc<GraphicsPipeline>> {
+        let subpass = Subpass::from(info.render_pass.clone(), 0).unwrap();
+        let vs = info.shaders.module(VERTEX_SHADER)?;
+        let fs = info.shaders.module(FRAGMENT_SHADER)?;
+        Ok(GraphicsPipeline::start()
+            .multisample_state(MultisampleState {
+                rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                ..Default::default()
+            })
+            .depth_stencil_state(if subpass.has_depth() {
+                DepthStencilState::simple_depth_test()
+            } else {
+                DepthStencilState::disabled()
+            })
+            .render_pass(subpass)
+            .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
+            .input_assembly_state(InputAssemblyState::new())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .build_with_cache(info.pipeline_cache.clone())
+            .build(info.device.clone())?)
+    }
+}
+
+impl Renderer for TriangleRenderer {
+    fn create(info: &RendererCreateInfo) -> anyhow::Result<Self> {
         Ok(Self {
-            pipeline: GraphicsPipeline::start()
-                .render_pass(Subpass::from(render_pass, 0).unwrap())
-                .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
-                .input_assembly_state(InputAssemblyState::new())
-                .vertex_shader(vs::load(device.clone())?.entry_point("main").unwrap(), ())
-                .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-                .fragment_shader(fs::load(device.clone())?.entry_point("main").unwrap(), ())
-                .build(device.clone())?,
-            vertex_buffer: CpuAccessibleBuffer::from_iter(
-                device,
+            pipeline: Self::create_pipeline(info)?,
+            vertex_buffer: info.uploads.buffer(
                 BufferUsage {
                     vertex_buffer: true,
                     ..BufferUsage::empty()
                 },
-                false,
-                [
+                &[
                     Vertex {
                         position: [-0.5, -0.25],
                     },
@@ -86,6 +99,53 @@ impl TriangleRenderer {
                     },
                 ],
             )?,
+            draw_uniforms: UniformPool::new(info.device.clone()),
+            transform: IDENTITY,
+            color: [1.0; 4],
         })
     }
+
+    fn shaders_changed(
+        &mut self,
+        info: &RendererCreateInfo,
+        changed: &[PathBuf],
+    ) -> anyhow::Result<()> {
+        if changed.contains(&info.shaders.path(VERTEX_SHADER))
+            || changed.contains(&info.shaders.path(FRAGMENT_SHADER))
+        {
+            self.pipeline = Self::create_pipeline(info)?;
+        }
+        Ok(())
+    }
+
+    fn render(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &RenderFrame,
+    ) -> anyhow::Result<()> {
+        let draw_set = self.draw_uniforms.descriptor_set(
+            &frame.descriptor_set_allocator,
+            self.pipeline.as_ref(),
+            1,
+            DrawUniforms {
+                model: self.transform,
+                color: self.color,
+            },
+        )?;
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                vec![frame.uniform_set(self.pipeline.as_ref())?, draw_set],
+            )
+            .bind_vertex_buffers(0, self.vertex_buffer.clone())
+            .draw(self.vertex_buffer.len() as u32, 1, 0, 0)?;
+        Ok(())
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
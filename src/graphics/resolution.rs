@@ -0,0 +1,88 @@
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResolutionMode {
+    // the scene is rendered at the size of the window
+    Window,
+    // the scene is rendered at a fixed logical size and scaled to fit the window, keeping its
+    // aspect ratio with bars on the sides or at the top and bottom
+    Fixed(PhysicalSize<u32>),
+    // the scene is rendered at a fraction of the window size and scaled to fill it
+    Scaled(f32),
+}
+
+// the logical size the scene is rendered at and the rectangle of the window it is shown in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Letterbox {
+    pub logical_size: PhysicalSize<u32>,
+    pub window_size: PhysicalSize<u32>,
+    // top left corner and size of the scene in window pixels
+    pub origin: [f32; 2],
+    pub size: [f32; 2],
+}
+
+impl Letterbox {
+    pub fn new(mode: ResolutionMode, window_size: PhysicalSize<u32>) -> Self {
+        let window = [
+            window_size.width.max(1) as f32,
+            window_size.height.max(1) as f32,
+        ];
+        let (logical_size, size) = match mode {
+            ResolutionMode::Window => (window_size, window),
+            ResolutionMode::Fixed(logical_size) => {
+                let logical = [
+                    logical_size.width.max(1) as f32,
+                    logical_size.height.max(1) as f32,
+                ];
+                let scale = (window[0] / logical[0]).min(window[1] / logical[1]);
+                (
+                    logical_size,
+                    [
+                        (logical[0] * scale).round().max(1.0),
+                        (logical[1] * scale).round().max(1.0),
+                    ],
+                )
+            }
+            ResolutionMode::Scaled(scale) => (
+                PhysicalSize::new(
+                    ((window[0] * scale).round() as u32).max(1),
+                    ((window[1] * scale).round() as u32).max(1),
+                ),
+                window,
+            ),
+        };
+        Self {
+            logical_size,
+            window_size,
+            // whole pixels, so the edges of the scene stay sharp
+            origin: [
+                ((window[0] - size[0]) / 2.0).floor(),
+                ((window[1] - size[1]) / 2.0).floor(),
+            ],
+            size,
+        }
+    }
+
+    // None if the position is on one of the bars
+    pub fn window_to_logical(&self, position: PhysicalPosition<f64>) -> Option<[f32; 2]> {
+        let [x, y] = self.window_to_logical_unclamped(position);
+        let inside = (0.0..=self.logical_size.width as f32).contains(&x)
+            && (0.0..=self.logical_size.height as f32).contains(&y);
+        inside.then_some([x, y])
+    }
+
+    // positions on the bars map to coordinates outside of the logical size
+    pub fn window_to_logical_unclamped(&self, position: PhysicalPosition<f64>) -> [f32; 2] {
+        [
+            (position.x as f32 - self.origin[0]) / self.size[0] * self.logical_size.width as f32,
+            (position.y as f32 - self.origin[1]) / self.size[1] * self.logical_size.height as f32,
+        ]
+    }
+
+    pub fn logical_to_window(&self, position: [f32; 2]) -> PhysicalPosition<f64> {
+        PhysicalPosition::new(
+            (self.origin[0] + position[0] / self.logical_size.width as f32 * self.size[0]) as f64,
+            (self.origin[1] + position[1] / self.logical_size.height as f32 * self.size[1]) as f64,
+        )
+    }
+}
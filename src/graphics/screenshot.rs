@@ -0,0 +1,102 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use vulkano::{buffer::CpuAccessibleBuffer, format::Format};
+
+pub type ScreenshotCallback = Box<dyn FnOnce(Screenshot) + Send>;
+
+// a rendered frame in tightly packed RGBA8
+#[derive(Clone)]
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    // the formats `from_raw` can read, HDR formats are not converted
+    pub fn supports_format(format: Format) -> bool {
+        matches!(
+            format,
+            Format::R8G8B8A8_UNORM
+                | Format::R8G8B8A8_SRGB
+                | Format::B8G8R8A8_UNORM
+                | Format::B8G8R8A8_SRGB
+        )
+    }
+
+    pub fn from_raw(format: Format, width: u32, height: u32, data: &[u8]) -> anyhow::Result<Self> {
+        if !Self::supports_format(format) {
+            return Err(anyhow!("Unsupported screenshot format {:?}", format));
+        }
+        let mut pixels = data.to_vec();
+        if let Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB = format {
+            pixels
+                .chunks_exact_mut(4)
+                .for_each(|pixel| pixel.swap(0, 2));
+        }
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let offset = (y * self.width + x) as usize * 4;
+        self.pixels[offset..offset + 4].try_into().unwrap()
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Unable to create {}", dir.display()))?;
+        }
+        image::save_buffer_with_format(
+            path,
+            &self.pixels,
+            self.width,
+            self.height,
+            image::ColorType::Rgba8,
+            image::ImageFormat::Png,
+        )
+        .with_context(|| format!("Unable to write {}", path.display()))
+    }
+}
+
+// screenshots/<milliseconds since the epoch>.png
+pub fn default_screenshot_path() -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis());
+    PathBuf::from("screenshots").join(format!("{}.png", millis))
+}
+
+// a copy recorded into a frame's command buffer, read back once the frame's fence is signaled
+pub(crate) struct PendingCapture {
+    pub(crate) buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+    pub(crate) format: Format,
+    pub(crate) extent: [u32; 2],
+    pub(crate) callbacks: Vec<ScreenshotCallback>,
+}
+
+impl PendingCapture {
+    pub(crate) fn finish(self) -> anyhow::Result<()> {
+        let data = self.buffer.read()?;
+        let screenshot = Screenshot::from_raw(self.format, self.extent[0], self.extent[1], &data)?;
+        let mut callbacks = self.callbacks;
+        let last = callbacks.pop();
+        for callback in callbacks {
+            callback(screenshot.clone());
+        }
+        if let Some(callback) = last {
+            callback(screenshot);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,314 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use shaderc::{CompileOptions, Compiler, IncludeType, ResolvedInclude, ShaderKind};
+use vulkano::{device::Device, shader::ShaderModule};
+
+use crate::{utils::watch::FileWatcher, vfs::Vfs};
+
+// editors usually write a file in several steps, so changes are collected for a short while
+// before recompiling
+const RELOAD_DELAY: Duration = Duration::from_millis(100);
+
+type CompileResult = (PathBuf, anyhow::Result<Vec<u32>>);
+
+// the files each shader included the last time it was compiled
+type Includes = Arc<Mutex<HashMap<PathBuf, Vec<PathBuf>>>>;
+
+// loads GLSL and SPIR-V shaders from a directory of a virtual file system at runtime, and
+// recompiles them on a background thread when their files in a mounted directory change, or any
+// file they include. shaders in archives are compiled once
+pub struct ShaderManager {
+    device: Arc<Device>,
+    vfs: Vfs,
+    root: PathBuf,
+    modules: Mutex<HashMap<PathBuf, Arc<ShaderModule>>>,
+    includes: Includes,
+    results: Mutex<Receiver<CompileResult>>,
+    _watchers: Vec<FileWatcher>,
+}
+
+impl ShaderManager {
+    // `root` is the path of the shader directory in `vfs`
+    pub fn new(device: Arc<Device>, vfs: Vfs, root: impl AsRef<Path>) -> Self {
+        let root = normalize(root.as_ref());
+        let (request_sender, request_receiver) = mpsc::channel::<PathBuf>();
+        let (result_sender, result_receiver) = mpsc::channel();
+        let includes = Includes::default();
+
+        // the thread exits once the watchers, and with them the request sender, are dropped
+        let thread_includes = includes.clone();
+        let thread_vfs = vfs.clone();
+        thread::Builder::new()
+            .name("shader compiler".into())
+            .spawn(move || {
+                while let Ok(path) = request_receiver.recv() {
+                    thread::sleep(RELOAD_DELAY);
+                    let mut paths = vec![path];
+                    paths.extend(request_receiver.try_iter());
+                    let paths = affected_shaders(&thread_includes, paths);
+                    for path in paths {
+                        let result = compile_tracked(&thread_vfs, &thread_includes, &path);
+                        if result_sender.send((path, result)).is_err() {
+                            return;
+                        }
+                    }
+                }
+            })
+            .expect("failed to spawn the shader compiler thread");
+
+        // included files have no fixed extension, so every change is passed on
+        let watchers = vfs
+            .directories()
+            .into_iter()
+            .filter_map(|(point, directory)| {
+                watch(request_sender.clone(), point, &directory)
+                    .map_err(|e| {
+                        log::warn!(
+                            "Shader hot reload disabled for {}: {}",
+                            directory.display(),
+                            e
+                        )
+                    })
+                    .ok()
+            })
+            .collect();
+
+        Self {
+            device,
+            vfs,
+            root,
+            modules: Mutex::new(HashMap::new()),
+            includes,
+            results: Mutex::new(result_receiver),
+            _watchers: watchers,
+        }
+    }
+
+    // path of a shader in the virtual file system, as reported by `poll`
+    pub fn path(&self, name: &str) -> PathBuf {
+        normalize(&self.root.join(name))
+    }
+
+    // compiles the shader on the calling thread on first use unless it was precompiled, later
+    // calls return the latest successfully compiled module
+    pub fn module(&self, name: &str) -> anyhow::Result<Arc<ShaderModule>> {
+        let path = self.path(name);
+        if let Some(module) = self.modules.lock().unwrap().get(&path) {
+            return Ok(module.clone());
+        }
+        let module = self.create_module(&compile_tracked(&self.vfs, &self.includes, &path)?)?;
+        self.modules.lock().unwrap().insert(path, module.clone());
+        Ok(module)
+    }
+
+    // compiles shaders in parallel ahead of their first use, e.g. at startup, so that `module`
+    // does not compile them later while a frame is recorded. shaders that fail to compile are
+    // left for `module` to report
+    pub fn precompile<'a>(&self, names: impl IntoIterator<Item = &'a str>) {
+        let mut paths = names
+            .into_iter()
+            .map(|name| self.path(name))
+            .filter(|path| !self.modules.lock().unwrap().contains_key(path))
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths.dedup();
+        let (vfs, includes) = (&self.vfs, &self.includes);
+        let results = thread::scope(|scope| {
+            paths
+                .iter()
+                .map(|path| scope.spawn(move || compile_tracked(vfs, includes, path)))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|compile| {
+                    compile
+                        .join()
+                        .unwrap_or_else(|_| Err(anyhow!("The shader compiler panicked")))
+                })
+                .collect::<Vec<_>>()
+        });
+        for (path, result) in paths.into_iter().zip(results) {
+            if let Ok(module) = result.and_then(|words| self.create_module(&words)) {
+                self.modules.lock().unwrap().insert(path, module);
+            }
+        }
+    }
+
+    // swaps in every shader recompiled since the last call and returns their paths, shaders that
+    // fail to compile keep their previous module
+    pub fn poll(&self) -> Vec<PathBuf> {
+        let results = self.results.lock().unwrap();
+        let mut changed = Vec::new();
+        for (path, result) in results.try_iter() {
+            if !self.modules.lock().unwrap().contains_key(&path) {
+                continue;
+            }
+            match result.and_then(|words| self.create_module(&words)) {
+                Ok(module) => {
+                    log::info!("Reloaded shader {}", path.display());
+                    self.modules.lock().unwrap().insert(path.clone(), module);
+                    changed.push(path);
+                }
+                Err(e) => log::error!(
+                    "Failed to reload shader {}, keeping the previous version: {:?}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+        changed
+    }
+
+    fn create_module(&self, words: &[u32]) -> anyhow::Result<Arc<ShaderModule>> {
+        // the SPIR-V either comes from shaderc or from a file the user built for this purpose
+        Ok(unsafe { ShaderModule::from_words(self.device.clone(), words)? })
+    }
+}
+
+// reports changed files of a directory mounted at `point` by their path in the file system
+fn watch(sender: Sender<PathBuf>, point: PathBuf, directory: &Path) -> anyhow::Result<FileWatcher> {
+    let root = directory.canonicalize()?;
+    FileWatcher::new(&root.clone(), move |path| {
+        if let Ok(path) = path.strip_prefix(&root) {
+            let _ = sender.send(point.join(path));
+        }
+    })
+}
+
+// the shaders to recompile for a set of changed files: the changed shaders themselves, and every
+// shader that included one of the files
+fn affected_shaders(includes: &Includes, changed: Vec<PathBuf>) -> Vec<PathBuf> {
+    let includes = includes.lock().unwrap();
+    let mut shaders = Vec::new();
+    for path in changed {
+        let path = normalize(&path);
+        if shader_kind(&path).is_some() || is_spirv(&path) {
+            shaders.push(path.clone());
+        }
+        shaders.extend(
+            includes
+                .iter()
+                .filter(|(_, included)| included.contains(&path))
+                .map(|(shader, _)| shader.clone()),
+        );
+    }
+    shaders.sort();
+    shaders.dedup();
+    shaders
+}
+
+// compiles the shader and records the files it included. a failed compile may have stopped
+// before some of them, so those of the previous compile are kept as well
+fn compile_tracked(vfs: &Vfs, includes: &Includes, path: &Path) -> anyhow::Result<Vec<u32>> {
+    let mut included = Vec::new();
+    let result = compile(vfs, path, &mut included);
+    let mut includes = includes.lock().unwrap();
+    if result.is_err() {
+        if let Some(previous) = includes.get(path) {
+            included.extend(previous.iter().cloned());
+        }
+    }
+    included.sort();
+    included.dedup();
+    includes.insert(path.to_path_buf(), included);
+    result
+}
+
+// include paths are joined to the including file, "post/../frame.glsl" and "frame.glsl" name the
+// same file
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            component => out.push(component),
+        }
+    }
+    out
+}
+
+fn is_spirv(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "spv")
+}
+
+fn shader_kind(path: &Path) -> Option<ShaderKind> {
+    Some(match path.extension()?.to_str()? {
+        "vert" => ShaderKind::Vertex,
+        "frag" => ShaderKind::Fragment,
+        "comp" => ShaderKind::Compute,
+        "geom" => ShaderKind::Geometry,
+        "tesc" => ShaderKind::TessControl,
+        "tese" => ShaderKind::TessEvaluation,
+        _ => return None,
+    })
+}
+
+fn compile(vfs: &Vfs, path: &Path, includes: &mut Vec<PathBuf>) -> anyhow::Result<Vec<u32>> {
+    if is_spirv(path) {
+        let bytes = vfs.read(path)?;
+        if bytes.len() % 4 != 0 {
+            return Err(anyhow!("{} is not a valid SPIR-V file", path.display()));
+        }
+        return Ok(bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_ne_bytes(word.try_into().unwrap()))
+            .collect());
+    }
+
+    let kind =
+        shader_kind(path).ok_or_else(|| anyhow!("Unknown shader type for {}", path.display()))?;
+    let source = String::from_utf8(vfs.read(path)?)
+        .map_err(|_| anyhow!("{} is not valid UTF-8", path.display()))?;
+    let mut compiler =
+        Compiler::new().ok_or_else(|| anyhow!("Unable to create a shader compiler"))?;
+    let mut options =
+        CompileOptions::new().ok_or_else(|| anyhow!("Unable to create shader compile options"))?;
+    let resolved_includes = RefCell::new(Vec::new());
+    options.set_include_callback(|name, ty, source, _depth| {
+        let base = match ty {
+            IncludeType::Relative => Path::new(source).parent().unwrap_or(Path::new("")),
+            IncludeType::Standard => path.parent().unwrap_or(Path::new("")),
+        };
+        let resolved = normalize(&base.join(name));
+        // recorded before reading, so creating a missing include triggers a rebuild
+        resolved_includes.borrow_mut().push(resolved.clone());
+        let content = vfs
+            .read(&resolved)
+            .map_err(|e| format!("{:#}", e))
+            .and_then(|bytes| {
+                String::from_utf8(bytes)
+                    .map_err(|_| format!("{} is not valid UTF-8", resolved.display()))
+            })?;
+        Ok(ResolvedInclude {
+            resolved_name: resolved.to_string_lossy().into_owned(),
+            content,
+        })
+    });
+    let artifact = compiler.compile_into_spirv(
+        &source,
+        kind,
+        &path.to_string_lossy(),
+        "main",
+        Some(&options),
+    );
+    drop(options);
+    includes.extend(resolved_includes.into_inner());
+    let artifact = artifact?;
+    if artifact.get_num_warnings() > 0 {
+        log::warn!("{}", artifact.get_warning_messages());
+    }
+    Ok(artifact.as_binary().to_vec())
+}
@@ -0,0 +1,269 @@
+use std::sync::Arc;
+
+use raw_window_handle::{
+    HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
+};
+use vulkano::{
+    device::{Device, DeviceOwned, Queue},
+    format::Format,
+    image::{
+        view::ImageView, AttachmentImage, ImageAccess, ImageUsage, ImageViewAbstract, SampleCount,
+        SwapchainImage,
+    },
+    instance::Instance,
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass},
+    swapchain::{ColorSpace, Surface, Swapchain, SwapchainCreateInfo, SwapchainCreationError},
+    sync::Sharing,
+};
+use vulkano_win::create_surface_from_handle;
+use winit::window::Window;
+
+use super::{
+    color::{select_surface_format, OutputColorSpace},
+    screenshot::Screenshot,
+};
+
+#[derive(Debug)]
+pub struct SendSyncWindowHandle {
+    pub window: RawWindowHandle,
+    pub display: RawDisplayHandle,
+}
+
+unsafe impl Send for SendSyncWindowHandle {}
+unsafe impl Sync for SendSyncWindowHandle {}
+
+unsafe impl HasRawWindowHandle for SendSyncWindowHandle {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        self.window
+    }
+}
+
+unsafe impl HasRawDisplayHandle for SendSyncWindowHandle {
+    fn raw_display_handle(&self) -> RawDisplayHandle {
+        self.display
+    }
+}
+
+pub fn create_surface(
+    window: &Window,
+    instance: Arc<Instance>,
+) -> anyhow::Result<Arc<Surface<SendSyncWindowHandle>>> {
+    let window_handle = SendSyncWindowHandle {
+        window: window.raw_window_handle(),
+        display: window.raw_display_handle(),
+    };
+    Ok(create_surface_from_handle(window_handle, instance)?)
+}
+
+pub struct SwapchainTarget {
+    pub surface: Arc<Surface<SendSyncWindowHandle>>,
+    pub present_queue: Arc<Queue>,
+    pub swapchain: Arc<Swapchain<SendSyncWindowHandle>>,
+    pub images: Vec<Arc<SwapchainImage<SendSyncWindowHandle>>>,
+}
+
+impl SwapchainTarget {
+    pub fn new(
+        device: Arc<Device>,
+        surface: Arc<Surface<SendSyncWindowHandle>>,
+        present_queue: Arc<Queue>,
+        queue_families: &[u32],
+        extent: [u32; 2],
+        color_space: OutputColorSpace,
+    ) -> anyhow::Result<Self> {
+        let phys_device = device.physical_device();
+        let surf_caps = phys_device.surface_capabilities(&surface, Default::default())?;
+        let image_count = (surf_caps.min_image_count + 1).clamp(
+            surf_caps.min_image_count,
+            surf_caps.max_image_count.unwrap_or(u32::MAX),
+        );
+        let (format, color_space) = select_surface_format(
+            &phys_device.surface_formats(&surface, Default::default())?,
+            color_space,
+        );
+        log::info!("Swapchain format {:?} in {:?}", format, color_space);
+        let (swapchain, images) = Swapchain::new(
+            device.clone(),
+            surface.clone(),
+            SwapchainCreateInfo {
+                image_color_space: color_space,
+                image_format: Some(format),
+                image_extent: extent,
+                image_sharing: match queue_families.len() {
+                    1 => Sharing::Exclusive,
+                    _ => Sharing::Concurrent(queue_families.iter().copied().collect()),
+                },
+                image_usage: ImageUsage {
+                    color_attachment: true,
+                    // needed to copy frames out for screenshots
+                    transfer_src: surf_caps.supported_usage_flags.transfer_src,
+                    ..Default::default()
+                },
+                min_image_count: image_count,
+                ..Default::default()
+            },
+        )?;
+        Ok(Self {
+            surface,
+            present_queue,
+            swapchain,
+            images,
+        })
+    }
+
+    // returns false if the surface cannot currently be resized to `extent`
+    pub fn recreate(&mut self, extent: [u32; 2]) -> anyhow::Result<bool> {
+        let (swapchain, images) = match self.swapchain.recreate(SwapchainCreateInfo {
+            image_extent: extent,
+            ..self.swapchain.create_info()
+        }) {
+            Ok(r) => r,
+            Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return Ok(false),
+            Err(e) => Err(e)?,
+        };
+        self.swapchain = swapchain;
+        self.images = images;
+        Ok(true)
+    }
+}
+
+// renders without a window, one image per frame in flight so a frame never overwrites an image
+// the GPU is still reading back
+pub struct OffscreenTarget {
+    pub format: Format,
+    pub images: Vec<Arc<AttachmentImage>>,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        device: Arc<Device>,
+        format: Format,
+        extent: [u32; 2],
+        count: usize,
+    ) -> anyhow::Result<Self> {
+        let images = (0..count)
+            .map(|_| {
+                AttachmentImage::with_usage(
+                    device.clone(),
+                    extent,
+                    format,
+                    ImageUsage {
+                        color_attachment: true,
+                        transfer_src: true,
+                        ..ImageUsage::empty()
+                    },
+                )
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { format, images })
+    }
+
+    pub fn recreate(&mut self, extent: [u32; 2]) -> anyhow::Result<bool> {
+        let device = self.images[0].device().clone();
+        *self = Self::new(device, self.format, extent, self.images.len())?;
+        Ok(true)
+    }
+}
+
+pub enum RenderTarget {
+    Swapchain(SwapchainTarget),
+    Offscreen(OffscreenTarget),
+}
+
+impl RenderTarget {
+    pub fn image_format(&self) -> Format {
+        match self {
+            RenderTarget::Swapchain(target) => target.swapchain.image_format(),
+            RenderTarget::Offscreen(target) => target.format,
+        }
+    }
+
+    // offscreen images are always sRGB
+    pub fn color_space(&self) -> ColorSpace {
+        match self {
+            RenderTarget::Swapchain(target) => target.swapchain.image_color_space(),
+            RenderTarget::Offscreen(_) => ColorSpace::SrgbNonLinear,
+        }
+    }
+
+    pub fn image(&self, index: usize) -> Arc<dyn ImageAccess> {
+        match self {
+            RenderTarget::Swapchain(target) => target.images[index].clone(),
+            RenderTarget::Offscreen(target) => target.images[index].clone(),
+        }
+    }
+
+    // frames can only be read back from 8-bit RGBA and BGRA images that allow copies
+    pub fn supports_capture(&self) -> bool {
+        let transfer_src = match self {
+            RenderTarget::Swapchain(target) => target.swapchain.image_usage().transfer_src,
+            RenderTarget::Offscreen(_) => true,
+        };
+        transfer_src && Screenshot::supports_format(self.image_format())
+    }
+
+    pub fn recreate(&mut self, extent: [u32; 2]) -> anyhow::Result<bool> {
+        match self {
+            RenderTarget::Swapchain(target) => target.recreate(extent),
+            RenderTarget::Offscreen(target) => target.recreate(extent),
+        }
+    }
+
+    pub fn create_framebuffers(
+        &self,
+        render_pass: &Arc<RenderPass>,
+        samples: SampleCount,
+        depth_stencil_format: Option<Format>,
+    ) -> anyhow::Result<Vec<Arc<Framebuffer>>> {
+        match self {
+            RenderTarget::Swapchain(target) => {
+                create_framebuffers(&target.images, render_pass, samples, depth_stencil_format)
+            }
+            RenderTarget::Offscreen(target) => {
+                create_framebuffers(&target.images, render_pass, samples, depth_stencil_format)
+            }
+        }
+    }
+}
+
+pub fn create_framebuffers<I: ImageAccess + 'static>(
+    images: &[Arc<I>],
+    render_pass: &Arc<RenderPass>,
+    samples: SampleCount,
+    depth_stencil_format: Option<Format>,
+) -> anyhow::Result<Vec<Arc<Framebuffer>>> {
+    let device = render_pass.device();
+    images
+        .iter()
+        .map(|img| -> anyhow::Result<Arc<Framebuffer>> {
+            let dimensions = img.dimensions().width_height();
+            let mut attachments: Vec<Arc<dyn ImageViewAbstract>> = Vec::new();
+            if samples != SampleCount::Sample1 {
+                let color = AttachmentImage::transient_multisampled(
+                    device.clone(),
+                    dimensions,
+                    samples,
+                    img.format(),
+                )?;
+                attachments.push(ImageView::new_default(color)?);
+            }
+            if let Some(format) = depth_stencil_format {
+                let depth = AttachmentImage::transient_multisampled(
+                    device.clone(),
+                    dimensions,
+                    samples,
+                    format,
+                )?;
+                attachments.push(ImageView::new_default(depth)?);
+            }
+            attachments.push(ImageView::new_default(img.clone())?);
+            Ok(Framebuffer::new(
+                render_pass.clone(),
+                FramebufferCreateInfo {
+                    attachments,
+                    ..Default::default()
+                },
+            )?)
+        })
+        .collect()
+}
@@ -0,0 +1,36 @@
+// helpers for tests that render, shared by the golden image tests and the tests of modules that
+// need a device. they render offscreen on a CPU device such as lavapipe, so they need a Vulkan
+// loader and one of those, and only run when asked for with `cargo test -- --ignored`
+use std::path::PathBuf;
+
+use winit::dpi::PhysicalSize;
+
+use crate::vfs::{DirectoryMount, Vfs};
+
+use super::{
+    config::RenderConfig, context::RenderContext, device::DeviceSelector,
+    validation::ValidationConfig,
+};
+
+// 128x96 with a single frame in flight, strict validation and no pipeline cache on disk
+pub(crate) fn offscreen_context(
+    configure: impl FnOnce(RenderConfig) -> RenderConfig,
+) -> RenderContext {
+    // references rendered on a GPU would depend on its hardware and driver
+    RenderContext::new_offscreen(
+        PhysicalSize::new(128, 96),
+        configure(
+            RenderConfig::new()
+                .vfs(Vfs::new().mount("", DirectoryMount::new(root_dir().join("assets"))))
+                .frames_in_flight(1)
+                .device(DeviceSelector::Cpu)
+                .pipeline_cache_dir(None)
+                .validation(ValidationConfig::new().enabled(true).strict(true)),
+        ),
+    )
+    .expect("rendering tests need a Vulkan loader and a CPU device such as lavapipe")
+}
+
+pub(crate) fn root_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
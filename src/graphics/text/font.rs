@@ -0,0 +1,37 @@
+use fontdue::FontSettings;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct FontId(pub(crate) usize);
+
+// the face is parsed once for shaping, rasterisation goes through fontdue
+pub struct Font {
+    // borrows `_data`, so it is declared first to be dropped first
+    face: rustybuzz::Face<'static>,
+    // the bytes the face borrows, never changed or reallocated while it exists
+    _data: Vec<u8>,
+    pub(crate) raster: fontdue::Font,
+}
+
+impl Font {
+    pub fn from_bytes(data: Vec<u8>) -> anyhow::Result<Self> {
+        // the bytes live on the heap, so they stay put when the vec moves into the font
+        let bytes = unsafe { std::slice::from_raw_parts(data.as_ptr(), data.len()) };
+        let face = rustybuzz::Face::from_slice(bytes, 0)
+            .ok_or_else(|| anyhow::anyhow!("Unable to parse font face"))?;
+        let raster = fontdue::Font::from_bytes(data.as_slice(), FontSettings::default())
+            .map_err(|e| anyhow::anyhow!("Unable to load font: {}", e))?;
+        Ok(Self {
+            face,
+            _data: data,
+            raster,
+        })
+    }
+
+    pub(crate) fn face(&self) -> &rustybuzz::Face<'_> {
+        &self.face
+    }
+
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.raster.lookup_glyph_index(c) != 0
+    }
+}
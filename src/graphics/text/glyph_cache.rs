@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use crate::graphics::texture::packer::ShelfPacker;
+
+use super::font::{Font, FontId};
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub(crate) struct GlyphKey {
+    pub font: FontId,
+    pub glyph: u16,
+    // bit pattern of the pixel size, so it can be hashed
+    pub size: u32,
+}
+
+impl GlyphKey {
+    pub fn new(font: FontId, glyph: u16, size: f32) -> Self {
+        Self {
+            font,
+            glyph,
+            size: size.to_bits(),
+        }
+    }
+}
+
+// offset is from the pen position to the top left corner of the glyph bitmap
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CachedGlyph {
+    pub position: [u32; 2],
+    pub size: [u32; 2],
+    pub offset: [f32; 2],
+}
+
+// a glyph bitmap that still has to be copied into the atlas image
+pub(crate) struct PendingGlyph {
+    pub position: [u32; 2],
+    pub size: [u32; 2],
+    pub pixels: Vec<u8>,
+}
+
+// glyphs are stored as white pixels with coverage in the alpha channel, so they can be drawn
+// with the sprite renderer and tinted with the sprite colour. the rest of the atlas is
+// transparent white
+pub(crate) struct GlyphCache {
+    packer: ShelfPacker,
+    // glyphs without a bitmap, like spaces, are cached as None
+    glyphs: HashMap<GlyphKey, Option<CachedGlyph>>,
+    pending: Vec<PendingGlyph>,
+}
+
+impl GlyphCache {
+    pub fn new(size: u32) -> Self {
+        Self {
+            packer: ShelfPacker::new(size, size, 1),
+            glyphs: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.packer.size()
+    }
+
+    // the glyphs inserted since the last call
+    pub fn take_pending(&mut self) -> Vec<PendingGlyph> {
+        std::mem::take(&mut self.pending)
+    }
+
+    pub fn get(&self, key: &GlyphKey) -> Option<CachedGlyph> {
+        self.glyphs.get(key).copied().flatten()
+    }
+
+    // returns false if the glyph does not fit in the remaining space
+    pub fn insert(&mut self, fonts: &[Font], key: GlyphKey) -> bool {
+        if self.glyphs.contains_key(&key) {
+            return true;
+        }
+        let (metrics, coverage) = fonts[key.font.0]
+            .raster
+            .rasterize_indexed(key.glyph, f32::from_bits(key.size));
+        let (width, height) = (metrics.width as u32, metrics.height as u32);
+        if width == 0 || height == 0 {
+            self.glyphs.insert(key, None);
+            return true;
+        }
+        let position = match self.packer.insert(width, height) {
+            Some(position) => position,
+            None => return false,
+        };
+        self.pending.push(PendingGlyph {
+            position,
+            size: [width, height],
+            pixels: coverage
+                .into_iter()
+                .flat_map(|alpha| [255, 255, 255, alpha])
+                .collect(),
+        });
+        self.glyphs.insert(
+            key,
+            Some(CachedGlyph {
+                position,
+                size: [width, height],
+                // fontdue measures ymin upwards from the baseline to the bottom of the bitmap
+                offset: [
+                    metrics.xmin as f32,
+                    -(metrics.ymin as f32 + metrics.height as f32),
+                ],
+            }),
+        );
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.packer.clear();
+        self.glyphs.clear();
+        self.pending.clear();
+    }
+}
@@ -0,0 +1,335 @@
+use std::ops::Range;
+
+use rustybuzz::UnicodeBuffer;
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+
+use super::font::{Font, FontId};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+// position is the pen position on the baseline, relative to the top left corner of the text
+#[derive(Clone, Copy, Debug)]
+pub struct LayoutGlyph {
+    pub font: FontId,
+    pub glyph: u16,
+    pub position: [f32; 2],
+}
+
+pub struct TextLayout {
+    pub glyphs: Vec<LayoutGlyph>,
+    pub size: [f32; 2],
+}
+
+pub(crate) struct LayoutOptions {
+    pub size: f32,
+    pub max_width: Option<f32>,
+    pub align: TextAlign,
+    pub line_height: f32,
+}
+
+struct ShapedGlyph {
+    font: FontId,
+    glyph: u16,
+    cluster: usize,
+    advance: f32,
+    offset: [f32; 2],
+}
+
+// the text between two line break opportunities
+struct Segment {
+    glyphs: Vec<ShapedGlyph>,
+    width: f32,
+    trailing_whitespace: f32,
+}
+
+impl Segment {
+    // `text` is what the glyphs were shaped from, their clusters are byte offsets into it
+    fn new(glyphs: Vec<ShapedGlyph>, text: &str) -> Self {
+        let content_len = text.trim_end().len();
+        Self {
+            width: glyphs.iter().map(|glyph| glyph.advance).sum(),
+            trailing_whitespace: glyphs
+                .iter()
+                .filter(|glyph| glyph.cluster >= content_len)
+                .map(|glyph| glyph.advance)
+                .sum(),
+            glyphs,
+        }
+    }
+}
+
+fn is_line_terminator(c: char) -> bool {
+    matches!(
+        c,
+        '\n' | '\r' | '\u{b}' | '\u{c}' | '\u{85}' | '\u{2028}' | '\u{2029}'
+    )
+}
+
+// splits text into runs of characters that are covered by the same font
+fn font_runs(
+    fonts: &[Font],
+    primary: FontId,
+    fallbacks: &[FontId],
+    text: &str,
+) -> Vec<(FontId, Range<usize>)> {
+    let mut runs: Vec<(FontId, Range<usize>)> = Vec::new();
+    for (index, c) in text.char_indices() {
+        let font = if c.is_whitespace() {
+            runs.last().map(|(font, _)| *font).unwrap_or(primary)
+        } else {
+            std::iter::once(primary)
+                .chain(fallbacks.iter().copied())
+                .find(|font| fonts[font.0].has_glyph(c))
+                .unwrap_or(primary)
+        };
+        let end = index + c.len_utf8();
+        match runs.last_mut() {
+            Some((run_font, range)) if *run_font == font => range.end = end,
+            _ => runs.push((font, index..end)),
+        }
+    }
+    runs
+}
+
+fn shape_run(
+    font: &Font,
+    font_id: FontId,
+    text: &str,
+    start: usize,
+    size: f32,
+) -> Vec<ShapedGlyph> {
+    let face = font.face();
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    let output = rustybuzz::shape(face, &[], buffer);
+    let scale = size / face.units_per_em() as f32;
+    output
+        .glyph_infos()
+        .iter()
+        .zip(output.glyph_positions())
+        .map(|(info, position)| ShapedGlyph {
+            font: font_id,
+            glyph: info.glyph_id as u16,
+            cluster: start + info.cluster as usize,
+            advance: position.x_advance as f32 * scale,
+            // harfbuzz offsets point up, screen space points down
+            offset: [
+                position.x_offset as f32 * scale,
+                -position.y_offset as f32 * scale,
+            ],
+        })
+        .collect()
+}
+
+fn shape_segment(
+    fonts: &[Font],
+    primary: FontId,
+    fallbacks: &[FontId],
+    text: &str,
+    size: f32,
+) -> Segment {
+    let glyphs = font_runs(fonts, primary, fallbacks, text)
+        .into_iter()
+        .flat_map(|(font, range)| {
+            shape_run(
+                &fonts[font.0],
+                font,
+                &text[range.clone()],
+                range.start,
+                size,
+            )
+        })
+        .collect();
+    Segment::new(glyphs, text)
+}
+
+fn line_width(line: &[Segment]) -> f32 {
+    let width: f32 = line.iter().map(|segment| segment.width).sum();
+    width
+        - line
+            .last()
+            .map(|segment| segment.trailing_whitespace)
+            .unwrap_or(0.0)
+}
+
+pub(crate) fn layout_text(
+    fonts: &[Font],
+    primary: FontId,
+    fallbacks: &[FontId],
+    text: &str,
+    options: &LayoutOptions,
+) -> TextLayout {
+    let (ascent, line_advance) = fonts[primary.0]
+        .raster
+        .horizontal_line_metrics(options.size)
+        .map(|metrics| (metrics.ascent, metrics.new_line_size))
+        .unwrap_or((options.size, options.size * 1.2));
+    let line_advance = line_advance * options.line_height;
+    layout_segments(text, ascent, line_advance, options, |segment| {
+        shape_segment(fonts, primary, fallbacks, segment, options.size)
+    })
+}
+
+// breaks text into lines of the segments between line break opportunities, as shaped by `shape`
+fn layout_segments(
+    text: &str,
+    ascent: f32,
+    line_advance: f32,
+    options: &LayoutOptions,
+    mut shape: impl FnMut(&str) -> Segment,
+) -> TextLayout {
+    // greedy line filling, a segment only moves to the next line if the current one is not empty
+    let mut lines: Vec<Vec<Segment>> = Vec::new();
+    let mut current_line = Vec::new();
+    let mut current_width = 0.0;
+    let mut start = 0;
+    for (end, opportunity) in linebreaks(text) {
+        let segment = shape(text[start..end].trim_end_matches(is_line_terminator));
+        start = end;
+        if let Some(max_width) = options.max_width {
+            let width = current_width + segment.width - segment.trailing_whitespace;
+            if !current_line.is_empty() && width > max_width {
+                lines.push(std::mem::take(&mut current_line));
+                current_width = 0.0;
+            }
+        }
+        current_width += segment.width;
+        current_line.push(segment);
+        if let BreakOpportunity::Mandatory = opportunity {
+            lines.push(std::mem::take(&mut current_line));
+            current_width = 0.0;
+        }
+    }
+    if !current_line.is_empty() {
+        lines.push(current_line);
+    }
+
+    let block_width = options.max_width.unwrap_or_else(|| {
+        lines
+            .iter()
+            .map(|line| line_width(line))
+            .fold(0.0, f32::max)
+    });
+    let mut glyphs = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let mut pen_x = match options.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => (block_width - line_width(line)) / 2.0,
+            TextAlign::Right => block_width - line_width(line),
+        };
+        let baseline = ascent + index as f32 * line_advance;
+        for glyph in line.iter().flat_map(|segment| &segment.glyphs) {
+            glyphs.push(LayoutGlyph {
+                font: glyph.font,
+                glyph: glyph.glyph,
+                position: [pen_x + glyph.offset[0], baseline + glyph.offset[1]],
+            });
+            pen_x += glyph.advance;
+        }
+    }
+
+    TextLayout {
+        glyphs,
+        size: [block_width, lines.len() as f32 * line_advance],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every character is a glyph one unit wide, with the character as its id
+    fn monospace(text: &str) -> Segment {
+        let glyphs = text
+            .char_indices()
+            .map(|(cluster, c)| ShapedGlyph {
+                font: FontId(0),
+                glyph: c as u16,
+                cluster,
+                advance: 1.0,
+                offset: [0.0; 2],
+            })
+            .collect();
+        Segment::new(glyphs, text)
+    }
+
+    // lines are 2 units apart, the first baseline is at 1
+    fn layout(text: &str, max_width: Option<f32>, align: TextAlign) -> TextLayout {
+        let options = LayoutOptions {
+            size: 1.0,
+            max_width,
+            align,
+            line_height: 1.0,
+        };
+        layout_segments(text, 1.0, 2.0, &options, monospace)
+    }
+
+    fn lines(layout: &TextLayout) -> Vec<String> {
+        let mut lines = vec![String::new(); (layout.size[1] / 2.0) as usize];
+        for glyph in &layout.glyphs {
+            let line = ((glyph.position[1] - 1.0) / 2.0) as usize;
+            lines[line].push(char::from_u32(glyph.glyph as u32).unwrap());
+        }
+        lines
+    }
+
+    // the x position of the first glyph of every line
+    fn line_starts(layout: &TextLayout) -> Vec<f32> {
+        let mut starts = Vec::new();
+        let mut baseline = None;
+        for glyph in &layout.glyphs {
+            if baseline != Some(glyph.position[1]) {
+                baseline = Some(glyph.position[1]);
+                starts.push(glyph.position[0]);
+            }
+        }
+        starts
+    }
+
+    #[test]
+    fn wrap_at_spaces() {
+        // the trailing space of a line does not count towards its width
+        let text = layout("one two three", Some(7.0), TextAlign::Left);
+        assert_eq!(lines(&text), ["one two ", "three"]);
+        assert_eq!(text.size, [7.0, 4.0]);
+    }
+
+    #[test]
+    fn overflow_words_longer_than_a_line() {
+        let text = layout("abcdefgh ij", Some(4.0), TextAlign::Left);
+        assert_eq!(lines(&text), ["abcdefgh ", "ij"]);
+    }
+
+    #[test]
+    fn keep_empty_lines() {
+        let text = layout("a\n\nb", None, TextAlign::Left);
+        assert_eq!(lines(&text), ["a", "", "b"]);
+        assert_eq!(text.size, [1.0, 6.0]);
+    }
+
+    #[test]
+    fn align_lines_in_the_block() {
+        let starts = |align| line_starts(&layout("ab cdef", Some(4.0), align));
+        assert_eq!(starts(TextAlign::Left), [0.0, 0.0]);
+        assert_eq!(starts(TextAlign::Center), [1.0, 0.0]);
+        assert_eq!(starts(TextAlign::Right), [2.0, 0.0]);
+        // without a wrap width the block is as wide as the widest line
+        let starts = line_starts(&layout("ab\ncdef", None, TextAlign::Right));
+        assert_eq!(starts, [2.0, 0.0]);
+    }
+
+    #[test]
+    fn break_between_cjk_characters() {
+        let text = layout("漢字かな", Some(2.0), TextAlign::Left);
+        assert_eq!(lines(&text), ["漢字", "かな"]);
+        // closing punctuation never starts a line
+        let text = layout("漢字。漢", Some(2.0), TextAlign::Left);
+        assert_eq!(lines(&text), ["漢", "字。", "漢"]);
+    }
+}
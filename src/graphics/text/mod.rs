@@ -0,0 +1,205 @@
+use std::{path::Path, sync::Arc};
+
+use vulkano::{
+    format::Format,
+    image::{view::ImageView, StorageImage},
+};
+
+use crate::vfs::Vfs;
+
+use super::{
+    renderers::sprite::{Sprite, SpriteRenderer},
+    texture::{Texture, TextureRegion},
+    upload::UploadQueue,
+};
+
+use self::{
+    font::{Font, FontId},
+    glyph_cache::{GlyphCache, GlyphKey},
+    layout::{layout_text, LayoutOptions, TextAlign, TextLayout},
+};
+
+pub mod font;
+pub mod glyph_cache;
+pub mod layout;
+
+const GLYPH_ATLAS_SIZE: u32 = 2048;
+
+#[derive(Clone, Debug)]
+pub struct TextStyle {
+    pub size: f32,
+    pub color: [f32; 4],
+    pub align: TextAlign,
+    pub max_width: Option<f32>,
+    pub line_height: f32,
+    pub layer: i32,
+}
+
+impl TextStyle {
+    pub fn new(size: f32) -> Self {
+        Self {
+            size,
+            color: [1.0; 4],
+            align: TextAlign::Left,
+            max_width: None,
+            line_height: 1.0,
+            layer: 0,
+        }
+    }
+
+    pub fn color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    // lines are broken at unicode line break opportunities to fit in the given width
+    pub fn wrap(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    pub fn line_height(mut self, line_height: f32) -> Self {
+        self.line_height = line_height;
+        self
+    }
+
+    pub fn layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+}
+
+pub struct TextRenderer {
+    fonts: Vec<Font>,
+    fallbacks: Vec<FontId>,
+    cache: GlyphCache,
+    atlas: Option<(Arc<StorageImage>, Arc<Texture>)>,
+    uploads: Arc<UploadQueue>,
+    vfs: Vfs,
+}
+
+impl TextRenderer {
+    // font files are read from `vfs`
+    pub fn new(vfs: Vfs, uploads: Arc<UploadQueue>) -> Self {
+        Self {
+            fonts: Vec::new(),
+            fallbacks: Vec::new(),
+            cache: GlyphCache::new(GLYPH_ATLAS_SIZE),
+            atlas: None,
+            uploads,
+            vfs,
+        }
+    }
+
+    pub fn load_font(&mut self, data: Vec<u8>) -> anyhow::Result<FontId> {
+        self.fonts.push(Font::from_bytes(data)?);
+        Ok(FontId(self.fonts.len() - 1))
+    }
+
+    pub fn load_font_file(&mut self, path: impl AsRef<Path>) -> anyhow::Result<FontId> {
+        let data = self.vfs.read(path)?;
+        self.load_font(data)
+    }
+
+    // fallback fonts are tried in order for characters missing from the requested font
+    pub fn add_fallback(&mut self, font: FontId) {
+        self.fallbacks.push(font);
+    }
+
+    pub fn layout(&self, font: FontId, text: &str, style: &TextStyle) -> TextLayout {
+        layout_text(
+            &self.fonts,
+            font,
+            &self.fallbacks,
+            text,
+            &LayoutOptions {
+                size: style.size,
+                max_width: style.max_width,
+                align: style.align,
+                line_height: style.line_height,
+            },
+        )
+    }
+
+    pub fn measure(&self, font: FontId, text: &str, style: &TextStyle) -> [f32; 2] {
+        self.layout(font, text, style).size
+    }
+
+    // position is the top left corner of the text block
+    pub fn draw_text(
+        &mut self,
+        sprites: &mut SpriteRenderer,
+        font: FontId,
+        text: &str,
+        position: [f32; 2],
+        style: &TextStyle,
+    ) -> anyhow::Result<()> {
+        let layout = self.layout(font, text, style);
+        let keys = layout
+            .glyphs
+            .iter()
+            .map(|glyph| GlyphKey::new(glyph.font, glyph.glyph, style.size))
+            .collect::<Vec<_>>();
+        if !keys.iter().all(|key| self.cache.insert(&self.fonts, *key)) {
+            // the atlas is full, start over with only the glyphs of this text in a new atlas
+            // image, sprites that were already drawn keep a reference to the previous one
+            self.cache.clear();
+            self.atlas = None;
+            for key in &keys {
+                anyhow::ensure!(
+                    self.cache.insert(&self.fonts, *key),
+                    "Text does not fit in the glyph atlas"
+                );
+            }
+        }
+        let (image, texture) = match &self.atlas {
+            Some(atlas) => atlas.clone(),
+            None => {
+                let [width, height] = self.cache.size();
+                let image = self.uploads.cleared_image(
+                    Format::R8G8B8A8_SRGB,
+                    [width, height],
+                    [1.0, 1.0, 1.0, 0.0],
+                )?;
+                let texture = Arc::new(Texture {
+                    view: ImageView::new_default(image.clone())?,
+                    image: image.clone(),
+                    width,
+                    height,
+                });
+                self.atlas = Some((image.clone(), texture.clone()));
+                (image, texture)
+            }
+        };
+        // only the new glyphs are copied, at the start of the next frame before anything is drawn
+        for glyph in self.cache.take_pending() {
+            self.uploads
+                .update_image(image.clone(), glyph.position, glyph.size, &glyph.pixels)?;
+        }
+
+        for (glyph, key) in layout.glyphs.iter().zip(&keys) {
+            if let Some(cached) = self.cache.get(key) {
+                // snap to whole pixels to keep the bitmaps crisp
+                let left = (position[0] + glyph.position[0] + cached.offset[0]).round();
+                let top = (position[1] + glyph.position[1] + cached.offset[1]).round();
+                let size = [cached.size[0] as f32, cached.size[1] as f32];
+                sprites.draw(
+                    Sprite::new([left + size[0] / 2.0, top + size[1] / 2.0], size)
+                        .region(&TextureRegion::sub(
+                            texture.clone(),
+                            cached.position,
+                            cached.size,
+                        ))
+                        .color(style.color)
+                        .layer(style.layer),
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::{collections::HashMap, hash::Hash, path::Path, sync::Arc};
+
+use image::{imageops, RgbaImage};
+
+use super::{decode_image_file, packer::ShelfPacker, Texture, TextureLoader, TextureRegion};
+
+pub struct Atlas<K> {
+    pub pages: Vec<Arc<Texture>>,
+    regions: HashMap<K, TextureRegion>,
+}
+
+impl<K: Hash + Eq> Atlas<K> {
+    pub fn get(&self, key: &K) -> Option<&TextureRegion> {
+        self.regions.get(key)
+    }
+}
+
+pub struct AtlasBuilder<K> {
+    page_size: u32,
+    padding: u32,
+    images: Vec<(K, RgbaImage)>,
+}
+
+impl<K: Hash + Eq> AtlasBuilder<K> {
+    pub fn new(page_size: u32) -> Self {
+        Self {
+            page_size,
+            padding: 1,
+            images: Vec::new(),
+        }
+    }
+
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn add(&mut self, key: K, image: RgbaImage) {
+        self.images.push((key, image));
+    }
+
+    pub fn add_file(&mut self, key: K, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.add(key, decode_image_file(path)?);
+        Ok(())
+    }
+
+    // packs the images into as many pages as needed and uploads every page
+    pub fn build(mut self, loader: &mut TextureLoader) -> anyhow::Result<Atlas<K>> {
+        // placing tall images first keeps shelves tightly filled
+        self.images
+            .sort_by_key(|(_, image)| std::cmp::Reverse(image.height()));
+
+        let mut pages: Vec<(ShelfPacker, RgbaImage)> = Vec::new();
+        let mut placements = Vec::with_capacity(self.images.len());
+        for (key, image) in self.images {
+            let (width, height) = image.dimensions();
+            let placed = pages
+                .iter_mut()
+                .enumerate()
+                .find_map(|(index, (packer, _))| {
+                    packer
+                        .insert(width, height)
+                        .map(|position| (index, position))
+                });
+            let (page, position) = match placed {
+                Some(placed) => placed,
+                None => {
+                    let mut packer = ShelfPacker::new(self.page_size, self.page_size, self.padding);
+                    let position = packer.insert(width, height).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Image of size {}x{} does not fit in an atlas page of size {}",
+                            width,
+                            height,
+                            self.page_size
+                        )
+                    })?;
+                    pages.push((packer, RgbaImage::new(self.page_size, self.page_size)));
+                    (pages.len() - 1, position)
+                }
+            };
+            imageops::replace(
+                &mut pages[page].1,
+                &image,
+                position[0] as i64,
+                position[1] as i64,
+            );
+            placements.push((key, page, position, [width, height]));
+        }
+
+        // no mipmaps, smaller levels would blend neighbouring images into each other across the
+        // padding
+        let pages = pages
+            .into_iter()
+            .map(|(_, canvas)| {
+                loader.upload_rgba(canvas.width(), canvas.height(), canvas.into_raw(), false)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let regions = placements
+            .into_iter()
+            .map(|(key, page, position, size)| {
+                (key, TextureRegion::sub(pages[page].clone(), position, size))
+            })
+            .collect();
+        Ok(Atlas { pages, regions })
+    }
+}
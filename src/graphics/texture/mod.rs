@@ -0,0 +1,213 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::Context;
+use image::RgbaImage;
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
+    },
+    device::{Device, Queue},
+    format::Format,
+    image::{
+        view::ImageView, ImageAccess, ImageDimensions, ImageViewAbstract, ImmutableImage,
+        MipmapsCount,
+    },
+    sync::GpuFuture,
+};
+
+use crate::assets::loader::{AssetLoader, LoadContext};
+
+use super::upload::UploadQueue;
+
+pub mod atlas;
+pub mod packer;
+
+pub struct Texture {
+    pub(crate) image: Arc<dyn ImageAccess>,
+    pub view: Arc<dyn ImageViewAbstract>,
+    pub width: u32,
+    pub height: u32,
+}
+
+// a rectangle of a texture, uv is [min u, min v, max u, max v]
+#[derive(Clone)]
+pub struct TextureRegion {
+    pub texture: Arc<Texture>,
+    pub uv: [f32; 4],
+    pub size: [u32; 2],
+}
+
+impl TextureRegion {
+    pub fn full(texture: Arc<Texture>) -> Self {
+        Self {
+            size: [texture.width, texture.height],
+            uv: [0.0, 0.0, 1.0, 1.0],
+            texture,
+        }
+    }
+
+    pub fn sub(texture: Arc<Texture>, position: [u32; 2], size: [u32; 2]) -> Self {
+        let (width, height) = (texture.width as f32, texture.height as f32);
+        Self {
+            uv: [
+                position[0] as f32 / width,
+                position[1] as f32 / height,
+                (position[0] + size[0]) as f32 / width,
+                (position[1] + size[1]) as f32 / height,
+            ],
+            size,
+            texture,
+        }
+    }
+}
+
+pub fn decode_image(bytes: &[u8]) -> anyhow::Result<RgbaImage> {
+    Ok(image::load_from_memory(bytes)?.to_rgba8())
+}
+
+pub fn decode_image_file(path: impl AsRef<Path>) -> anyhow::Result<RgbaImage> {
+    let path = path.as_ref();
+    Ok(image::open(path)
+        .with_context(|| format!("Unable to decode image {}", path.display()))?
+        .to_rgba8())
+}
+
+// records texture uploads into a single command buffer, textures must not be used before the
+// next call to flush
+pub struct TextureLoader {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    pending: Option<AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>>,
+}
+
+impl TextureLoader {
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    ) -> Self {
+        Self {
+            device,
+            queue,
+            command_buffer_allocator,
+            pending: None,
+        }
+    }
+
+    fn builder(
+        &mut self,
+    ) -> anyhow::Result<&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>> {
+        if self.pending.is_none() {
+            self.pending = Some(AutoCommandBufferBuilder::primary(
+                self.command_buffer_allocator.as_ref(),
+                self.queue.queue_family_index(),
+                CommandBufferUsage::OneTimeSubmit,
+            )?);
+        }
+        Ok(self.pending.as_mut().unwrap())
+    }
+
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> anyhow::Result<Arc<Texture>> {
+        let image = decode_image_file(path)?;
+        self.upload_rgba(image.width(), image.height(), image.into_raw(), true)
+    }
+
+    pub fn load_memory(&mut self, bytes: &[u8]) -> anyhow::Result<Arc<Texture>> {
+        let image = decode_image(bytes)?;
+        self.upload_rgba(image.width(), image.height(), image.into_raw(), true)
+    }
+
+    // pixels are tightly packed sRGB RGBA8 rows, copied through a host-visible staging buffer
+    // into a device-local image
+    pub fn upload_rgba(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+        mipmaps: bool,
+    ) -> anyhow::Result<Arc<Texture>> {
+        let size = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|texels| texels.checked_mul(4))
+            .ok_or_else(|| anyhow::anyhow!("Texture of size {}x{} is too large", width, height))?;
+        anyhow::ensure!(
+            pixels.len() == size,
+            "Expected {} bytes of pixel data, got {}",
+            size,
+            pixels.len()
+        );
+        let staging = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage {
+                transfer_src: true,
+                ..BufferUsage::empty()
+            },
+            false,
+            pixels,
+        )?;
+        // with more than one mip level, vulkano blits the rest of the chain from the base level
+        let mip_levels = match mipmaps {
+            true => MipmapsCount::Log2,
+            false => MipmapsCount::One,
+        };
+        let image = ImmutableImage::from_buffer(
+            staging,
+            ImageDimensions::Dim2d {
+                width,
+                height,
+                array_layers: 1,
+            },
+            mip_levels,
+            Format::R8G8B8A8_SRGB,
+            self.builder()?,
+        )?;
+        Ok(Arc::new(Texture {
+            view: ImageView::new_default(image.clone())?,
+            image,
+            width,
+            height,
+        }))
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        if let Some(builder) = self.pending.take() {
+            builder
+                .build()?
+                .execute(self.queue.clone())?
+                .then_signal_fence_and_flush()?
+                .wait(None)?;
+        }
+        Ok(())
+    }
+}
+
+// loads png and jpeg assets as textures without mipmaps. the copy runs on the transfer queue, a
+// loaded texture may be drawn right away since frames wait for the copy on the GPU
+pub struct TextureAssetLoader {
+    uploads: Arc<UploadQueue>,
+}
+
+impl TextureAssetLoader {
+    pub fn new(uploads: Arc<UploadQueue>) -> Self {
+        Self { uploads }
+    }
+}
+
+impl AssetLoader for TextureAssetLoader {
+    type Asset = Texture;
+
+    fn extensions(&self) -> &[&str] {
+        &["png", "jpg", "jpeg"]
+    }
+
+    fn load(&self, ctx: &mut LoadContext) -> anyhow::Result<Arc<Texture>> {
+        let image = decode_image(&ctx.read()?)?;
+        let upload = self
+            .uploads
+            .texture_async(image.width(), image.height(), image.as_raw())?;
+        Ok(upload.resource)
+    }
+}
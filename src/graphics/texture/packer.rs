@@ -0,0 +1,108 @@
+struct Shelf {
+    y: u32,
+    height: u32,
+    used_width: u32,
+}
+
+// shelf packer, rectangles are placed left to right on horizontal shelves whose height is set
+// by the first rectangle placed on them
+pub struct ShelfPacker {
+    width: u32,
+    height: u32,
+    padding: u32,
+    shelves: Vec<Shelf>,
+}
+
+impl ShelfPacker {
+    pub fn new(width: u32, height: u32, padding: u32) -> Self {
+        Self {
+            width,
+            height,
+            padding,
+            shelves: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        [self.width, self.height]
+    }
+
+    pub fn insert(&mut self, width: u32, height: u32) -> Option<[u32; 2]> {
+        let padded_width = width + self.padding;
+        let padded_height = height + self.padding;
+        if padded_width > self.width || padded_height > self.height {
+            return None;
+        }
+
+        let page_width = self.width;
+        if let Some(shelf) = self
+            .shelves
+            .iter_mut()
+            .filter(|shelf| {
+                shelf.height >= padded_height && shelf.used_width + padded_width <= page_width
+            })
+            .min_by_key(|shelf| shelf.height - padded_height)
+        {
+            let position = [shelf.used_width, shelf.y];
+            shelf.used_width += padded_width;
+            return Some(position);
+        }
+
+        let y = self
+            .shelves
+            .last()
+            .map(|shelf| shelf.y + shelf.height)
+            .unwrap_or(0);
+        if y + padded_height > self.height {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height: padded_height,
+            used_width: padded_width,
+        });
+        Some([0, y])
+    }
+
+    pub fn clear(&mut self) {
+        self.shelves.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_shelves_left_to_right() {
+        let mut packer = ShelfPacker::new(10, 10, 1);
+        assert_eq!(packer.insert(3, 4), Some([0, 0]));
+        assert_eq!(packer.insert(3, 2), Some([4, 0]));
+        // the padded width no longer fits on the first shelf
+        assert_eq!(packer.insert(3, 2), Some([0, 5]));
+        assert_eq!(packer.insert(5, 2), Some([4, 5]));
+    }
+
+    #[test]
+    fn pick_the_tightest_shelf() {
+        let mut packer = ShelfPacker::new(16, 16, 0);
+        assert_eq!(packer.insert(16, 6), Some([0, 0]));
+        assert_eq!(packer.insert(4, 2), Some([0, 6]));
+        assert_eq!(packer.insert(4, 8), Some([0, 8]));
+        // both shelves have room, the one only as high as the rectangle wastes nothing
+        assert_eq!(packer.insert(4, 2), Some([4, 6]));
+        assert_eq!(packer.insert(4, 3), Some([4, 8]));
+    }
+
+    #[test]
+    fn reject_what_does_not_fit() {
+        let mut packer = ShelfPacker::new(8, 8, 1);
+        // the padding counts towards the size
+        assert_eq!(packer.insert(8, 1), None);
+        assert_eq!(packer.insert(7, 4), Some([0, 0]));
+        assert_eq!(packer.insert(7, 4), None);
+        assert_eq!(packer.insert(7, 2), Some([0, 5]));
+        packer.clear();
+        assert_eq!(packer.insert(7, 7), Some([0, 0]));
+    }
+}
@@ -0,0 +1,184 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    device::{DeviceOwned, Queue},
+    query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
+    sync::PipelineStage,
+};
+
+// scopes beyond this in a single frame are not timed
+const MAX_SCOPES: u32 = 64;
+
+#[derive(Clone, Debug)]
+pub struct PassTiming {
+    pub name: String,
+    pub duration: Duration,
+}
+
+struct TimerFrame {
+    pool: Arc<QueryPool>,
+    scopes: Vec<String>,
+}
+
+// records timestamp queries around passes, with one query pool per frame in flight. a pool is
+// only read back when its frame comes around again, after the frame's fence was waited on, so
+// reading the results never stalls
+pub struct GpuTimer {
+    frames: Vec<TimerFrame>,
+    current: usize,
+    period_ns: f64,
+    valid_mask: u64,
+    timings: Vec<PassTiming>,
+}
+
+impl GpuTimer {
+    pub fn new(queue: &Queue, frames_in_flight: usize) -> anyhow::Result<Self> {
+        let device = queue.device();
+        let phys_device = device.physical_device();
+        let valid_bits = phys_device.queue_family_properties()[queue.queue_family_index() as usize]
+            .timestamp_valid_bits
+            .unwrap_or(0);
+        let frames = if valid_bits == 0 {
+            log::warn!("The graphics queue does not support timestamps, GPU timings are disabled");
+            Vec::new()
+        } else {
+            (0..frames_in_flight)
+                .map(|_| -> anyhow::Result<TimerFrame> {
+                    Ok(TimerFrame {
+                        pool: QueryPool::new(
+                            device.clone(),
+                            QueryPoolCreateInfo {
+                                query_count: MAX_SCOPES * 2,
+                                ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+                            },
+                        )?,
+                        scopes: Vec::new(),
+                    })
+                })
+                .collect::<anyhow::Result<_>>()?
+        };
+        Ok(Self {
+            frames,
+            current: 0,
+            period_ns: phys_device.properties().timestamp_period as f64,
+            valid_mask: u64::MAX >> (64 - valid_bits.clamp(1, 64)),
+            timings: Vec::new(),
+        })
+    }
+
+    // timings of the most recent frame whose results are available
+    pub fn timings(&self) -> &[PassTiming] {
+        &self.timings
+    }
+
+    // must be called outside of a render pass, after the frame's fence was waited on
+    pub fn begin_frame(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame_index: usize,
+    ) -> anyhow::Result<()> {
+        if self.frames.is_empty() {
+            return Ok(());
+        }
+        self.current = frame_index;
+        let (period_ns, valid_mask) = (self.period_ns, self.valid_mask);
+        let frame = &mut self.frames[frame_index];
+        if !frame.scopes.is_empty() {
+            let mut data = vec![0u64; frame.scopes.len() * 2];
+            let available = frame
+                .pool
+                .queries_range(0..data.len() as u32)
+                .unwrap()
+                .get_results(&mut data, QueryResultFlags::default())?;
+            if available {
+                self.timings = frame
+                    .scopes
+                    .drain(..)
+                    .zip(data.chunks_exact(2))
+                    .map(|(name, stamps)| {
+                        let ticks = stamps[1].wrapping_sub(stamps[0]) & valid_mask;
+                        PassTiming {
+                            name,
+                            duration: Duration::from_nanos((ticks as f64 * period_ns) as u64),
+                        }
+                    })
+                    .collect();
+            }
+            frame.scopes.clear();
+        }
+        unsafe {
+            builder.reset_query_pool(frame.pool.clone(), 0..MAX_SCOPES * 2)?;
+        }
+        Ok(())
+    }
+
+    // times the commands recorded by `record`, scopes may be nested
+    pub fn scope<T>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        name: &str,
+        record: impl FnOnce(
+            &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        ) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let query = match self.frames.get_mut(self.current) {
+            Some(frame) if (frame.scopes.len() as u32) < MAX_SCOPES => {
+                frame.scopes.push(name.to_owned());
+                let query = (frame.scopes.len() as u32 - 1) * 2;
+                unsafe {
+                    builder.write_timestamp(frame.pool.clone(), query, PipelineStage::TopOfPipe)?;
+                }
+                Some((frame.pool.clone(), query + 1))
+            }
+            _ => None,
+        };
+        let result = record(builder)?;
+        if let Some((pool, query)) = query {
+            unsafe {
+                builder.write_timestamp(pool, query, PipelineStage::BottomOfPipe)?;
+            }
+        }
+        Ok(result)
+    }
+}
+
+// CPU side timings of the render loop next to the GPU timings of each pass
+#[derive(Clone, Debug, Default)]
+pub struct FrameStats {
+    // time between the starts of the last two frames
+    pub frame_time: Duration,
+    // time spent recording and submitting the last frame
+    pub cpu_time: Duration,
+    pub gpu_passes: Vec<PassTiming>,
+    // validation messages reported while recording and submitting the last frame
+    pub validation_errors: usize,
+    pub validation_warnings: usize,
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "frame {:.2}ms, cpu {:.2}ms",
+            self.frame_time.as_secs_f64() * 1000.0,
+            self.cpu_time.as_secs_f64() * 1000.0
+        )?;
+        for pass in &self.gpu_passes {
+            write!(
+                f,
+                ", {} {:.2}ms",
+                pass.name,
+                pass.duration.as_secs_f64() * 1000.0
+            )?;
+        }
+        if self.validation_errors > 0 || self.validation_warnings > 0 {
+            write!(
+                f,
+                ", {} validation errors, {} warnings",
+                self.validation_errors, self.validation_warnings
+            )?;
+        }
+        Ok(())
+    }
+}
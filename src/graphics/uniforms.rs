@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::{
+    buffer::{cpu_pool::CpuBufferPoolSubbuffer, BufferAccess, BufferContents, CpuBufferPool},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Device,
+    pipeline::{Pipeline, PipelineLayout},
+};
+
+use super::camera::{Mat4, IDENTITY};
+
+// the `Frame` block of assets/shaders/frame.glsl, laid out for std140
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Zeroable, Pod)]
+pub struct FrameUniforms {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
+    // the logical render extent in pixels
+    pub resolution: [f32; 2],
+    // seconds since the context was created and since the previous frame
+    pub time: f32,
+    pub delta_time: f32,
+}
+
+impl Default for FrameUniforms {
+    fn default() -> Self {
+        Self {
+            view: IDENTITY,
+            projection: IDENTITY,
+            view_projection: IDENTITY,
+            resolution: [0.0; 2],
+            time: 0.0,
+            delta_time: 0.0,
+        }
+    }
+}
+
+// hands out a fresh uniform buffer for every draw. the memory is recycled once the command
+// buffers using it are dropped, so a pool is meant to live as long as its renderer
+pub struct UniformPool<T> {
+    pool: CpuBufferPool<T>,
+}
+
+impl<T> UniformPool<T>
+where
+    T: Pod + Send + Sync,
+    [T]: BufferContents,
+{
+    pub fn new(device: Arc<Device>) -> Self {
+        Self {
+            pool: CpuBufferPool::uniform_buffer(device),
+        }
+    }
+
+    pub fn next(&self, data: T) -> anyhow::Result<Arc<CpuBufferPoolSubbuffer<T>>> {
+        Ok(self.pool.from_data(data)?)
+    }
+
+    // a set with the data at binding 0, allocated against the layout of `set` in the pipeline
+    pub fn descriptor_set(
+        &self,
+        allocator: &StandardDescriptorSetAllocator,
+        pipeline: &impl Pipeline,
+        set: usize,
+        data: T,
+    ) -> anyhow::Result<Arc<PersistentDescriptorSet>> {
+        uniform_set(allocator, pipeline.layout(), set, self.next(data)?)
+    }
+}
+
+pub(crate) fn uniform_set(
+    allocator: &StandardDescriptorSetAllocator,
+    layout: &Arc<PipelineLayout>,
+    set: usize,
+    buffer: Arc<impl BufferAccess + 'static>,
+) -> anyhow::Result<Arc<PersistentDescriptorSet>> {
+    let set_layout = layout
+        .set_layouts()
+        .get(set)
+        .ok_or_else(|| anyhow::anyhow!("The pipeline has no descriptor set {}", set))?;
+    Ok(PersistentDescriptorSet::new(
+        allocator,
+        set_layout.clone(),
+        [WriteDescriptorSet::buffer(0, buffer)],
+    )?)
+}
@@ -0,0 +1,590 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use bytemuck::Pod;
+use vulkano::{
+    buffer::{
+        BufferAccess, BufferContents, BufferUsage, CpuBufferPool, DeviceLocalBuffer,
+        TypedBufferAccess,
+    },
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BufferCopy,
+        ClearColorImageInfo, CommandBufferUsage, CopyBufferInfo, CopyBufferToImageInfo,
+        PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
+    },
+    device::{Device, DeviceOwned, Queue},
+    format::{ClearColorValue, Format},
+    image::{
+        view::ImageView, ImageAccess, ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage,
+    },
+    sync::GpuFuture,
+    DeviceSize,
+};
+
+use super::{
+    ownership::{OwnedResource, OwnershipTransfer},
+    texture::Texture,
+};
+
+// staging chunks are made of whole blocks, so every chunk starts at an offset that is a multiple
+// of the largest texel block size, as copies into images require
+type StagingBlock = [u8; 16];
+const STAGING_BLOCK_SIZE: usize = std::mem::size_of::<StagingBlock>();
+// the staging ring grows beyond this when a frame uploads more
+const INITIAL_STAGING_SIZE: DeviceSize = 4 * 1024 * 1024;
+
+enum PendingCopy {
+    Buffer(CopyBufferInfo),
+    Image(CopyBufferToImageInfo),
+    Clear(ClearColorImageInfo),
+}
+
+impl PendingCopy {
+    // clears are only recorded at the start of a frame, on the graphics queue
+    fn destination(&self) -> Option<OwnedResource> {
+        match self {
+            PendingCopy::Buffer(info) => Some(OwnedResource::Buffer(info.dst_buffer.clone())),
+            PendingCopy::Image(info) => Some(OwnedResource::Image(info.dst_image.clone())),
+            PendingCopy::Clear(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AllocationKind {
+    Buffer,
+    Image,
+}
+
+struct TrackedAllocation {
+    resource: Weak<dyn Send + Sync>,
+    kind: AllocationKind,
+    size: DeviceSize,
+}
+
+// a batch of copies submitted to the transfer queue. the next frame waits on its semaphore
+// before it starts, and sets `ready` once it has finished. with a dedicated transfer queue the
+// frame also submits the acquire half of the ownership transfer before it
+pub(crate) struct SubmittedUpload {
+    pub future: Box<dyn GpuFuture + Send>,
+    pub ready: Arc<AtomicBool>,
+    pub ownership_transfer: Option<OwnershipTransfer>,
+}
+
+// a resource whose data is being copied on the transfer queue. it may be used by any frame
+// rendered after the upload was started, those frames wait for the copy on the GPU
+pub struct AsyncUpload<R> {
+    pub resource: R,
+    ready: Arc<AtomicBool>,
+}
+
+impl<R> AsyncUpload<R> {
+    // true once a frame that waited for the copy has finished
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+}
+
+// uploads data into device-local buffers and images. the data is copied into a host-visible
+// staging ring right away, and the copies into device-local memory are recorded at the start of
+// the next frame, before anything else in it. staging memory is reused once the frame that
+// copied from it has finished.
+//
+// the async variants record their copies into their own command buffer on the transfer queue
+// instead, which may be called from any thread. every resource is owned exclusively by the
+// graphics queue family, so when the transfer queue belongs to another family the copies are
+// followed by a release barrier, and the next frame acquires the resources before it runs
+pub struct UploadQueue {
+    device: Arc<Device>,
+    graphics_queue: Arc<Queue>,
+    transfer_queue: Option<Arc<Queue>>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    staging: CpuBufferPool<StagingBlock>,
+    pending: Mutex<Vec<PendingCopy>>,
+    submitted: Mutex<Vec<SubmittedUpload>>,
+    allocations: Mutex<Vec<TrackedAllocation>>,
+}
+
+impl UploadQueue {
+    // `transfer_queue` runs the async uploads, they run on the graphics queue without one
+    pub fn new(
+        graphics_queue: Arc<Queue>,
+        transfer_queue: Option<Arc<Queue>>,
+        command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    ) -> anyhow::Result<Self> {
+        let device = graphics_queue.device().clone();
+        let staging = CpuBufferPool::upload(device.clone());
+        staging.reserve(INITIAL_STAGING_SIZE / STAGING_BLOCK_SIZE as DeviceSize)?;
+        Ok(Self {
+            device,
+            graphics_queue,
+            transfer_queue,
+            command_buffer_allocator,
+            staging,
+            pending: Mutex::new(Vec::new()),
+            submitted: Mutex::new(Vec::new()),
+            allocations: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn stage(&self, bytes: &[u8]) -> anyhow::Result<Arc<dyn BufferAccess>> {
+        let blocks = bytes.chunks(STAGING_BLOCK_SIZE).map(|chunk| {
+            let mut block = [0; STAGING_BLOCK_SIZE];
+            block[..chunk.len()].copy_from_slice(chunk);
+            block
+        });
+        Ok(self.staging.from_iter(blocks)?)
+    }
+
+    fn track(&self, resource: Weak<dyn Send + Sync>, kind: AllocationKind, size: DeviceSize) {
+        self.allocations.lock().unwrap().push(TrackedAllocation {
+            resource,
+            kind,
+            size,
+        });
+    }
+
+    // a device-local buffer holding `data`, usable from the next frame on
+    pub fn buffer<T>(
+        &self,
+        usage: BufferUsage,
+        data: &[T],
+    ) -> anyhow::Result<Arc<DeviceLocalBuffer<[T]>>>
+    where
+        T: Pod + Send + Sync,
+        [T]: BufferContents,
+    {
+        let (buffer, copy) = self.stage_buffer(usage, data)?;
+        self.pending.lock().unwrap().push(copy);
+        Ok(buffer)
+    }
+
+    // like `buffer`, but copied on the transfer queue without waiting for the next frame
+    pub fn buffer_async<T>(
+        &self,
+        usage: BufferUsage,
+        data: &[T],
+    ) -> anyhow::Result<AsyncUpload<Arc<DeviceLocalBuffer<[T]>>>>
+    where
+        T: Pod + Send + Sync,
+        [T]: BufferContents,
+    {
+        let (buffer, copy) = self.stage_buffer(usage, data)?;
+        self.submit(buffer, vec![copy])
+    }
+
+    fn stage_buffer<T>(
+        &self,
+        usage: BufferUsage,
+        data: &[T],
+    ) -> anyhow::Result<(Arc<DeviceLocalBuffer<[T]>>, PendingCopy)>
+    where
+        T: Pod + Send + Sync,
+        [T]: BufferContents,
+    {
+        anyhow::ensure!(!data.is_empty(), "Cannot create an empty buffer");
+        let buffer = DeviceLocalBuffer::array(
+            self.device.clone(),
+            data.len() as DeviceSize,
+            BufferUsage {
+                transfer_dst: true,
+                ..usage
+            },
+            [self.graphics_queue.queue_family_index()],
+        )?;
+        self.track(
+            Arc::downgrade(&buffer) as Weak<dyn Send + Sync>,
+            AllocationKind::Buffer,
+            buffer.size(),
+        );
+        let copy = self.stage_update(buffer.clone(), 0, data)?;
+        Ok((buffer, copy))
+    }
+
+    // overwrites the elements of `buffer` starting at `offset`. copies are recorded in the order
+    // they were requested, so a later update of the same range wins
+    pub fn update_buffer<T>(
+        &self,
+        buffer: Arc<DeviceLocalBuffer<[T]>>,
+        offset: DeviceSize,
+        data: &[T],
+    ) -> anyhow::Result<()>
+    where
+        T: Pod + Send + Sync,
+        [T]: BufferContents,
+    {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let copy = self.stage_update(buffer, offset, data)?;
+        self.pending.lock().unwrap().push(copy);
+        Ok(())
+    }
+
+    fn stage_update<T>(
+        &self,
+        buffer: Arc<DeviceLocalBuffer<[T]>>,
+        offset: DeviceSize,
+        data: &[T],
+    ) -> anyhow::Result<PendingCopy>
+    where
+        T: Pod + Send + Sync,
+        [T]: BufferContents,
+    {
+        anyhow::ensure!(
+            offset + data.len() as DeviceSize <= buffer.len(),
+            "Update of {} elements at {} is out of bounds for a buffer of {}",
+            data.len(),
+            offset,
+            buffer.len()
+        );
+        let element_size = std::mem::size_of::<T>() as DeviceSize;
+        let staging = self.stage(bytemuck::cast_slice(data))?;
+        Ok(PendingCopy::Buffer(CopyBufferInfo {
+            regions: [BufferCopy {
+                src_offset: 0,
+                dst_offset: offset * element_size,
+                size: data.len() as DeviceSize * element_size,
+                ..Default::default()
+            }]
+            .into(),
+            ..CopyBufferInfo::buffers(staging, buffer)
+        }))
+    }
+
+    // a sampled 2D image with a single mip level, `pixels` are tightly packed rows of an
+    // uncompressed `format`
+    pub fn image(
+        &self,
+        format: Format,
+        extent: [u32; 2],
+        pixels: &[u8],
+    ) -> anyhow::Result<Arc<StorageImage>> {
+        let (image, copy) = self.stage_image(format, extent, pixels)?;
+        self.pending.lock().unwrap().push(copy);
+        Ok(image)
+    }
+
+    // like `image`, but copied on the transfer queue without waiting for the next frame
+    pub fn image_async(
+        &self,
+        format: Format,
+        extent: [u32; 2],
+        pixels: &[u8],
+    ) -> anyhow::Result<AsyncUpload<Arc<StorageImage>>> {
+        let (image, copy) = self.stage_image(format, extent, pixels)?;
+        self.submit(image, vec![copy])
+    }
+
+    // an sRGB RGBA8 texture without mipmaps, `TextureLoader` generates them but copies on the
+    // graphics queue
+    pub fn texture_async(
+        &self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> anyhow::Result<AsyncUpload<Arc<Texture>>> {
+        let (image, copy) = self.stage_image(Format::R8G8B8A8_SRGB, [width, height], pixels)?;
+        let texture = Arc::new(Texture {
+            view: ImageView::new_default(image.clone())?,
+            image,
+            width,
+            height,
+        });
+        self.submit(texture, vec![copy])
+    }
+
+    // a sampled 2D image filled with `color`, for images that are only ever updated in parts
+    pub fn cleared_image(
+        &self,
+        format: Format,
+        extent: [u32; 2],
+        color: [f32; 4],
+    ) -> anyhow::Result<Arc<StorageImage>> {
+        let image = self.create_image(format, extent)?;
+        self.pending
+            .lock()
+            .unwrap()
+            .push(PendingCopy::Clear(ClearColorImageInfo {
+                clear_value: ClearColorValue::Float(color),
+                ..ClearColorImageInfo::image(image.clone())
+            }));
+        Ok(image)
+    }
+
+    // overwrites the rectangle of `image` at `offset`, `pixels` are tightly packed rows in the
+    // format of the image. copies are recorded in the order they were requested
+    pub fn update_image(
+        &self,
+        image: Arc<StorageImage>,
+        offset: [u32; 2],
+        extent: [u32; 2],
+        pixels: &[u8],
+    ) -> anyhow::Result<()> {
+        let [width, height] = image.dimensions().width_height();
+        anyhow::ensure!(
+            offset[0] + extent[0] <= width && offset[1] + extent[1] <= height,
+            "Update of {}x{} pixels at {:?} is out of bounds for a {}x{} image",
+            extent[0],
+            extent[1],
+            offset,
+            width,
+            height
+        );
+        if extent[0] == 0 || extent[1] == 0 {
+            return Ok(());
+        }
+        check_pixels(image.format(), extent, pixels)?;
+        let staging = self.stage(pixels)?;
+        let mut copy = CopyBufferToImageInfo::buffer_image(staging, image);
+        copy.regions[0].image_offset = [offset[0], offset[1], 0];
+        copy.regions[0].image_extent = [extent[0], extent[1], 1];
+        self.pending.lock().unwrap().push(PendingCopy::Image(copy));
+        Ok(())
+    }
+
+    fn stage_image(
+        &self,
+        format: Format,
+        extent: [u32; 2],
+        pixels: &[u8],
+    ) -> anyhow::Result<(Arc<StorageImage>, PendingCopy)> {
+        check_pixels(format, extent, pixels)?;
+        let image = self.create_image(format, extent)?;
+        let staging = self.stage(pixels)?;
+        let copy = PendingCopy::Image(CopyBufferToImageInfo::buffer_image(staging, image.clone()));
+        Ok((image, copy))
+    }
+
+    fn create_image(&self, format: Format, extent: [u32; 2]) -> anyhow::Result<Arc<StorageImage>> {
+        let image = StorageImage::with_usage(
+            self.device.clone(),
+            ImageDimensions::Dim2d {
+                width: extent[0],
+                height: extent[1],
+                array_layers: 1,
+            },
+            format,
+            ImageUsage {
+                transfer_dst: true,
+                sampled: true,
+                ..ImageUsage::empty()
+            },
+            ImageCreateFlags::empty(),
+            [self.graphics_queue.queue_family_index()],
+        )?;
+        let size = extent[0] as DeviceSize * extent[1] as DeviceSize * block_size(format)?;
+        self.track(
+            Arc::downgrade(&image) as Weak<dyn Send + Sync>,
+            AllocationKind::Image,
+            size,
+        );
+        Ok(image)
+    }
+
+    fn submit<R>(&self, resource: R, copies: Vec<PendingCopy>) -> anyhow::Result<AsyncUpload<R>> {
+        let queue = self.transfer_queue.as_ref().unwrap_or(&self.graphics_queue);
+        let graphics_family = self.graphics_queue.queue_family_index();
+        let transferred = match queue.queue_family_index() == graphics_family {
+            true => Vec::new(),
+            false => copies.iter().filter_map(PendingCopy::destination).collect(),
+        };
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.as_ref(),
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        record_copies(&mut builder, copies)?;
+        let future = builder
+            .build()?
+            .execute(queue.clone())?
+            .then_signal_semaphore_and_flush()?;
+        let ownership_transfer = match transferred.is_empty() {
+            true => None,
+            false => Some(OwnershipTransfer::release(
+                queue,
+                graphics_family,
+                transferred,
+            )?),
+        };
+        let ready = Arc::new(AtomicBool::new(false));
+        self.submitted.lock().unwrap().push(SubmittedUpload {
+            future: Box::new(future),
+            ready: ready.clone(),
+            ownership_transfer,
+        });
+        Ok(AsyncUpload { resource, ready })
+    }
+
+    // the async uploads submitted since the last call, for the next frame to wait on
+    pub(crate) fn take_submitted(&self) -> Vec<SubmittedUpload> {
+        std::mem::take(&mut *self.submitted.lock().unwrap())
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.lock().unwrap().is_empty()
+    }
+
+    // records every pending copy, must be called outside of a render pass
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> anyhow::Result<()> {
+        let copies = std::mem::take(&mut *self.pending.lock().unwrap());
+        record_copies(builder, copies)
+    }
+
+    // submits the pending copies on the graphics queue right away and waits for them, for
+    // uploads made while no frames are rendered
+    pub fn flush(&self) -> anyhow::Result<()> {
+        if !self.has_pending() {
+            return Ok(());
+        }
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.as_ref(),
+            self.graphics_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        self.record(&mut builder)?;
+        builder
+            .build()?
+            .execute(self.graphics_queue.clone())?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+        Ok(())
+    }
+
+    // memory of buffers and images created through this queue, and of the staging ring. other
+    // allocations are not tracked. vulkano picks the first memory type that fits, so allocations
+    // are attributed to the heap of the first device-local or host-visible type
+    pub fn memory_report(&self) -> MemoryReport {
+        let properties = self.device.physical_device().memory_properties();
+        let mut heaps = properties
+            .memory_heaps
+            .iter()
+            .enumerate()
+            .map(|(index, heap)| HeapUsage {
+                index: index as u32,
+                size: heap.size,
+                device_local: heap.flags.device_local,
+                buffers: 0,
+                images: 0,
+                staging: 0,
+            })
+            .collect::<Vec<_>>();
+        let heap_of = |device_local: bool| {
+            properties
+                .memory_types
+                .iter()
+                .find(|ty| {
+                    if device_local {
+                        ty.property_flags.device_local
+                    } else {
+                        ty.property_flags.host_visible
+                    }
+                })
+                .map(|ty| ty.heap_index as usize)
+        };
+
+        let mut allocations = self.allocations.lock().unwrap();
+        allocations.retain(|allocation| allocation.resource.strong_count() > 0);
+        if let Some(heap) = heap_of(true).and_then(|index| heaps.get_mut(index)) {
+            for allocation in allocations.iter() {
+                match allocation.kind {
+                    AllocationKind::Buffer => heap.buffers += allocation.size,
+                    AllocationKind::Image => heap.images += allocation.size,
+                }
+            }
+        }
+        if let Some(heap) = heap_of(false).and_then(|index| heaps.get_mut(index)) {
+            heap.staging += self.staging.capacity() * STAGING_BLOCK_SIZE as DeviceSize;
+        }
+        MemoryReport { heaps }
+    }
+}
+
+fn record_copies(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    copies: Vec<PendingCopy>,
+) -> anyhow::Result<()> {
+    for copy in copies {
+        match copy {
+            PendingCopy::Buffer(info) => builder.copy_buffer(info)?,
+            PendingCopy::Image(info) => builder.copy_buffer_to_image(info)?,
+            PendingCopy::Clear(info) => builder.clear_color_image(info)?,
+        };
+    }
+    Ok(())
+}
+
+fn block_size(format: Format) -> anyhow::Result<DeviceSize> {
+    format
+        .block_size()
+        .ok_or_else(|| anyhow::anyhow!("{:?} has no fixed block size", format))
+}
+
+fn check_pixels(format: Format, extent: [u32; 2], pixels: &[u8]) -> anyhow::Result<()> {
+    let size = extent[0] as DeviceSize * extent[1] as DeviceSize * block_size(format)?;
+    anyhow::ensure!(
+        pixels.len() as DeviceSize == size,
+        "Expected {} bytes of pixel data, got {}",
+        size,
+        pixels.len()
+    );
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct HeapUsage {
+    pub index: u32,
+    pub size: DeviceSize,
+    pub device_local: bool,
+    // bytes of live buffers and images, and the capacity of the staging ring
+    pub buffers: DeviceSize,
+    pub images: DeviceSize,
+    pub staging: DeviceSize,
+}
+
+impl HeapUsage {
+    pub fn used(&self) -> DeviceSize {
+        self.buffers + self.images + self.staging
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryReport {
+    pub heaps: Vec<HeapUsage>,
+}
+
+fn mib(bytes: DeviceSize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "memory:")?;
+        for heap in &self.heaps {
+            write!(
+                f,
+                "\n  heap {}{}: {:.1} / {:.1} MiB (buffers {:.1}, images {:.1}, staging {:.1})",
+                heap.index,
+                if heap.device_local {
+                    " (device local)"
+                } else {
+                    ""
+                },
+                mib(heap.used()),
+                mib(heap.size),
+                mib(heap.buffers),
+                mib(heap.images),
+                mib(heap.staging)
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use vulkano::{
+    instance::{
+        debug::{
+            DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessenger,
+            DebugUtilsMessengerCreateInfo, Message,
+        },
+        Instance,
+    },
+    VulkanLibrary,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ValidationSeverity {
+    Error,
+    Warning,
+    Info,
+    Verbose,
+}
+
+impl ValidationSeverity {
+    fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "error" => Self::Error,
+            "warning" => Self::Warning,
+            "info" => Self::Info,
+            "verbose" => Self::Verbose,
+            _ => return None,
+        })
+    }
+}
+
+// defaults to the Khronos validation layer with every severity in debug builds, and to no layers
+// in release builds. the environment overrides the defaults:
+// AMK_VALIDATION=off|error|warning|info|verbose, AMK_VALIDATION_LAYERS=<comma separated names>
+// and AMK_VALIDATION_STRICT=1
+#[derive(Clone, Debug)]
+pub struct ValidationConfig {
+    pub(crate) enabled: bool,
+    pub(crate) layers: Vec<String>,
+    pub(crate) severity: ValidationSeverity,
+    pub(crate) strict: bool,
+}
+
+impl ValidationConfig {
+    pub fn new() -> Self {
+        let mut config = Self {
+            enabled: cfg!(debug_assertions),
+            layers: vec!["VK_LAYER_KHRONOS_validation".to_owned()],
+            severity: ValidationSeverity::Verbose,
+            strict: false,
+        };
+        if let Ok(value) = env::var("AMK_VALIDATION") {
+            match (value.as_str(), ValidationSeverity::parse(&value)) {
+                ("off", _) => config.enabled = false,
+                (_, Some(severity)) => {
+                    config.enabled = true;
+                    config.severity = severity;
+                }
+                _ => log::warn!("Ignoring unknown AMK_VALIDATION value {:?}", value),
+            }
+        }
+        if let Ok(layers) = env::var("AMK_VALIDATION_LAYERS") {
+            config.layers = layers
+                .split(',')
+                .map(|layer| layer.trim().to_owned())
+                .filter(|layer| !layer.is_empty())
+                .collect();
+        }
+        if env::var("AMK_VALIDATION_STRICT").map_or(false, |value| value == "1") {
+            config.enabled = true;
+            config.strict = true;
+        }
+        config
+    }
+
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    pub fn layers(mut self, layers: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.layers = layers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    // messages below this severity are not reported, errors are always counted
+    pub fn severity(mut self, severity: ValidationSeverity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    // `RenderContext::render` fails with the first validation error reported during the frame,
+    // which stops the render loop or fails the test that rendered it
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // the configured layers that are installed, missing ones are skipped with a warning
+    pub(crate) fn available_layers(&self, lib: &VulkanLibrary) -> anyhow::Result<Vec<String>> {
+        if !self.enabled {
+            return Ok(Vec::new());
+        }
+        let installed = lib
+            .layer_properties()?
+            .map(|layer| String::from(layer.name().trim_end_matches('\0')))
+            .collect::<Vec<_>>();
+        Ok(self
+            .layers
+            .iter()
+            .filter(|layer| {
+                let found = installed.contains(layer);
+                if !found {
+                    log::warn!("Validation layer {} is not installed", layer);
+                }
+                found
+            })
+            .cloned()
+            .collect())
+    }
+}
+
+// validation messages counted since the last frame, shared with the messenger callback
+#[derive(Default)]
+pub struct ValidationState {
+    errors: AtomicUsize,
+    warnings: AtomicUsize,
+    first_error: Mutex<Option<String>>,
+}
+
+impl ValidationState {
+    // (errors, warnings) reported since the last call
+    pub fn take_counts(&self) -> (usize, usize) {
+        (
+            self.errors.swap(0, Ordering::Relaxed),
+            self.warnings.swap(0, Ordering::Relaxed),
+        )
+    }
+
+    pub fn take_error(&self) -> Option<String> {
+        self.first_error.lock().unwrap().take()
+    }
+
+    fn report(&self, msg: &Message, strict: bool) {
+        if msg.severity.error {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            if strict && msg.ty.validation {
+                self.first_error
+                    .lock()
+                    .unwrap()
+                    .get_or_insert_with(|| msg.description.to_owned());
+            }
+        } else if msg.severity.warning {
+            self.warnings.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+pub(crate) fn create_messenger(
+    instance: Arc<Instance>,
+    config: &ValidationConfig,
+    state: Arc<ValidationState>,
+) -> anyhow::Result<DebugUtilsMessenger> {
+    let severity = config.severity;
+    let strict = config.strict;
+    Ok(unsafe {
+        DebugUtilsMessenger::new(
+            instance,
+            DebugUtilsMessengerCreateInfo {
+                message_severity: DebugUtilsMessageSeverity {
+                    error: true,
+                    warning: severity >= ValidationSeverity::Warning,
+                    information: severity >= ValidationSeverity::Info,
+                    verbose: severity >= ValidationSeverity::Verbose,
+                    ..Default::default()
+                },
+                message_type: DebugUtilsMessageType {
+                    general: true,
+                    validation: true,
+                    performance: true,
+                    ..Default::default()
+                },
+                ..DebugUtilsMessengerCreateInfo::user_callback(Arc::new(move |msg| {
+                    state.report(msg, strict);
+                    if msg.severity.error {
+                        log::error!(target: "vk", "{}", msg.description);
+                    } else if msg.severity.warning {
+                        log::warn!(target: "vk", "{}", msg.description);
+                    } else if msg.severity.information {
+                        log::info!(target: "vk", "{}", msg.description);
+                    } else if msg.severity.verbose {
+                        log::debug!(target: "vk", "{}", msg.description);
+                    }
+                }))
+            },
+        )?
+    })
+}
@@ -4,15 +4,23 @@
 
 use std::sync::Arc;
 
+use assets::manager::AssetManager;
 use exec::{
     loop_impl::RenderLoop,
     manager::MAIN_THREAD_ID,
     mode::Mode,
     msg::{ELGLMMsg, ELRLMsg},
 };
-use graphics::context::RenderContext;
+use graphics::{
+    color::OutputColorSpace,
+    config::RenderConfig,
+    context::RenderContext,
+    device::{device_report, DeviceSelector},
+    dump::FrameDumpConfig,
+};
 use logging::init_log;
 use scenes::root::RootScene;
+use vfs::default_vfs;
 use winit::{dpi::PhysicalSize, window::WindowBuilder};
 
 use crate::exec::{
@@ -20,30 +28,57 @@ use crate::exec::{
     manager::{GameLoopManager, WinitEventLoop},
 };
 
+pub mod assets;
 pub mod exec;
 pub mod graphics;
 pub mod logging;
 pub mod scenes;
 pub mod utils;
+pub mod vfs;
 
 fn main() -> anyhow::Result<()> {
     init_log()?;
     let window_event_loop = WinitEventLoop::new();
+    if std::env::args().any(|arg| arg == "--list-gpus") {
+        // surface formats and present modes can only be queried against a window
+        let window = WindowBuilder::new()
+            .with_visible(false)
+            .build(&window_event_loop)?;
+        print!("{}", device_report(&window)?);
+        return Ok(());
+    }
     let window = WindowBuilder::new()
         .with_inner_size(PhysicalSize::new(1280, 720))
         .with_title("hello")
         .build(&window_event_loop)?;
 
-    let root_scene = Arc::new(RootScene::new());
+    let assets = AssetManager::new(default_vfs()?);
+    let root_scene = Arc::new(RootScene::new(assets.clone()));
 
     // ELRL communication channels
     let (elrl_sender, elrl_receiver) = std::sync::mpsc::channel::<ELRLMsg>();
     // EventLoop-GameLoopManager (ELGLM) communication channels
     let (elglm_sender, elglm_receiver) = std::sync::mpsc::channel::<ELGLMMsg>();
 
+    let mut render_ctx = RenderContext::new(
+        &window,
+        RenderConfig::new()
+            .vfs(assets.vfs().clone())
+            .frames_in_flight(2)
+            .samples(4)
+            .depth_stencil(true)
+            .device(DeviceSelector::from_args()?.unwrap_or(DeviceSelector::Auto))
+            .output_color_space(OutputColorSpace::from_args().unwrap_or(OutputColorSpace::Srgb)),
+    )?;
+    render_ctx.register_asset_loaders(&assets);
+    if let Some(config) = FrameDumpConfig::from_args() {
+        render_ctx.start_frame_dump(config)?;
+    }
+    let dump_clock = render_ctx.frame_dump().map(|dump| dump.clock());
+    let dump_sink = render_ctx.frame_dump().and_then(|dump| dump.audio_sink());
     let render_loop = RenderLoop {
         root_scene: root_scene.clone(),
-        render_ctx: RenderContext::new(&window)?,
+        render_ctx,
         new_size: None,
         elrl_receiver,
     };
@@ -56,7 +91,7 @@ fn main() -> anyhow::Result<()> {
     let update_loop = UpdateLoop {
         root_scene: root_scene.clone(),
     };
-    let audio_loop = AudioLoop { root_scene };
+    let audio_loop = AudioLoop::new(root_scene, dump_sink);
 
     let manager = GameLoopManager::new_moded(
         event_loop,
@@ -71,6 +106,7 @@ fn main() -> anyhow::Result<()> {
             .update(0, 1.0)
             .render(1, 1.0)
             .audio(2, 1.0),
+        dump_clock,
     );
     manager.run(window_event_loop, elglm_receiver);
 }
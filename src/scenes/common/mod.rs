@@ -1,2 +1,3 @@
 pub mod close_window;
-pub mod resize_window;
\ No newline at end of file
+pub mod resize_window;
+pub mod screenshot;
\ No newline at end of file
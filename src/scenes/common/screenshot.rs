@@ -0,0 +1,33 @@
+use std::sync::mpsc::Sender;
+
+use winit::{
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    window::WindowId,
+};
+
+use crate::exec::msg::ELRLMsg;
+
+pub(crate) struct ScreenshotScene;
+impl ScreenshotScene {
+    pub fn handle_event(e: &Event<()>, wid: WindowId, sender: &Sender<ELRLMsg>) -> bool {
+        match e {
+            Event::WindowEvent {
+                window_id,
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F12),
+                                ..
+                            },
+                        ..
+                    },
+            } if *window_id == wid => {
+                sender.send(ELRLMsg::Screenshot).unwrap();
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -1,4 +1,10 @@
-use std::time::{Duration, SystemTime};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SystemInstant(SystemTime);
@@ -40,3 +46,53 @@ impl Clock for InstantClock {
         std::time::Instant::now()
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VirtualInstant(Duration);
+
+impl std::ops::Sub<VirtualInstant> for VirtualInstant {
+    type Output = Duration;
+
+    fn sub(self, rhs: VirtualInstant) -> Self::Output {
+        self.0 - rhs.0
+    }
+}
+impl std::ops::Add<Duration> for VirtualInstant {
+    type Output = VirtualInstant;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        VirtualInstant(self.0 + rhs)
+    }
+}
+impl std::ops::Sub<Duration> for VirtualInstant {
+    type Output = VirtualInstant;
+
+    fn sub(self, rhs: Duration) -> Self::Output {
+        VirtualInstant(self.0.saturating_sub(rhs))
+    }
+}
+impl SubtractableInstant for VirtualInstant {}
+impl ConstructibleInstant for VirtualInstant {}
+
+// only moves when advanced, so runs driven by it do not depend on how fast the machine is.
+// clones share the same time
+#[derive(Clone, Default)]
+pub struct VirtualClock {
+    nanos: Arc<AtomicU64>,
+}
+impl VirtualClock {
+    pub fn advance(&self, step: Duration) {
+        self.nanos
+            .fetch_add(step.as_nanos() as u64, Ordering::AcqRel);
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Acquire))
+    }
+}
+impl Clock for VirtualClock {
+    type Instant = VirtualInstant;
+    fn now(&self) -> VirtualInstant {
+        VirtualInstant(self.elapsed())
+    }
+}
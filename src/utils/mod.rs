@@ -1,2 +1,3 @@
 pub mod sync;
-pub mod clock;
\ No newline at end of file
+pub mod clock;
+pub mod watch;
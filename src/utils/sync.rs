@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use super::clock::{Clock, SubtractableInstant, InstantClock};
+use super::clock::{Clock, SubtractableInstant, InstantClock, VirtualClock, VirtualInstant};
 
 pub trait ClockSync {
     fn sync(&mut self, frequency: f64) {
@@ -49,6 +49,75 @@ impl<I: SubtractableInstant> OFClockSync<I> {
     }
 }
 
-pub fn new_clock_sync() -> Box<dyn ClockSync> {
-    Box::new(OFClockSync::new(Box::new(InstantClock)))
+// paces a loop by a virtual clock that something else advances, e.g. a frame dump. instead of
+// sleeping for the rest of the period it waits until the clock has reached the next tick, so
+// the loop runs as often per virtual second as it would per real second
+pub struct VirtualClockSync {
+    clock: VirtualClock,
+    next_tick: VirtualInstant,
+}
+
+impl ClockSync for VirtualClockSync {
+    fn sync_impl(&mut self, frequency: f64) {
+        const POLL_INTERVAL: Duration = Duration::from_micros(100);
+        let period = Duration::from_secs_f64(1.0 / frequency);
+        // never falls more than one period behind, like `OFClockSync` limits its lag
+        self.next_tick = (self.next_tick + period).max(self.clock.now() - period);
+        while self.clock.now() < self.next_tick {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl VirtualClockSync {
+    pub fn new(clock: VirtualClock) -> Self {
+        Self {
+            next_tick: clock.now(),
+            clock,
+        }
+    }
+}
+
+// `None` follows the wall clock
+pub fn new_clock_sync(clock: Option<VirtualClock>) -> Box<dyn ClockSync> {
+    match clock {
+        Some(clock) => Box::new(VirtualClockSync::new(clock)),
+        None => Box::new(OFClockSync::new(Box::new(InstantClock))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread, time::Duration};
+
+    use super::*;
+
+    // a 50 Hz loop runs twice per frame of a 25 fps dump. the clock only advances once the loop
+    // has reported both ticks of the previous frame, so every tick has to see the time of the
+    // frame it belongs to, a tick that did not wait would see the previous one
+    #[test]
+    fn virtual_clock_paces_loops() {
+        const FRAME: Duration = Duration::from_millis(40);
+        let clock = VirtualClock::default();
+        let (sender, ticks) = mpsc::channel();
+        // created before the clock moves, the first tick is one period after it
+        let mut sync = VirtualClockSync::new(clock.clone());
+        let ticker = {
+            let clock = clock.clone();
+            thread::spawn(move || {
+                for _ in 0..6 {
+                    sync.sync(50.0);
+                    sender.send(clock.elapsed()).unwrap();
+                }
+            })
+        };
+        for frame in 1..=3 {
+            clock.advance(FRAME);
+            for _ in 0..2 {
+                assert_eq!(ticks.recv().unwrap(), FRAME * frame);
+            }
+        }
+        ticker.join().unwrap();
+        assert!(ticks.try_recv().is_err());
+    }
 }
@@ -0,0 +1,366 @@
+// the packed asset archive format, shared by the game and the `pack` tool. all integers are
+// little endian:
+//
+//   header: magic, version: u32, entry count: u32, index offset: u64
+//   blobs:  the stored bytes of every file, one after another
+//   index:  per entry, path length: u16, path (utf-8, '/' separated), offset: u64,
+//           stored size: u64, size: u64, crc32 of the original bytes: u32, compression: u8
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::{Component, Path},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+
+pub const MAGIC: [u8; 8] = *b"AMKPACK\0";
+pub const VERSION: u32 = 1;
+const HEADER_SIZE: u64 = 8 + 4 + 4 + 8;
+// an index entry with an empty path
+const MIN_ENTRY_SIZE: u64 = 2 + 8 + 8 + 8 + 4 + 1;
+// deflate cannot expand data by more than this, larger sizes in the index are corrupt
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None = 0,
+    Deflate = 1,
+}
+
+impl Compression {
+    fn from_u8(value: u8) -> anyhow::Result<Self> {
+        match value {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Deflate),
+            _ => Err(anyhow::anyhow!("Unknown compression {}", value)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ArchiveEntry {
+    pub offset: u64,
+    pub stored_size: u64,
+    pub size: u64,
+    pub checksum: u32,
+    pub compression: Compression,
+}
+
+// the name of a file in an archive, None for paths that leave the archive root
+pub fn archive_path(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+pub struct ArchiveWriter<W: Write + Seek> {
+    out: W,
+    entries: Vec<(String, ArchiveEntry)>,
+    offset: u64,
+}
+
+impl<W: Write + Seek> ArchiveWriter<W> {
+    pub fn new(mut out: W) -> anyhow::Result<Self> {
+        // the header is rewritten with the real values by `finish`
+        out.write_all(&[0; HEADER_SIZE as usize])?;
+        Ok(Self {
+            out,
+            entries: Vec::new(),
+            offset: HEADER_SIZE,
+        })
+    }
+
+    // files that deflate does not make smaller, like png images, are stored as they are
+    pub fn add(&mut self, path: &str, data: &[u8]) -> anyhow::Result<&ArchiveEntry> {
+        anyhow::ensure!(
+            path.len() <= u16::MAX as usize,
+            "The path {} is too long",
+            path
+        );
+        anyhow::ensure!(
+            self.entries.iter().all(|(name, _)| name != path),
+            "{} was added twice",
+            path
+        );
+        let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(data)?;
+        let deflated = encoder.finish()?;
+        let (compression, stored) = match deflated.len() < data.len() {
+            true => (Compression::Deflate, deflated.as_slice()),
+            false => (Compression::None, data),
+        };
+        self.out.write_all(stored)?;
+        let entry = ArchiveEntry {
+            offset: self.offset,
+            stored_size: stored.len() as u64,
+            size: data.len() as u64,
+            checksum: crc32fast::hash(data),
+            compression,
+        };
+        self.offset += entry.stored_size;
+        self.entries.push((path.to_owned(), entry));
+        Ok(&self.entries.last().unwrap().1)
+    }
+
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        for (path, entry) in &self.entries {
+            self.out.write_all(&(path.len() as u16).to_le_bytes())?;
+            self.out.write_all(path.as_bytes())?;
+            self.out.write_all(&entry.offset.to_le_bytes())?;
+            self.out.write_all(&entry.stored_size.to_le_bytes())?;
+            self.out.write_all(&entry.size.to_le_bytes())?;
+            self.out.write_all(&entry.checksum.to_le_bytes())?;
+            self.out.write_all(&[entry.compression as u8])?;
+        }
+        self.out.seek(SeekFrom::Start(0))?;
+        self.out.write_all(&MAGIC)?;
+        self.out.write_all(&VERSION.to_le_bytes())?;
+        self.out
+            .write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        self.out.write_all(&self.offset.to_le_bytes())?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// reads files out of an archive on demand, only the index is kept in memory
+pub struct ArchiveReader<R> {
+    source: Mutex<R>,
+    entries: HashMap<String, ArchiveEntry>,
+}
+
+impl ArchiveReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("Unable to open {}", path.display()))?;
+        Self::new(BufReader::new(file))
+            .with_context(|| format!("{} is not a valid archive", path.display()))
+    }
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    // nothing read from the index is trusted until it has been checked against the length of
+    // the archive, so a corrupt file fails to open instead of causing huge allocations
+    pub fn new(mut source: R) -> anyhow::Result<Self> {
+        let len = source.seek(SeekFrom::End(0))?;
+        source.seek(SeekFrom::Start(0))?;
+        let mut magic = [0; 8];
+        source.read_exact(&mut magic)?;
+        anyhow::ensure!(magic == MAGIC, "Wrong magic number");
+        let version = read_u32(&mut source)?;
+        anyhow::ensure!(version == VERSION, "Unsupported version {}", version);
+        let count = read_u32(&mut source)?;
+        let index_offset = read_u64(&mut source)?;
+        anyhow::ensure!(
+            (HEADER_SIZE..=len).contains(&index_offset),
+            "The index is out of bounds"
+        );
+        anyhow::ensure!(
+            count as u64 <= (len - index_offset) / MIN_ENTRY_SIZE,
+            "The index is truncated"
+        );
+
+        source.seek(SeekFrom::Start(index_offset))?;
+        let mut entries = HashMap::with_capacity(count as usize);
+        for _ in 0..count {
+            let mut path = vec![0; read_u16(&mut source)? as usize];
+            source.read_exact(&mut path)?;
+            let entry = ArchiveEntry {
+                offset: read_u64(&mut source)?,
+                stored_size: read_u64(&mut source)?,
+                size: read_u64(&mut source)?,
+                checksum: read_u32(&mut source)?,
+                compression: Compression::from_u8(read_u8(&mut source)?)?,
+            };
+            let path = String::from_utf8(path)?;
+            // the stored bytes lie between the header and the index
+            let end = entry.offset.checked_add(entry.stored_size);
+            anyhow::ensure!(
+                entry.offset >= HEADER_SIZE && matches!(end, Some(end) if end <= index_offset),
+                "{} is out of bounds",
+                path
+            );
+            let max_size = match entry.compression {
+                Compression::None => entry.stored_size,
+                Compression::Deflate => entry.stored_size.saturating_mul(MAX_DEFLATE_RATIO),
+            };
+            anyhow::ensure!(entry.size <= max_size, "{} has an invalid size", path);
+            entries.insert(path, entry);
+        }
+        Ok(Self {
+            source: Mutex::new(source),
+            entries,
+        })
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &ArchiveEntry)> {
+        self.entries
+            .iter()
+            .map(|(path, entry)| (path.as_str(), entry))
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    // None if the archive has no such file, an error if its data does not match its checksum
+    pub fn read(&self, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let entry = match self.entries.get(path) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let mut stored = vec![0; entry.stored_size as usize];
+        {
+            let mut source = self.source.lock().unwrap();
+            source.seek(SeekFrom::Start(entry.offset))?;
+            source.read_exact(&mut stored)?;
+        }
+        let data = match entry.compression {
+            Compression::None => stored,
+            Compression::Deflate => {
+                let mut data = Vec::with_capacity(entry.size as usize);
+                // one byte more than expected is enough to tell that the size is wrong
+                DeflateDecoder::new(stored.as_slice())
+                    .take(entry.size + 1)
+                    .read_to_end(&mut data)?;
+                data
+            }
+        };
+        anyhow::ensure!(
+            data.len() as u64 == entry.size && crc32fast::hash(&data) == entry.checksum,
+            "{} is corrupted",
+            path
+        );
+        Ok(Some(data))
+    }
+}
+
+fn read_u8(source: &mut impl Read) -> anyhow::Result<u8> {
+    let mut bytes = [0; 1];
+    source.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u16(source: &mut impl Read) -> anyhow::Result<u16> {
+    let mut bytes = [0; 2];
+    source.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(source: &mut impl Read) -> anyhow::Result<u32> {
+    let mut bytes = [0; 4];
+    source.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(source: &mut impl Read) -> anyhow::Result<u64> {
+    let mut bytes = [0; 8];
+    source.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn pack(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        for (path, data) in files {
+            writer.add(path, data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn open(bytes: Vec<u8>) -> anyhow::Result<ArchiveReader<Cursor<Vec<u8>>>> {
+        ArchiveReader::new(Cursor::new(bytes))
+    }
+
+    fn set_u64(bytes: &mut [u8], at: usize, value: u64) {
+        bytes[at..at + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = "compressible ".repeat(100);
+        let bytes = pack(&[("a/text.txt", text.as_bytes()), ("b.bin", &[1, 2, 3])]);
+        let archive = open(bytes).unwrap();
+        let entries: HashMap<_, _> = archive.entries().collect();
+        assert_eq!(entries["a/text.txt"].compression, Compression::Deflate);
+        assert_eq!(entries["b.bin"].compression, Compression::None);
+        assert_eq!(
+            archive.read("a/text.txt").unwrap().unwrap(),
+            text.as_bytes()
+        );
+        assert_eq!(archive.read("b.bin").unwrap().unwrap(), [1, 2, 3]);
+        assert!(archive.read("c.bin").unwrap().is_none());
+    }
+
+    #[test]
+    fn detect_corrupt_data() {
+        let mut bytes = pack(&[("a.bin", &[1, 2, 3])]);
+        bytes[HEADER_SIZE as usize] ^= 0xff;
+        let e = open(bytes).unwrap().read("a.bin").unwrap_err();
+        assert!(e.to_string().contains("corrupted"), "{}", e);
+    }
+
+    #[test]
+    fn reject_invalid_index() {
+        let bytes = pack(&[("a.bin", &[1, 2, 3])]);
+        let index_offset = HEADER_SIZE as usize + 3;
+        // where the fields of the only entry start in its index entry
+        let offset_at = index_offset + 2 + "a.bin".len();
+        let stored_size_at = offset_at + 8;
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        let mut huge_count = bytes.clone();
+        huge_count[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut index_past_end = bytes.clone();
+        set_u64(&mut index_past_end, 16, bytes.len() as u64 + 1);
+        let mut data_past_index = bytes.clone();
+        set_u64(&mut data_past_index, stored_size_at, 4);
+        let mut overflowing_data = bytes.clone();
+        set_u64(&mut overflowing_data, offset_at, u64::MAX);
+        let mut huge_size = bytes.clone();
+        set_u64(&mut huge_size, stored_size_at + 8, u64::MAX);
+        let truncated = bytes[..bytes.len() - 1].to_vec();
+
+        for (name, bytes) in [
+            ("wrong magic", wrong_magic),
+            ("huge count", huge_count),
+            ("index past the end", index_past_end),
+            ("data past the index", data_past_index),
+            ("overflowing data", overflowing_data),
+            ("huge size", huge_size),
+            ("truncated", truncated),
+        ] {
+            assert!(open(bytes).is_err(), "{} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn paths_stay_inside_the_root() {
+        assert_eq!(
+            archive_path(Path::new("a/./b/../c.png")).unwrap(),
+            "a/c.png"
+        );
+        assert!(archive_path(Path::new("../a.png")).is_none());
+        assert!(archive_path(Path::new("a/../../a.png")).is_none());
+        assert!(archive_path(Path::new("/a.png")).is_none());
+    }
+}
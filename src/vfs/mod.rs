@@ -0,0 +1,194 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+
+use self::archive::{archive_path, ArchiveReader};
+
+pub mod archive;
+
+pub const ASSET_DIRECTORY: &str = "assets";
+pub const ASSET_ARCHIVE: &str = "assets.pak";
+
+// a source of files below a mount point. paths are relative to the mount point
+pub trait Mount: Send + Sync {
+    // None if the mount has no such file
+    fn read(&self, path: &Path) -> anyhow::Result<Option<Vec<u8>>>;
+
+    fn exists(&self, path: &Path) -> bool;
+
+    // the directory on disk holding the files, if changes to them can be watched
+    fn directory(&self) -> Option<&Path> {
+        None
+    }
+}
+
+pub struct DirectoryMount {
+    root: PathBuf,
+}
+
+impl DirectoryMount {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    // normalizing first keeps ".." from reaching outside of the directory
+    fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        Some(self.root.join(archive_path(path)?))
+    }
+}
+
+impl Mount for DirectoryMount {
+    fn read(&self, path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+        let path = match self.resolve(path) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        match fs::read(&path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Unable to read {}", path.display())),
+        }
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        matches!(self.resolve(path), Some(path) if path.is_file())
+    }
+
+    fn directory(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+}
+
+impl<R: io::Read + io::Seek + Send> Mount for ArchiveReader<R> {
+    fn read(&self, path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+        match archive_path(path) {
+            Some(path) => ArchiveReader::read(self, &path),
+            None => Ok(None),
+        }
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        matches!(archive_path(path), Some(path) if self.contains(&path))
+    }
+}
+
+// a read-only view of several directories and archives as one tree. every file is read from the
+// most recently added mount that has it, so loose files can override those of an archive
+#[derive(Clone, Default)]
+pub struct Vfs {
+    mounts: Vec<(PathBuf, Arc<dyn Mount>)>,
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // `point` is the path the files of the mount appear under, "" for the root
+    pub fn mount(mut self, point: impl AsRef<Path>, mount: impl Mount + 'static) -> Self {
+        self.mounts
+            .push((point.as_ref().to_path_buf(), Arc::new(mount)));
+        self
+    }
+
+    pub fn read(&self, path: impl AsRef<Path>) -> anyhow::Result<Vec<u8>> {
+        let path = path.as_ref();
+        for (point, mount) in self.mounts.iter().rev() {
+            if let Ok(relative) = path.strip_prefix(point) {
+                if let Some(bytes) = mount.read(relative)? {
+                    return Ok(bytes);
+                }
+            }
+        }
+        Err(anyhow::anyhow!("{} is not in any mount", path.display()))
+    }
+
+    pub fn exists(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        self.mounts.iter().any(|(point, mount)| {
+            matches!(path.strip_prefix(point), Ok(relative) if mount.exists(relative))
+        })
+    }
+
+    // the mount points and directories of every mount whose files can be watched
+    pub fn directories(&self) -> Vec<(PathBuf, PathBuf)> {
+        self.mounts
+            .iter()
+            .filter_map(|(point, mount)| Some((point.clone(), mount.directory()?.to_path_buf())))
+            .collect()
+    }
+}
+
+// the archive shipped with the game, with loose files in the asset directory taking precedence
+// so they can be iterated on without repacking
+pub fn default_vfs() -> anyhow::Result<Vfs> {
+    let mut vfs = Vfs::new();
+    if Path::new(ASSET_ARCHIVE).is_file() {
+        vfs = vfs.mount("", ArchiveReader::open(ASSET_ARCHIVE)?);
+    }
+    if Path::new(ASSET_DIRECTORY).is_dir() {
+        vfs = vfs.mount("", DirectoryMount::new(ASSET_DIRECTORY));
+    }
+    Ok(vfs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::Cursor, process};
+
+    use super::{archive::ArchiveWriter, *};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("amk-vfs-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn archive(files: &[(&str, &str)]) -> ArchiveReader<Cursor<Vec<u8>>> {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        for (path, data) in files {
+            writer.add(path, data.as_bytes()).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+        ArchiveReader::new(Cursor::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn directory_mount_stays_inside_its_root() {
+        let dir = temp_dir("escape");
+        fs::create_dir_all(dir.join("root/sub")).unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+        fs::write(dir.join("root/a.txt"), "a").unwrap();
+        let vfs = Vfs::new().mount("", DirectoryMount::new(dir.join("root")));
+        assert_eq!(vfs.read("sub/../a.txt").unwrap(), b"a");
+        for path in ["../secret.txt", "sub/../../secret.txt", "/secret.txt"] {
+            assert!(vfs.read(path).is_err(), "{} was read", path);
+            assert!(!vfs.exists(path), "{} exists", path);
+        }
+    }
+
+    #[test]
+    fn later_mounts_take_precedence() {
+        let dir = temp_dir("precedence");
+        fs::write(dir.join("a.txt"), "loose").unwrap();
+        let vfs = Vfs::new()
+            .mount("", archive(&[("a.txt", "packed"), ("b.txt", "packed")]))
+            .mount("", DirectoryMount::new(&dir))
+            .mount("extra", archive(&[("a.txt", "extra")]));
+        assert_eq!(vfs.read("a.txt").unwrap(), b"loose");
+        // files missing from a later mount fall through to earlier ones
+        assert_eq!(vfs.read("b.txt").unwrap(), b"packed");
+        assert_eq!(vfs.read("extra/a.txt").unwrap(), b"extra");
+        assert!(vfs.read("c.txt").is_err());
+        assert!(vfs.exists("b.txt") && !vfs.exists("extra/b.txt"));
+        // only the directory can be watched
+        assert_eq!(vfs.directories(), [(PathBuf::new(), dir)]);
+    }
+}